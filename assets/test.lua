@@ -1,6 +0,0 @@
--- Test system
-local e = Find("main_entity")
-local transform = e:GetComponent("transform")
-for e in Query("transform", "sprite") do
-    
-end
\ No newline at end of file
@@ -8,6 +8,8 @@ use serde::de::{Visitor, self, DeserializeSeed};
 use serde::ser::{SerializeSeq, SerializeTuple};
 use serde::{Serialize, Deserialize, Deserializer, Serializer};
 
+use crate::event::asset::{ImportAssetEvent, AssetImportEntry};
+use crate::feature::asset::{lua_script::LuaScript, rhai_script::RhaiScript};
 use crate::registry::asset::{Asset, AssetRegistry};
 use crate::uid::UID;
 
@@ -269,6 +271,7 @@ pub struct AssetManager {
     containers: HashMap<UID, Box<dyn AnyAssetContainer>>,
     defaults: HashMap<UID, UID>,
     bundles: HashMap<UID, AssetBundle>,
+    modified: Vec<(UID, UID)>,
 }
 
 impl AssetManager {
@@ -462,7 +465,9 @@ impl AssetManager {
         let uid = self.add_bundle(&import.name)?;
         let bundle = self.bundles.get_mut(&uid).unwrap();
         for (asset, mut container) in import.containers {
-            bundle.assets.insert(asset, container.collect_uids());
+            let uids = container.collect_uids();
+            self.modified.extend(uids.iter().map(|uid| (asset, *uid)));
+            bundle.assets.insert(asset, uids);
             if let Some(self_container) = self.containers.get_mut(&asset) {
                 self_container.merge(container.as_mut())?;
             } else {
@@ -496,6 +501,34 @@ impl AssetManager {
         self.bundles.get_mut(&bundle).unwrap().assets.entry(asset)
             .or_insert_with(Default::default)
             .insert(uid);
+        self.modified.push((asset, uid));
+        Ok(())
+    }
+
+    pub(crate) fn replace<A: Asset>(&mut self, asset: UID, uid: UID, data: A) -> Result<()> {
+        let entry = self.container_mut::<A>(asset)?.with_context(|| "Asset type not found")?
+            .0.get_mut(&uid).with_context(|| "Asset not found")?;
+        entry.asset = data;
+        self.modified.push((asset, uid));
+        Ok(())
+    }
+
+    /// Scripts imported again replace the existing ones, their caches are invalidated
+    /// from the modified assets. Other imports are left to the application which
+    /// chooses the bundle of the asset, renderer resources are not reloaded.
+    pub(crate) fn dispatch_import(&mut self, import: &ImportAssetEvent) -> Result<()> {
+        match import {
+            ImportAssetEvent::RhaiScript(entry) => self.reimport(RhaiScript::UID, entry),
+            ImportAssetEvent::LuaScript(entry) => self.reimport(LuaScript::UID, entry),
+            _ => Ok(()),
+        }
+    }
+
+    fn reimport<A: Asset + Clone>(&mut self, asset: UID, entry: &AssetImportEntry<A>) -> Result<()> {
+        let uid = UID::new(&entry.name);
+        if self.entry::<A>(asset, uid)?.is_some() {
+            self.replace(asset, uid, entry.data.clone())?;
+        }
         Ok(())
     }
 
@@ -508,12 +541,18 @@ impl AssetManager {
             self.bundles.get_mut(&entry.bundle).expect("Bundle not found")
                 .assets.get_mut(&asset).expect("Asset not found")
                 .remove(&uid);
+            self.modified.push((asset, uid));
         } else {
             return Err(anyhow!("Asset not found"));
         }
         Ok(())
     }
 
+    /// Drain the (asset type, uid) pairs added, replaced or removed since the last call
+    pub(crate) fn drain_modified(&mut self) -> impl Iterator<Item = (UID, UID)> + '_ {
+        self.modified.drain(..)
+    }
+
     pub(crate) fn transfer<A: Asset>(&mut self, asset: UID, uid: UID, dst_bundle: UID) -> Result<()> {
         let src_bundle = self.container::<A>(asset)?.with_context(|| "Asset container not found")?
             .0.get(&uid).with_context(|| "Asset not found")?.bundle;
@@ -1,12 +1,14 @@
-use self::{asset::AssetContext, renderer::RendererContext, input::InputContext, scheduler::SchedulerContext, procedure::ProcedureContext, world::WorldContext, registry::RegistryContext, event::EventContext};
+use self::{asset::AssetContext, renderer::RendererContext, input::InputContext, scheduler::SchedulerContext, procedure::ProcedureContext, world::{WorldContext, ParallelWorldContext}, registry::RegistryContext, event::EventContext, script::ScriptContext};
 
 pub mod asset;
+pub mod condition;
 pub mod event;
 pub mod input;
 pub mod procedure;
 pub mod registry;
 pub mod renderer;
 pub mod scheduler;
+pub mod script;
 pub mod time;
 pub mod world;
 
@@ -18,6 +20,12 @@ pub struct SystemContext<'a> {
     pub registry: RegistryContext<'a>,
     pub renderer: RendererContext<'a>,
     pub scheduler: SchedulerContext<'a>,
+    pub script: ScriptContext<'a>,
     pub time: time::TimeContext,
     pub world: WorldContext<'a>,
-}
\ No newline at end of file
+}
+/// Context of systems running on the thread pool, only the active world is reachable
+pub struct ParallelSystemContext<'a> {
+    pub time: time::TimeContext,
+    pub world: ParallelWorldContext<'a>,
+}
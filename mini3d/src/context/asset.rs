@@ -38,6 +38,10 @@ impl<'a> AssetContext<'a> {
         self.manager.add::<A>(&self.registry.borrow().assets, asset, name, bundle, data)
     }
 
+    pub fn replace<A: Asset>(&mut self, asset: UID, uid: UID, data: A) -> Result<()> {
+        self.manager.replace::<A>(asset, uid, data)
+    }
+
     pub fn remove<A: Asset>(&mut self, asset: UID, uid: UID) -> Result<()> {
         self.manager.remove::<A>(asset, uid)
     }
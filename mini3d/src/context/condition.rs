@@ -0,0 +1,43 @@
+use anyhow::Result;
+
+use crate::{uid::UID, ecs::{world::World, entity::Entity, component::Component, singleton::SingletonRef}, input::{InputManager, InputActionState, InputAxisState}};
+
+/// Read-only state given to run condition predicates
+pub struct RunConditionContext<'a> {
+    pub(crate) procedure: UID,
+    pub(crate) world_uid: UID,
+    pub(crate) world: &'a World,
+    pub(crate) input: &'a InputManager,
+}
+
+impl<'a> RunConditionContext<'a> {
+
+    /// Procedure running the system
+    pub fn procedure(&self) -> UID {
+        self.procedure
+    }
+
+    pub fn world(&self) -> UID {
+        self.world_uid
+    }
+
+    pub fn action(&self, uid: UID) -> Result<&InputActionState> {
+        self.input.action(uid)
+    }
+
+    pub fn axis(&self, uid: UID) -> Result<&InputAxisState> {
+        self.input.axis(uid)
+    }
+
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.world.find(name)
+    }
+
+    pub fn contains(&self, entity: Entity, component: UID) -> Result<bool> {
+        self.world.contains(entity, component)
+    }
+
+    pub fn get_singleton<C: Component>(&self, component: UID) -> Result<Option<SingletonRef<'_, C>>> {
+        self.world.get_singleton(component)
+    }
+}
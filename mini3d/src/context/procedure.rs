@@ -1,10 +1,12 @@
 use anyhow::Result;
 use std::collections::VecDeque;
-use crate::{uid::UID, ecs::scheduler::Invocation};
+use crate::{uid::UID, ecs::{scheduler::Invocation, procedure::FrameProcedure}};
 
 pub struct ProcedureContext<'a> {
     pub(crate) active_procedure: UID,
-    pub(crate) frame_procedures: &'a mut VecDeque<UID>,
+    /// Index of the active procedure among the procedures run this frame
+    pub(crate) run: usize,
+    pub(crate) frame_procedures: &'a mut VecDeque<FrameProcedure>,
     pub(crate) next_frame_procedures: &'a mut VecDeque<UID>,
 }
 
@@ -13,10 +15,10 @@ impl<'a> ProcedureContext<'a> {
     pub fn invoke(&mut self, procedure: UID, invocation: Invocation) -> Result<()> {
         match invocation {
             Invocation::Immediate => {
-                self.frame_procedures.push_front(procedure);
+                self.frame_procedures.push_front(FrameProcedure { uid: procedure, invoker: Some(self.run) });
             },
             Invocation::EndFrame => {
-                self.frame_procedures.push_back(procedure);
+                self.frame_procedures.push_back(FrameProcedure { uid: procedure, invoker: Some(self.run) });
             },
             Invocation::NextFrame => {
                 self.next_frame_procedures.push_back(procedure);
@@ -2,7 +2,7 @@ use core::cell::RefCell;
 
 use anyhow::Result;
 
-use crate::{registry::{RegistryManager, component::DynamicComponentDefinition}, ecs::{component::Component, system::SystemCallback}, uid::UID};
+use crate::{registry::{RegistryManager, component::DynamicComponentDefinition}, ecs::{component::{Component, ComponentHooks}, system::{SystemCallback, ParallelSystemCallback, SystemAccess}, condition::RunConditionCallback}, uid::UID};
 
 pub struct RegistryContext<'a> {
     pub(crate) manager: &'a RefCell<RegistryManager>,
@@ -10,8 +10,8 @@ pub struct RegistryContext<'a> {
 
 impl<'a> RegistryContext<'a> {
 
-    pub fn define_static_component<C: Component>(&self, name: &str) -> Result<UID> {
-        self.manager.borrow_mut().components.define_static::<C>(name)
+    pub fn define_static_component<C: Component>(&self, name: &str, hooks: ComponentHooks<C>) -> Result<UID> {
+        self.manager.borrow_mut().components.define_static::<C>(name, hooks)
     }
 
     pub fn define_dynamic_component(&self, name: &str, definition: DynamicComponentDefinition) -> Result<UID> {
@@ -22,7 +22,21 @@ impl<'a> RegistryContext<'a> {
         self.manager.borrow_mut().systems.define_static(name, system)
     }
 
+    /// Systems declaring their access may run on the thread pool alongside the other systems of the pipeline
+    pub fn define_parallel_system(&self, name: &str, system: ParallelSystemCallback, access: SystemAccess) -> Result<()> {
+        self.manager.borrow_mut().systems.define_parallel(name, system, access)
+    }
+
+    /// Predicate usable by `RunCondition::Predicate`
+    pub fn define_run_condition(&self, name: &str, predicate: RunConditionCallback) -> Result<()> {
+        self.manager.borrow_mut().systems.define_condition(name, predicate)
+    }
+
     pub fn define_rhai_system(&self, name: &str, script: UID) -> Result<()> {
         self.manager.borrow_mut().systems.define_rhai(name, script)
     }
+
+    pub fn define_lua_system(&self, name: &str, script: UID) -> Result<()> {
+        self.manager.borrow_mut().systems.define_lua(name, script)
+    }
 }
\ No newline at end of file
@@ -18,6 +18,15 @@ impl<'a> SchedulerContext<'a> {
         self.scheduler.remove_group(group)
     }
 
+    /// Invoke the procedure `rate` times per second, starting next frame. Its systems get the fixed timestep as delta time.
+    pub fn add_fixed_procedure(&mut self, name: &str, rate: f64) -> Result<UID> {
+        self.scheduler.add_fixed_procedure(name, rate)
+    }
+
+    pub fn remove_fixed_procedure(&mut self, procedure: UID) -> Result<()> {
+        self.scheduler.remove_fixed_procedure(procedure)
+    }
+
     /// Applied at the end of the procedure
     pub fn enable_group(&mut self, group: UID) -> Result<()> {
         self.scheduler.enable_group(group)
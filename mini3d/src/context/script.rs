@@ -0,0 +1,50 @@
+use core::cell::RefCell;
+
+use crate::{script::ScriptManager, rhai::RhaiScriptLimits, uid::UID};
+
+pub struct ScriptContext<'a> {
+    pub(crate) manager: &'a RefCell<ScriptManager>,
+}
+
+impl<'a> ScriptContext<'a> {
+
+    pub fn rhai_limits(&self) -> RhaiScriptLimits {
+        self.manager.borrow().rhai.limits()
+    }
+
+    pub fn set_rhai_limits(&self, limits: RhaiScriptLimits) {
+        self.manager.borrow_mut().rhai.set_limits(limits);
+    }
+
+    pub fn rhai_fault(&self, script: UID) -> Option<String> {
+        self.manager.borrow().rhai.fault(script).map(String::from)
+    }
+
+    pub fn rhai_faults(&self) -> Vec<(UID, String)> {
+        self.manager.borrow().rhai.iter_faults().map(|(uid, fault)| (uid, fault.to_owned())).collect()
+    }
+
+    pub fn reset_rhai_fault(&self, script: UID) {
+        self.manager.borrow_mut().rhai.reset_fault(script);
+    }
+
+    pub fn lua_max_instructions(&self) -> u64 {
+        self.manager.borrow().lua.max_instructions()
+    }
+
+    pub fn set_lua_max_instructions(&self, max_instructions: u64) {
+        self.manager.borrow_mut().lua.set_max_instructions(max_instructions);
+    }
+
+    pub fn lua_fault(&self, script: UID) -> Option<String> {
+        self.manager.borrow().lua.fault(script).map(String::from)
+    }
+
+    pub fn lua_faults(&self) -> Vec<(UID, String)> {
+        self.manager.borrow().lua.iter_faults().map(|(uid, fault)| (uid, fault.to_owned())).collect()
+    }
+
+    pub fn reset_lua_fault(&self, script: UID) {
+        self.manager.borrow_mut().lua.reset_fault(script);
+    }
+}
\ No newline at end of file
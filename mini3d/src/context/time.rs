@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct TimeContext {
     pub(crate) delta: f64,
     pub(crate) global: f64,
@@ -1,15 +1,19 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Result, Context, anyhow};
 
-use crate::{ecs::{world::World, entity::Entity, view::{ComponentViewRef, ComponentViewMut}, query::Query, component::{ComponentRef, Component, ComponentMut}, singleton::{SingletonRef, SingletonMut}}, uid::UID, registry::RegistryManager};
+use crate::{ecs::{world::World, entity::Entity, view::{ComponentViewRef, ComponentViewMut}, query::{Query, QueryMut, QueryParam, QueryFilter}, component::{ComponentRef, Component, ComponentMut}, singleton::{SingletonRef, SingletonMut}, command::WorldCommands, system::SystemAccess}, uid::UID, registry::{RegistryManager, component::{ComponentKind, DynamicComponentDefinition, ComponentRegistry}}, feature::asset::{runtime_component::FieldValue, world_template::WorldTemplate}};
 use core::cell::RefCell;
 use std::{collections::{HashMap, HashSet}, cell::{RefMut, Ref}};
 
+use super::asset::AssetContext;
+
 pub struct WorldContext<'a> {
     pub(crate) registry: &'a RefCell<RegistryManager>,
     pub(crate) worlds: &'a mut HashMap<UID, RefCell<Box<World>>>,
     pub(crate) active_world: UID,
     pub(crate) change_world: &'a mut Option<UID>,
     pub(crate) removed_worlds: &'a mut HashSet<UID>,
+    /// System currently running, used for change detection
+    pub(crate) system: UID,
 }
 
 impl<'a> WorldContext<'a> {
@@ -39,14 +43,34 @@ impl<'a> WorldContext<'a> {
     }
 
     pub fn active(&mut self) -> WorldInstanceContext<'_> {
-        WorldInstanceContext { uid: self.active_world, world: self.worlds.get(&self.active_world).unwrap().borrow_mut(), registry: self.registry.borrow() }
+        let world = self.worlds.get(&self.active_world).unwrap().borrow_mut();
+        WorldInstanceContext { uid: self.active_world, last_run: world.last_run(self.system), world, registry: self.registry.borrow() }
     }
 
     pub fn get(&mut self, uid: UID) -> Result<WorldInstanceContext<'_>> {
-        if !self.worlds.contains_key(&uid) {
-            return Err(anyhow!("World not found"));
+        let world = self.worlds.get(&uid).with_context(|| "World not found")?.borrow_mut();
+        Ok(WorldInstanceContext { uid, last_run: world.last_run(self.system), world, registry: self.registry.borrow() })
+    }
+
+    /// Create the entities of the template in the active world, applied immediately.
+    /// Entities are returned by template name and named `<prefix><name>` if a prefix is given.
+    pub fn instantiate_template(&mut self, asset: &AssetContext, uid: UID, prefix: Option<&str>) -> Result<HashMap<String, Entity>> {
+        let template = asset.manager.get::<WorldTemplate>(WorldTemplate::UID, uid)?.with_context(|| "World template not found")?;
+        let mut world = self.worlds.get(&self.active_world).unwrap().borrow_mut();
+        template.instantiate(&mut world, &self.registry.borrow().components, prefix)
+    }
+
+    /// Sync point after each system: queued commands are applied
+    pub(crate) fn end_system(&mut self) -> Result<()> {
+        let registry = self.registry.borrow();
+        let mut result = Ok(());
+        for world in self.worlds.values_mut() {
+            let world = world.get_mut();
+            let applied = world.apply_commands(&registry.components);
+            world.end_system(self.system);
+            result = result.and(applied);
         }
-        Ok(WorldInstanceContext { uid, world: self.worlds.get(&uid).unwrap().borrow_mut(), registry: self.registry.borrow() })
+        result
     }
 
     /// Applied at the end of the procedure
@@ -64,6 +88,7 @@ impl<'a> WorldContext<'a> {
 
 pub struct WorldInstanceContext<'a> {
     uid: UID,
+    last_run: u64,
     world: RefMut<'a, Box<World>>,
     registry: Ref<'a, RegistryManager>
 }
@@ -82,6 +107,38 @@ impl<'a> WorldInstanceContext<'a> {
         self.world.destroy(entity)
     }
 
+    /// False for entities destroyed or never created in this world
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.world.is_alive(entity)
+    }
+
+    /// Check the internal consistency of the world
+    pub fn validate(&self) -> Result<()> {
+        self.world.validate()
+    }
+
+    /// Queue structural changes, usable while views and queries are borrowed
+    pub fn commands(&self) -> WorldCommands<'_> {
+        WorldCommands { world: &self.world }
+    }
+
+    /// Give a unique name to the entity, replacing its previous one
+    pub fn set_name(&mut self, entity: Entity, name: &str) -> Result<()> {
+        self.world.set_name(entity, name)
+    }
+
+    pub fn remove_name(&mut self, entity: Entity) {
+        self.world.remove_name(entity)
+    }
+
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.world.find(name)
+    }
+
+    pub fn name(&self, entity: Entity) -> Option<&str> {
+        self.world.name(entity)
+    }
+
     pub fn add<C: Component>(&mut self, entity: Entity, component: UID, data: C) -> Result<()> {
         self.world.add(&self.registry.components, entity, component, data)
     }
@@ -90,6 +147,49 @@ impl<'a> WorldInstanceContext<'a> {
         self.world.remove(entity, component)
     }
 
+    pub fn contains(&self, entity: Entity, component: UID) -> Result<bool> {
+        self.world.contains(entity, component)
+    }
+
+    /// Entities whose component was removed or destroyed this frame and not drained yet
+    pub fn removed(&self, component: UID) -> Vec<Entity> {
+        self.world.removed(component)
+    }
+
+    /// Take the removed components with their last value. Removals that are not
+    /// drained are discarded at the start of the next frame.
+    pub fn drain_removed<C: Component>(&mut self, component: UID) -> Result<Vec<(Entity, C)>> {
+        self.world.drain_removed(component)
+    }
+
+    pub(crate) fn serialize_component(&self, entity: Entity, component: UID) -> Result<Option<Box<dyn erased_serde::Serialize + '_>>> {
+        self.world.serialize_component(&self.registry.components, entity, component)
+    }
+
+    pub(crate) fn deserialize_component(&mut self, entity: Entity, component: UID, deserializer: &mut dyn erased_serde::Deserializer) -> Result<()> {
+        self.world.deserialize_component(&self.registry.components, entity, component, deserializer)
+    }
+
+    pub fn dynamic_definition(&self, component: UID) -> Option<&DynamicComponentDefinition> {
+        self.registry.components.get(component).and_then(|definition| match &definition.kind {
+            ComponentKind::Dynamic(definition) => Some(definition),
+            ComponentKind::Static => None,
+        })
+    }
+
+    /// Add a dynamic component with default field values
+    pub fn add_dynamic(&mut self, entity: Entity, component: UID) -> Result<()> {
+        self.world.add_dynamic(&self.registry.components, entity, component)
+    }
+
+    pub fn get_field(&self, entity: Entity, component: UID, field: &str) -> Result<Option<FieldValue>> {
+        self.world.get_field(&self.registry.components, entity, component, field)
+    }
+
+    pub fn set_field(&mut self, entity: Entity, component: UID, field: &str, value: FieldValue) -> Result<()> {
+        self.world.set_field(&self.registry.components, entity, component, field, value)
+    }
+
     pub fn get<C: Component>(&self, entity: Entity, component: UID) -> Result<Option<ComponentRef<'_, C>>> {
         self.world.get(entity, component)
     }
@@ -110,6 +210,18 @@ impl<'a> WorldInstanceContext<'a> {
         self.world.query(components)
     }
 
+    /// Typed query over statically defined components, e.g. `query_mut::<(Entity, &mut Transform, &FreeFly)>()`.
+    /// Conflicting or already borrowed components are reported as errors.
+    pub fn query_mut<Q: QueryParam>(&self) -> Result<QueryMut<'_, Q>> {
+        self.world.query_mut(&self.registry.components, self.last_run)
+    }
+
+    /// Typed query restricted by filters, e.g. `query_filtered::<&Camera, (With<LocalToWorld>, Without<Hierarchy>)>()`.
+    /// `Changed` filters are relative to the last run of the current system.
+    pub fn query_filtered<Q: QueryParam, F: QueryFilter>(&self) -> Result<QueryMut<'_, Q, F>> {
+        self.world.query_mut(&self.registry.components, self.last_run)
+    }
+
     pub fn add_singleton<C: Component>(&mut self, component: UID, data: C) -> Result<()> {
         self.world.add_singleton(component, data)
     }
@@ -125,4 +237,88 @@ impl<'a> WorldInstanceContext<'a> {
     pub fn get_singleton_mut<C: Component>(&self, component: UID) -> Result<Option<SingletonMut<'_, C>>> {
         self.world.get_singleton_mut(component)
     }
-}
\ No newline at end of file
+}
+/// Active world seen by a parallel system. Only the components and singletons declared
+/// in the system access are reachable and structural changes are not available.
+pub struct ParallelWorldContext<'a> {
+    pub(crate) uid: UID,
+    pub(crate) world: &'a World,
+    pub(crate) registry: &'a ComponentRegistry,
+    pub(crate) access: &'a SystemAccess,
+    pub(crate) last_run: u64,
+    /// Change tick of the system, as if systems of the stage were run one after another
+    pub(crate) tick: u64,
+}
+
+impl<'a> ParallelWorldContext<'a> {
+
+    fn check_access(&self, component: UID, mutable: bool) -> Result<()> {
+        let name = self.registry.get(component).map_or("unknown", |definition| definition.name.as_str());
+        if mutable && !self.access.can_write(component) {
+            return Err(anyhow!("Component '{}' is not declared as written by the system", name));
+        }
+        if !self.access.can_read(component) {
+            return Err(anyhow!("Component '{}' is not declared as read by the system", name));
+        }
+        Ok(())
+    }
+
+    pub fn uid(&self) -> UID {
+        self.uid
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.world.is_alive(entity)
+    }
+
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.world.find(name)
+    }
+
+    pub fn name(&self, entity: Entity) -> Option<&str> {
+        self.world.name(entity)
+    }
+
+    pub fn contains(&self, entity: Entity, component: UID) -> Result<bool> {
+        self.world.contains(entity, component)
+    }
+
+    pub fn view<C: Component>(&self, component: UID) -> Result<ComponentViewRef<'_, C>> {
+        self.check_access(component, false)?;
+        if self.access.can_write(component) {
+            self.world.view(component)
+        } else {
+            // SAFETY: no other system of the parallel stage writes the component
+            unsafe { self.world.view_scheduled(component) }
+        }
+    }
+
+    pub fn view_mut<C: Component>(&self, component: UID) -> Result<ComponentViewMut<'_, C>> {
+        self.check_access(component, true)?;
+        self.world.view_mut_at(component, self.tick)
+    }
+
+    pub fn query_mut<Q: QueryParam>(&self) -> Result<QueryMut<'_, Q>> {
+        QueryMut::scheduled(self.world, self.registry, self.access, self.last_run, self.tick)
+    }
+
+    /// Filtered components must be declared as read
+    pub fn query_filtered<Q: QueryParam, F: QueryFilter>(&self) -> Result<QueryMut<'_, Q, F>> {
+        QueryMut::scheduled(self.world, self.registry, self.access, self.last_run, self.tick)
+    }
+
+    pub fn get_singleton<C: Component>(&self, component: UID) -> Result<Option<SingletonRef<'_, C>>> {
+        self.check_access(component, false)?;
+        if self.access.can_write(component) {
+            self.world.get_singleton(component)
+        } else {
+            // SAFETY: no other system of the parallel stage writes the singleton
+            unsafe { self.world.get_singleton_scheduled(component) }
+        }
+    }
+
+    pub fn get_singleton_mut<C: Component>(&self, component: UID) -> Result<Option<SingletonMut<'_, C>>> {
+        self.check_access(component, true)?;
+        self.world.get_singleton_mut(component)
+    }
+}
@@ -1,13 +1,16 @@
 use std::collections::{HashMap, VecDeque, HashSet};
 use core::cell::RefCell;
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::{Serialize, ser::{SerializeTuple, SerializeSeq}, de::{SeqAccess, DeserializeSeed, Visitor}, Serializer, Deserializer};
 
-use crate::{uid::UID, renderer::RendererManager, script::ScriptManager, input::InputManager, asset::AssetManager, registry::{RegistryManager, component::ComponentRegistry}, context::{SystemContext, asset::AssetContext, input::InputContext, procedure::ProcedureContext, renderer::RendererContext, scheduler::SchedulerContext, world::WorldContext, registry::RegistryContext, time::TimeContext, event::EventContext}, feature::asset::system_group::{SystemGroup, SystemPipeline}, event::Events};
+use crate::{uid::UID, renderer::RendererManager, script::ScriptManager, input::InputManager, asset::AssetManager, registry::{RegistryManager, component::ComponentRegistry}, context::{SystemContext, asset::AssetContext, input::InputContext, procedure::ProcedureContext, renderer::RendererContext, scheduler::SchedulerContext, script::ScriptContext, world::WorldContext, registry::RegistryContext, time::TimeContext, event::EventContext}, feature::asset::system_group::{SystemGroup, SystemPipeline}, event::Events};
 
-use self::{world::World, scheduler::Scheduler, procedure::Procedure, system::SystemCallback};
+use self::{world::World, scheduler::Scheduler, procedure::{Procedure, FrameProcedure}, system::SystemCallback};
 
+pub mod command;
 pub mod component;
+pub mod condition;
 pub mod container;
 pub mod entity;
 pub mod pipeline;
@@ -17,10 +20,14 @@ pub mod scheduler;
 pub mod singleton;
 pub mod sparse;
 pub mod system;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod view;
 pub mod world;
 
 const INIT_NAME: &str = "_init";
+const DEFAULT_PROCEDURE_BUDGET: u32 = 1024;
+const FIXED_UPDATE_RATE: f64 = 60.0;
 
 #[derive(Default)]
 pub(crate) struct ECSManager {
@@ -28,11 +35,29 @@ pub(crate) struct ECSManager {
     next_frame_procedures: VecDeque<UID>,
     pub(crate) worlds: RefCell<HashMap<UID, RefCell<Box<World>>>>,
     pub(crate) active_world: UID,
+    /// Runs parallel systems, they are run on the calling thread without it
+    thread_pool: Option<ThreadPool>,
+    /// Maximum number of procedures invoked by systems in a single frame. Procedures queued
+    /// by the engine, such as the catch-up runs of fixed procedures, are not counted.
+    pub(crate) procedure_budget: u32,
+}
+
+/// Shorten long lists of names to their first and last elements
+fn summarize(names: Vec<String>, separator: &str) -> String {
+    const KEEP: usize = 8;
+    if names.len() > 2 * KEEP {
+        format!("{}{}... {} more ...{}{}", names[..KEEP].join(separator), separator, names.len() - 2 * KEEP, separator, names[names.len() - KEEP..].join(separator))
+    } else {
+        names.join(separator)
+    }
 }
 
 impl ECSManager {
 
     pub(crate) fn setup(&mut self, init: SystemCallback, registry: &mut RegistryManager) -> Result<()> {
+        // Platforms without threads run every system on the calling thread
+        self.thread_pool = ThreadPoolBuilder::new().build().ok();
+        self.procedure_budget = DEFAULT_PROCEDURE_BUDGET;
         // Define the init system
         registry.systems.define_static(INIT_NAME, init)?;
         // Create the init world and set as active
@@ -43,6 +68,8 @@ impl ECSManager {
         init_group.insert(INIT_NAME, SystemPipeline::single(INIT_NAME.into()), 0);
         // Add the init procedure to the scheduler with the init system
         self.scheduler.add_group(INIT_NAME, init_group)?;
+        // Fixed update runs at the default rate, it can be removed like custom fixed procedures
+        self.scheduler.add_fixed_procedure(Procedure::FIXED_UPDATE, FIXED_UPDATE_RATE)?;
         // Invoke the init procedure
         self.next_frame_procedures.push_back(INIT_NAME.into());
         Ok(())
@@ -147,38 +174,74 @@ impl ECSManager {
         Ok(())
     }
 
+    /// Diagnostic of a frame running too many procedures, the invocation chain of the
+    /// pending procedure is followed back to detect a cycle.
+    fn procedure_budget_error(&self, runs: &[FrameProcedure], pending: FrameProcedure) -> anyhow::Error {
+        let name = |uid: &UID| self.scheduler.procedure_name(*uid).map_or_else(|| uid.to_string(), str::to_string);
+        let mut chain = vec![pending.uid];
+        let mut invoker = pending.invoker;
+        while let Some(run) = invoker {
+            chain.push(runs[run].uid);
+            invoker = runs[run].invoker;
+        }
+        chain.reverse();
+        let mut message = format!("Procedure budget of {} invocations per frame exceeded", self.procedure_budget);
+        if let Some(start) = chain[..chain.len() - 1].iter().rposition(|uid| *uid == pending.uid) {
+            message += &format!(", invocation cycle: {}", chain[start..].iter().map(name).collect::<Vec<_>>().join(" -> "));
+        }
+        message += &format!("\nInvocation chain: {}", summarize(chain.iter().map(name).collect(), " -> "));
+        message += &format!("\nProcedures run this frame: {}", summarize(runs.iter().map(|run| name(&run.uid)).collect(), ", "));
+        anyhow!(message)
+    }
+
     pub(crate) fn update(
         &mut self,
         registry: &RefCell<RegistryManager>,
         asset: &mut AssetManager,
         input: &mut InputManager,
         renderer: &mut RendererManager,
-        script: &mut ScriptManager,
+        script: &RefCell<ScriptManager>,
         events: &Events,
         delta_time: f64,
         time: f64,
-        fixed_delta_time: f64,
-        fixed_update_count: u32,
     ) -> Result<()> {
 
         // Prepare frame
         let mut change_world: Option<UID> = None;
         let mut removed_worlds: HashSet<UID> = Default::default();
+        for world in self.worlds.get_mut().values_mut() {
+            world.get_mut().clear_removed();
+        }
     
         // Collect procedures
-        let mut frame_procedures = self.next_frame_procedures.drain(..).collect::<VecDeque<_>>();
-        for _ in 0..fixed_update_count {
-            frame_procedures.push_back(Procedure::FIXED_UPDATE.into());
+        let mut frame_procedures = self.next_frame_procedures.drain(..).map(FrameProcedure::from).collect::<VecDeque<_>>();
+        for fixed in self.scheduler.fixed_procedures_mut() {
+            for _ in 0..fixed.advance(delta_time) {
+                frame_procedures.push_back(fixed.procedure.into());
+            }
         }
-        frame_procedures.push_back(Procedure::UPDATE.into());
+        frame_procedures.push_back(UID::from(Procedure::UPDATE).into());
 
-        // Run procedures
-        // TODO: protect against infinite loop
-        while let Some(procedure) = frame_procedures.pop_front() {
+        // Run procedures, the budget stops procedures invoking each other endlessly
+        let mut runs = Vec::new();
+        let mut invoked = 0;
+        while let Some(frame_procedure) = frame_procedures.pop_front() {
+            if frame_procedure.invoker.is_some() {
+                if invoked >= self.procedure_budget {
+                    return Err(self.procedure_budget_error(&runs, frame_procedure));
+                }
+                invoked += 1;
+            }
+            let run = runs.len();
+            runs.push(frame_procedure);
+            let procedure = frame_procedure.uid;
 
             // Build pipeline
             if let Some(pipeline) = self.scheduler.build_pipeline(procedure, registry)? {
 
+                // Fixed procedures advance by their own timestep
+                let delta = self.scheduler.fixed_timestep(procedure).unwrap_or(delta_time);
+
                 // Build context
                 let mut context = SystemContext {
                     asset: AssetContext {
@@ -193,6 +256,7 @@ impl ECSManager {
                     },
                     procedure: ProcedureContext {
                         active_procedure: procedure,
+                        run,
                         frame_procedures: &mut frame_procedures,
                         next_frame_procedures: &mut self.next_frame_procedures,
                     },
@@ -205,8 +269,11 @@ impl ECSManager {
                     scheduler: SchedulerContext {
                         scheduler: &mut self.scheduler,
                     },
+                    script: ScriptContext {
+                        manager: script,
+                    },
                     time: TimeContext {
-                        delta: if procedure == Procedure::FIXED_UPDATE.into() { fixed_delta_time } else { delta_time },
+                        delta,
                         global: time,
                     },
                     world: WorldContext {
@@ -215,16 +282,19 @@ impl ECSManager {
                         active_world: self.active_world,
                         change_world: &mut change_world,
                         removed_worlds: &mut removed_worlds,
+                        system: UID::null(),
                     },
                 };
 
                 // Run pipeline
-                pipeline.run(&mut context, script)?;
+                pipeline.run(&mut context, self.thread_pool.as_ref())?;
             }
 
             // Remove worlds
             for uid in removed_worlds.drain() {
-                self.worlds.borrow_mut().remove(&uid);
+                if let Some(world) = self.worlds.borrow_mut().remove(&uid) {
+                    world.into_inner().clear();
+                }
             }
 
             // Change world
@@ -237,4 +307,71 @@ impl ECSManager {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::{engine::Engine, context::SystemContext, event::Events, request::Requests, uid::UID, ecs::{procedure::Procedure, scheduler::Invocation, system::SystemCallback}, feature::asset::system_group::{SystemGroup, SystemPipeline}};
+
+    /// Invokes the next procedure of the cycle: self -> self and a -> b -> a
+    fn bounce(ctx: &mut SystemContext) -> Result<()> {
+        let next = match ctx.procedure.uid() {
+            uid if uid == UID::new("a") => "b",
+            uid if uid == UID::new("b") => "a",
+            _ => "self",
+        };
+        ctx.procedure.invoke(next.into(), Invocation::Immediate)
+    }
+
+    fn start_self(ctx: &mut SystemContext) -> Result<()> {
+        ctx.procedure.invoke("self".into(), Invocation::Immediate)
+    }
+
+    fn start_cycle(ctx: &mut SystemContext) -> Result<()> {
+        ctx.procedure.invoke("a".into(), Invocation::EndFrame)
+    }
+
+    fn setup(ctx: &mut SystemContext, start: SystemCallback) -> Result<()> {
+        ctx.registry.define_static_system("bounce", bounce)?;
+        ctx.registry.define_static_system("start", start)?;
+        let mut group = SystemGroup::empty();
+        group.insert(Procedure::UPDATE, SystemPipeline::single("start".into()), 0);
+        for procedure in ["self", "a", "b"] {
+            group.insert(procedure, SystemPipeline::single("bounce".into()), 0);
+        }
+        ctx.scheduler.add_group("test", group)?;
+        Ok(())
+    }
+
+    fn budget_error(init: SystemCallback) -> String {
+        let mut engine = Engine::new(init).unwrap();
+        engine.set_procedure_budget(4);
+        engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn self_invoking_procedure_exceeds_the_budget() {
+        let err = budget_error(|ctx| setup(ctx, start_self));
+        let lines = err.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "Procedure budget of 4 invocations per frame exceeded, invocation cycle: self -> self", "{}", err);
+        assert_eq!(lines[1], "Invocation chain: update -> self -> self -> self -> self -> self", "{}", err);
+    }
+
+    #[test]
+    fn procedure_cycle_exceeds_the_budget() {
+        let err = budget_error(|ctx| setup(ctx, start_cycle));
+        let lines = err.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "Procedure budget of 4 invocations per frame exceeded, invocation cycle: a -> b -> a", "{}", err);
+        assert_eq!(lines[1], "Invocation chain: update -> a -> b -> a -> b -> a", "{}", err);
+    }
+
+    #[test]
+    fn fixed_catch_up_runs_are_not_budgeted() {
+        let mut engine = Engine::new(|_| Ok(())).unwrap();
+        engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap();
+        engine.set_procedure_budget(0);
+        // The longest frame runs several fixed updates
+        engine.progress(&Events::new(), &mut Requests::default(), 1.0).unwrap();
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::Result;
+
+use crate::{uid::UID, registry::component::ComponentRegistry};
+
+use super::{entity::Entity, world::World, component::Component};
+
+type AddCommand = Box<dyn FnOnce(&mut World, &ComponentRegistry) -> Result<()>>;
+
+pub(crate) enum WorldCommand {
+    Destroy(Entity),
+    Add(Entity, UID, AddCommand),
+    Remove(Entity, UID),
+}
+
+/// Structural changes queued while the world is borrowed. Commands are applied
+/// in order when the system that queued them returns. A failing command doesn't
+/// prevent the next ones from being applied, failures are reported together.
+pub struct WorldCommands<'a> {
+    pub(crate) world: &'a World,
+}
+
+impl<'a> WorldCommands<'a> {
+
+    /// Reserve an entity immediately. It holds no component until queued
+    /// additions are applied but can be referenced by other commands.
+    pub fn create(&self) -> Entity {
+        self.world.reserve()
+    }
+
+    pub fn destroy(&self, entity: Entity) {
+        self.world.commands.borrow_mut().push(WorldCommand::Destroy(entity));
+    }
+
+    pub fn add<C: Component>(&self, entity: Entity, component: UID, data: C) {
+        self.world.commands.borrow_mut().push(WorldCommand::Add(entity, component, Box::new(move |world, registry| {
+            world.add(registry, entity, component, data)
+        })));
+    }
+
+    pub fn remove(&self, entity: Entity, component: UID) {
+        self.world.commands.borrow_mut().push(WorldCommand::Remove(entity, component));
+    }
+}
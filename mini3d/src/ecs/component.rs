@@ -1,4 +1,4 @@
-use std::{cell::{Ref, RefMut}, ops::{Deref, DerefMut}};
+use std::{cell::{Ref, RefMut, RefCell}, ops::{Deref, DerefMut}, rc::Rc};
 
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
@@ -15,6 +15,55 @@ pub trait Component: Serialize + for<'de> Deserialize<'de> + 'static {
     fn resolve_entities(&mut self, _resolver: &EntityResolver) -> Result<()> { Ok(()) }
 }
 
+pub type ComponentAddHook<C> = Rc<dyn Fn(Entity, &mut C)>;
+pub type ComponentRemoveHook<C> = Rc<dyn Fn(Entity, &C)>;
+
+/// Callbacks run when a component is inserted into or removed from an entity, including
+/// when the entity is destroyed. Replacing an existing component runs the remove callback on
+/// the previous value then the add callback on the new one. Loading a world does not run them.
+pub struct ComponentHooks<C: Component> {
+    pub on_add: Option<ComponentAddHook<C>>,
+    pub on_remove: Option<ComponentRemoveHook<C>>,
+}
+
+impl<C: Component> Default for ComponentHooks<C> {
+    fn default() -> Self {
+        Self { on_add: None, on_remove: None }
+    }
+}
+
+impl<C: Component> Clone for ComponentHooks<C> {
+    fn clone(&self) -> Self {
+        Self { on_add: self.on_add.clone(), on_remove: self.on_remove.clone() }
+    }
+}
+
+/// Shared borrow of component data. Parallel systems don't touch the borrow flag,
+/// which is not thread safe, the scheduler ensures instead that nobody writes the data.
+pub(crate) enum SharedRef<'a, T> {
+    Tracked(Ref<'a, T>),
+    Scheduled(&'a T),
+}
+
+impl<'a, T> SharedRef<'a, T> {
+
+    /// # Safety
+    /// The cell must not be borrowed mutably while the reference is alive.
+    pub(crate) unsafe fn scheduled(cell: &'a RefCell<T>) -> Self {
+        Self::Scheduled(&*cell.as_ptr())
+    }
+}
+
+impl<T> Deref for SharedRef<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        match self {
+            Self::Tracked(data) => data,
+            Self::Scheduled(data) => data,
+        }
+    }
+}
+
 pub struct ComponentRef<'a, C: Component> {
     pub(crate) components: Ref<'a, Vec<C>>,
     pub(crate) index: usize,
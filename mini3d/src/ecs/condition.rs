@@ -0,0 +1,170 @@
+use anyhow::{Result, Context, anyhow};
+use serde::{Serialize, Deserialize};
+
+use crate::{uid::UID, context::condition::RunConditionContext, registry::RegistryManager};
+
+use super::system::SystemAccess;
+
+pub type RunConditionCallback = fn(&RunConditionContext) -> Result<bool>;
+
+/// Checked before each run of a system, the system is skipped when it doesn't hold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RunCondition {
+    /// Boolean field of a singleton of the active world. Doesn't hold if the singleton is missing.
+    Singleton { component: UID, field: String, value: bool },
+    /// Input action is pressed or not
+    Action { action: UID, pressed: bool },
+    /// Active world
+    World(UID),
+    /// Predicate defined in the registry, given the running procedure
+    Predicate(UID),
+}
+
+impl RunCondition {
+
+    pub(crate) fn check(&self, context: &RunConditionContext, registry: &RegistryManager) -> Result<bool> {
+        match self {
+            RunCondition::Singleton { component, field, value } => {
+                if let Some(singleton) = context.world.singleton_value(&registry.components, *component)? {
+                    let flag = singleton.get(field).and_then(|flag| flag.as_bool())
+                        .with_context(|| format!("Singleton field '{}' is not a boolean", field))?;
+                    Ok(flag == *value)
+                } else {
+                    Ok(false)
+                }
+            },
+            RunCondition::Action { action, pressed } => {
+                Ok(context.input.action(*action)?.is_pressed() == *pressed)
+            },
+            RunCondition::World(world) => {
+                Ok(context.world_uid == *world)
+            },
+            RunCondition::Predicate(predicate) => {
+                let predicate = registry.systems.get_condition(predicate).with_context(|| "Run condition predicate not found in registry")?;
+                (predicate.callback)(context).map_err(|err| anyhow!("Run condition '{}' failed: {}", predicate.name, err))
+            },
+        }
+    }
+
+    /// Components read by the condition, taken into account when parallel systems are grouped
+    pub(crate) fn access(&self, access: SystemAccess) -> SystemAccess {
+        match self {
+            RunCondition::Singleton { component, .. } => access.read(*component),
+            _ => access,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Result, anyhow};
+    use serde::{Serialize, Deserialize};
+
+    use crate::{uid::UID, context::condition::RunConditionContext, registry::RegistryManager, input::InputManager, event::input::{InputEvent, InputActionEvent}, feature::asset::input_table::{InputTable, InputAction}, ecs::{world::World, component::{Component, ComponentHooks}, procedure::Procedure}};
+
+    use super::RunCondition;
+
+    const STATE: UID = UID::new("state");
+    const JUMP: UID = UID::new("jump");
+
+    #[derive(Serialize, Deserialize)]
+    struct State {
+        paused: bool,
+        level: u32,
+    }
+
+    impl Component for State {}
+
+    fn update(context: &RunConditionContext) -> Result<bool> {
+        Ok(context.procedure() == UID::new(Procedure::UPDATE))
+    }
+
+    fn failing(_: &RunConditionContext) -> Result<bool> {
+        Err(anyhow!("no state"))
+    }
+
+    fn setup() -> (RegistryManager, World, InputManager) {
+        let mut registry = RegistryManager::default();
+        registry.components.define_static::<State>("state", ComponentHooks::default()).unwrap();
+        registry.systems.define_condition("update", update).unwrap();
+        registry.systems.define_condition("failing", failing).unwrap();
+        let mut input = InputManager::default();
+        input.add_table(&InputTable {
+            name: "table".into(),
+            display_name: "Table".into(),
+            description: String::new(),
+            actions: vec![InputAction { name: "jump".into(), display_name: "Jump".into(), description: String::new(), default_pressed: false }],
+            axis: Vec::new(),
+        }).unwrap();
+        (registry, World::new("test"), input)
+    }
+
+    fn check(condition: &RunCondition, registry: &RegistryManager, world: &World, input: &InputManager) -> Result<bool> {
+        let context = RunConditionContext { procedure: Procedure::UPDATE.into(), world_uid: UID::new("test"), world, input };
+        condition.check(&context, registry)
+    }
+
+    #[test]
+    fn singleton_condition() {
+        let (registry, mut world, input) = setup();
+        let paused = RunCondition::Singleton { component: STATE, field: "paused".into(), value: true };
+        let running = RunCondition::Singleton { component: STATE, field: "paused".into(), value: false };
+        // Missing singletons don't hold
+        assert!(!check(&paused, &registry, &world, &input).unwrap());
+        assert!(!check(&running, &registry, &world, &input).unwrap());
+        world.add_singleton(STATE, State { paused: false, level: 1 }).unwrap();
+        assert!(!check(&paused, &registry, &world, &input).unwrap());
+        assert!(check(&running, &registry, &world, &input).unwrap());
+        // Writes are seen by the next check
+        world.get_singleton_mut::<State>(STATE).unwrap().unwrap().paused = true;
+        assert!(check(&paused, &registry, &world, &input).unwrap());
+        assert!(!check(&running, &registry, &world, &input).unwrap());
+        let level = RunCondition::Singleton { component: STATE, field: "level".into(), value: true };
+        let err = check(&level, &registry, &world, &input).unwrap_err();
+        assert_eq!(err.to_string(), "Singleton field 'level' is not a boolean");
+    }
+
+    #[test]
+    fn action_condition() {
+        let (registry, world, mut input) = setup();
+        let pressed = RunCondition::Action { action: JUMP, pressed: true };
+        let released = RunCondition::Action { action: JUMP, pressed: false };
+        assert!(!check(&pressed, &registry, &world, &input).unwrap());
+        assert!(check(&released, &registry, &world, &input).unwrap());
+        input.dispatch_event(&InputEvent::Action(InputActionEvent { action: JUMP, pressed: true }));
+        assert!(check(&pressed, &registry, &world, &input).unwrap());
+        assert!(!check(&released, &registry, &world, &input).unwrap());
+        assert!(check(&RunCondition::Action { action: UID::new("unknown"), pressed: true }, &registry, &world, &input).is_err());
+    }
+
+    #[test]
+    fn world_condition() {
+        let (registry, world, input) = setup();
+        assert!(check(&RunCondition::World(UID::new("test")), &registry, &world, &input).unwrap());
+        assert!(!check(&RunCondition::World(UID::new("other")), &registry, &world, &input).unwrap());
+    }
+
+    #[test]
+    fn predicate_condition() {
+        let (registry, world, input) = setup();
+        assert!(check(&RunCondition::Predicate(UID::new("update")), &registry, &world, &input).unwrap());
+        let err = check(&RunCondition::Predicate(UID::new("failing")), &registry, &world, &input).unwrap_err();
+        assert_eq!(err.to_string(), "Run condition 'failing' failed: no state");
+        let err = check(&RunCondition::Predicate(UID::new("unknown")), &registry, &world, &input).unwrap_err();
+        assert_eq!(err.to_string(), "Run condition predicate not found in registry");
+    }
+
+    #[test]
+    fn serialization_round_trip() {
+        let conditions = vec![
+            RunCondition::Singleton { component: STATE, field: "paused".into(), value: false },
+            RunCondition::Action { action: JUMP, pressed: true },
+            RunCondition::World(UID::new("test")),
+            RunCondition::Predicate(UID::new("update")),
+        ];
+        let json = serde_json::to_string(&conditions).unwrap();
+        let loaded: Vec<RunCondition> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", conditions));
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+    }
+}
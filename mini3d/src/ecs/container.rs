@@ -1,13 +1,13 @@
 use std::{any::Any, marker::PhantomData, fmt};
 
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
 use serde::{Serialize, Deserialize, de::{Visitor, self}, Deserializer, Serializer, ser::SerializeTuple};
 
 use crate::{feature::asset::runtime_component::FieldValue};
 
-use std::cell::RefCell;
+use std::cell::{RefCell, Cell};
 
-use super::{entity::Entity, sparse::PagedVector, component::{Component, ComponentRef, ComponentMut}};
+use super::{entity::Entity, sparse::PagedVector, component::{Component, ComponentRef, ComponentMut, ComponentHooks}};
 
 pub(crate) trait AnyComponentContainer {
     fn as_any(&self) -> &dyn Any;
@@ -16,12 +16,24 @@ pub(crate) trait AnyComponentContainer {
     fn contains(&self, entity: Entity) -> bool;
     fn len(&self) -> usize;
     fn remove(&mut self, entity: Entity);
+    fn mark_changed(&self, entity: Entity, tick: u64);
+    fn mark_added(&self, entity: Entity, tick: u64);
+    fn removed(&self) -> Vec<Entity>;
+    fn clear_removed(&mut self);
+    fn validate(&self) -> Result<()>;
 }
 
 pub(crate) struct ComponentContainer<C: Component> {
     pub(crate) components: RefCell<Vec<C>>,
     pub(crate) entities: Vec<Entity>,
     pub(crate) indices: PagedVector<usize>,
+    /// Tick of the insertion of each component
+    pub(crate) added: Vec<Cell<u64>>,
+    /// Tick of the last mutable access of each component
+    pub(crate) changed: Vec<Cell<u64>>,
+    /// Removed components, kept until drained or cleared at the start of the next frame
+    pub(crate) removed: Vec<(Entity, C)>,
+    pub(crate) hooks: ComponentHooks<C>,
 }
 
 impl<C: Component> ComponentContainer<C> {
@@ -45,10 +57,17 @@ impl<C: Component> ComponentContainer<C> {
                 use serde::de::Error;
                 let entities: Vec<Entity> = seq.next_element()?.with_context(|| "Expect entities").map_err(Error::custom)?;
                 let components: Vec<C> = seq.next_element()?.with_context(|| "Expect components").map_err(Error::custom)?;
+                // Loaded components are considered added since systems never ran on them
+                let added = vec![Cell::new(1); entities.len()];
+                let changed = added.clone();
                 let mut container = ComponentContainer::<C> {
                     components: RefCell::new(components),
                     entities,
                     indices: PagedVector::new(),
+                    added,
+                    changed,
+                    removed: Vec::new(),
+                    hooks: ComponentHooks::default(),
                 };
                 for (index, entity) in container.entities.iter().enumerate() {
                     container.indices.set(entity.key(), index);
@@ -59,11 +78,15 @@ impl<C: Component> ComponentContainer<C> {
         deserializer.deserialize_tuple(2, ContainerVisitor::<C> { marker: PhantomData })
     }
 
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(hooks: ComponentHooks<C>) -> Self {
         Self {
             components: RefCell::new(Vec::with_capacity(128)),
             entities: Vec::with_capacity(128),
             indices: PagedVector::new(),
+            added: Vec::with_capacity(128),
+            changed: Vec::with_capacity(128),
+            removed: Vec::new(),
+            hooks,
         }
     }
 
@@ -71,8 +94,13 @@ impl<C: Component> ComponentContainer<C> {
         self.entities.len()
     }
 
-    pub(crate) fn add(&mut self, entity: Entity, component: C) -> Result<()> {
+    pub(crate) fn add(&mut self, entity: Entity, mut component: C) -> Result<()> {
+        if let Some(hook) = &self.hooks.on_add {
+            hook(entity, &mut component);
+        }
         self.entities.push(entity);
+        self.added.push(Cell::new(0));
+        self.changed.push(Cell::new(0));
         self.indices.set(entity.key(), self.entities.len() - 1);
         self.components
             .try_borrow_mut().with_context(|| "Container already borrowed")?
@@ -81,38 +109,62 @@ impl<C: Component> ComponentContainer<C> {
     }
 
     pub(crate) fn remove(&mut self, entity: Entity) -> Result<()> {
-        if let Some(index) = self.indices.get(entity.key()).copied() {
-            self.components
+        if self.contains(entity) {
+            let index = *self.indices.get(entity.key()).unwrap();
+            let component = self.components
                 .try_borrow_mut().with_context(|| "Component container already borrowed")?
                 .swap_remove(index);
-            self.entities.swap_remove(index);
-            let swapped_entity = self.entities[index];
-            self.indices.set(swapped_entity.key(), index);
-            self.entities[entity.key()] = Entity::null();
+            if let Some(hook) = &self.hooks.on_remove {
+                hook(entity, &component);
+            }
+            self.removed.push((self.entities.swap_remove(index), component));
+            self.added.swap_remove(index);
+            self.changed.swap_remove(index);
+            // Update the index of the component moved in place of the removed one
+            if index < self.entities.len() {
+                let swapped_entity = self.entities[index];
+                self.indices.set(swapped_entity.key(), index);
+            }
         }
         Ok(())
     }
 
-    pub(crate) fn get(&self, entity: Entity) -> Option<ComponentRef<'_, C>> {
-        let components = self.components.borrow();
-        self.indices.get(entity.key()).and_then(|index| {
-            if self.entities[*index] == entity {
-                Some(ComponentRef { components, index: *index })
-            } else {
-                None
-            }
-        })
+    /// Replace the component of the entity. The remove hook runs on the previous
+    /// value and the add hook on the new one, as if the component was removed then added.
+    pub(crate) fn replace(&mut self, entity: Entity, mut component: C) -> Result<()> {
+        let index = *self.indices.get(entity.key())
+            .filter(|index| self.entities.get(**index) == Some(&entity))
+            .with_context(|| "Component not found")?;
+        let mut components = self.components
+            .try_borrow_mut().with_context(|| "Component container already borrowed")?;
+        if let Some(hook) = &self.hooks.on_remove {
+            hook(entity, &components[index]);
+        }
+        if let Some(hook) = &self.hooks.on_add {
+            hook(entity, &mut component);
+        }
+        components[index] = component;
+        Ok(())
     }
 
-    pub(crate) fn get_mut(&self, entity: Entity) -> Option<ComponentMut<'_, C>> {
-        let components = self.components.borrow_mut();
-        self.indices.get(entity.key()).and_then(|index| {
-            if self.entities[*index] == entity {
-                Some(ComponentMut { components, index: *index })
-            } else {
-                None
-            }
-        })
+    pub(crate) fn drain_removed(&mut self) -> Vec<(Entity, C)> {
+        std::mem::take(&mut self.removed)
+    }
+
+    fn index(&self, entity: Entity) -> Option<usize> {
+        self.indices.get(entity.key()).copied().filter(|index| self.entities.get(*index) == Some(&entity))
+    }
+
+    pub(crate) fn get(&self, entity: Entity) -> Result<Option<ComponentRef<'_, C>>> {
+        let components = self.components
+            .try_borrow().with_context(|| "Component container already borrowed mutably")?;
+        Ok(self.index(entity).map(|index| ComponentRef { components, index }))
+    }
+
+    pub(crate) fn get_mut(&self, entity: Entity) -> Result<Option<ComponentMut<'_, C>>> {
+        let components = self.components
+            .try_borrow_mut().with_context(|| "Component container already borrowed")?;
+        Ok(self.index(entity).map(|index| ComponentMut { components, index }))
     }
 }
 
@@ -129,20 +181,39 @@ impl<C: Component> AnyComponentContainer for ComponentContainer<C> {
     }
     fn len(&self) -> usize { self.len() }
     fn remove(&mut self, entity: Entity) { self.remove(entity).unwrap(); }
+    fn mark_changed(&self, entity: Entity, tick: u64) {
+        if self.contains(entity) {
+            self.changed[*self.indices.get(entity.key()).unwrap()].set(tick);
+        }
+    }
+    fn mark_added(&self, entity: Entity, tick: u64) {
+        if self.contains(entity) {
+            let index = *self.indices.get(entity.key()).unwrap();
+            self.added[index].set(tick);
+            self.changed[index].set(tick);
+        }
+    }
+    fn removed(&self) -> Vec<Entity> {
+        self.removed.iter().map(|(entity, _)| *entity).collect()
+    }
+    fn clear_removed(&mut self) { self.removed.clear(); }
+    fn validate(&self) -> Result<()> {
+        let components = self.components.try_borrow().with_context(|| "Container already borrowed")?.len();
+        if components != self.len() || self.added.len() != self.len() || self.changed.len() != self.len() {
+            return Err(anyhow!("Container holds {} entities but {} components, {} added and {} changed ticks", self.len(), components, self.added.len(), self.changed.len()));
+        }
+        for (index, entity) in self.entities.iter().enumerate() {
+            if self.indices.get(entity.key()) != Some(&index) {
+                return Err(anyhow!("Entity {:?} at index {} is indexed at {:?}", entity, index, self.indices.get(entity.key())));
+            }
+        }
+        Ok(())
+    }
 }
 
+/// Component whose fields are described at runtime by a DynamicComponentDefinition.
+/// Fields are stored in definition order and serialized as a sequence, states
+/// saved with the former fixed-size field arrays can't be loaded.
 #[derive(Serialize, Deserialize)]
-pub(crate) struct DynamicComponent1([FieldValue; 1]);
-impl Component for DynamicComponent1 {}
-#[derive(Serialize, Deserialize)]
-pub(crate) struct DynamicComponent2([FieldValue; 2]);
-impl Component for DynamicComponent2 {}
-#[derive(Serialize, Deserialize)]
-pub(crate) struct DynamicComponent3([FieldValue; 3]);
-impl Component for DynamicComponent3 {}
-#[derive(Serialize, Deserialize)]
-pub(crate) struct DynamicComponent4([FieldValue; 4]);
-impl Component for DynamicComponent4 {}
-#[derive(Serialize, Deserialize)]
-pub(crate) struct DynamicComponent5([FieldValue; 5]);
-impl Component for DynamicComponent5 {}
+pub(crate) struct DynamicComponent(pub(crate) Vec<FieldValue>);
+impl Component for DynamicComponent {}
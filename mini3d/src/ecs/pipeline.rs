@@ -1,34 +1,317 @@
 use anyhow::{Result, Context};
+use rayon::ThreadPool;
 
-use crate::{context::SystemContext, script::ScriptManager, registry::system::{SystemRegistry, SystemCode}, uid::UID};
+use crate::{context::{SystemContext, ParallelSystemContext, world::ParallelWorldContext, condition::RunConditionContext}, registry::{system::{SystemRegistry, SystemCode}, RegistryManager}, uid::UID};
+
+use super::{system::{ParallelSystemCallback, SystemAccess}, condition::RunCondition};
+
+struct ParallelSystem {
+    uid: UID,
+    callback: ParallelSystemCallback,
+    access: SystemAccess,
+    /// Declared access extended with the components read by the conditions
+    stage_access: SystemAccess,
+    conditions: Vec<RunCondition>,
+}
+
+enum PipelineStage {
+    Exclusive(UID, SystemCode, Vec<RunCondition>),
+    /// Consecutive parallel systems whose accesses don't conflict
+    Parallel(Vec<ParallelSystem>),
+}
+
+/// Moves the context of a parallel system to a worker thread.
+struct SendContext<'a>(ParallelSystemContext<'a>);
+
+// SAFETY: the contexts of a stage share the world, which is only sound while the stage runs if:
+// - There is no structural mutation. A parallel world context can't create or destroy entities,
+//   add or remove components or queue commands, so containers, entities and names are not modified
+//   and no component hook runs.
+// - No `Cell` is written by a system while another one reaches it. Borrow flags and change ticks
+//   of a component are only written by the single system of the stage declaring it as written.
+//   Components read by several systems are accessed without touching their borrow flag.
+unsafe impl Send for SendContext<'_> {}
+
+impl SendContext<'_> {
+    fn run(mut self, callback: ParallelSystemCallback) -> Result<()> {
+        callback(&mut self.0)
+    }
+}
 
 pub(crate) struct SystemPipeline {
-    systems: Vec<SystemCode>,
+    stages: Vec<PipelineStage>,
 }
 
 impl SystemPipeline {
 
-    pub(crate) fn build<'a>(registry: &SystemRegistry, systems: impl Iterator<Item = &'a UID>) -> Result<Self> {
-        let mut codes = Vec::new();
-        for uid in systems {
-            let system = registry.get(uid).with_context(|| "System not found in registry")?;
-            codes.push(system.code);
+    pub(crate) fn build(registry: &SystemRegistry, systems: impl Iterator<Item = (UID, Vec<RunCondition>)>) -> Result<Self> {
+        let mut stages = Vec::new();
+        for (uid, conditions) in systems {
+            let system = registry.get(&uid).with_context(|| "System not found in registry")?;
+            match &system.code {
+                SystemCode::Parallel(callback, access) => {
+                    let stage_access = conditions.iter().fold(access.clone(), |access, condition| condition.access(access));
+                    // Predicates can read anything, they are checked before the other systems of the stage run
+                    let predicate = conditions.iter().any(|condition| matches!(condition, RunCondition::Predicate(_)));
+                    let system = ParallelSystem { uid, callback: *callback, access: access.clone(), stage_access, conditions };
+                    if let Some(PipelineStage::Parallel(stage)) = stages.last_mut() {
+                        if !predicate && stage.iter().all(|other| other.uid != uid && !system.stage_access.conflicts(&other.stage_access)) {
+                            stage.push(system);
+                            continue;
+                        }
+                    }
+                    stages.push(PipelineStage::Parallel(vec![system]));
+                },
+                code => stages.push(PipelineStage::Exclusive(uid, code.clone(), conditions)),
+            }
+        }
+        Ok(Self { stages })
+    }
+
+    fn check_conditions(conditions: &[RunCondition], context: &RunConditionContext, registry: &RegistryManager) -> Result<bool> {
+        for condition in conditions {
+            if !condition.check(context, registry)? {
+                return Ok(false);
+            }
         }
-        Ok(Self { systems: codes })
+        Ok(true)
     }
 
-    pub(crate) fn run(&self, context: &mut SystemContext, _script: &ScriptManager) -> Result<()> {
-        for system in &self.systems {
-            match system {
-                SystemCode::Static(callback) => callback(context)?,
-                SystemCode::Rhai(_uid) => {
-                    todo!()
+    /// Parallel stages run on the thread pool when there is one, otherwise one system after another.
+    pub(crate) fn run(&self, context: &mut SystemContext, pool: Option<&ThreadPool>) -> Result<()> {
+        for stage in &self.stages {
+            match stage {
+                PipelineStage::Exclusive(uid, system, conditions) => {
+                    if !conditions.is_empty() {
+                        let registry = context.world.registry.borrow();
+                        let world = context.world.worlds.get(&context.world.active_world).unwrap().borrow();
+                        let condition_context = RunConditionContext {
+                            procedure: context.procedure.active_procedure,
+                            world_uid: context.world.active_world,
+                            world: &world,
+                            input: context.input.manager,
+                        };
+                        if !Self::check_conditions(conditions, &condition_context, &registry)? {
+                            continue;
+                        }
+                    }
+                    context.world.system = *uid;
+                    match system {
+                        SystemCode::Static(callback) => callback(context)?,
+                        SystemCode::Parallel(..) => unreachable!("Parallel systems are run by stages"),
+                        SystemCode::Rhai(uid) => {
+                            context.script.manager.borrow_mut().rhai.run_system(*uid, context);
+                        },
+                        SystemCode::Lua(uid) => {
+                            context.script.manager.borrow_mut().lua.run_system(*uid, context);
+                        },
+                    }
+                    context.world.end_system()?;
                 },
-                SystemCode::Lua(_uid) => {
-                    todo!()
+                PipelineStage::Parallel(systems) => {
+                    let results = Self::run_stage(systems, context, pool)?;
+                    // Systems end in pipeline order, giving the same change ticks as a sequential run
+                    for (uid, result) in results {
+                        result?;
+                        context.world.system = uid;
+                        context.world.end_system()?;
+                    }
                 },
             }
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Systems whose conditions hold are run, their results are returned in pipeline order
+    fn run_stage(systems: &[ParallelSystem], context: &SystemContext, pool: Option<&ThreadPool>) -> Result<Vec<(UID, Result<()>)>> {
+        let registry = context.world.registry.borrow();
+        let world = context.world.worlds.get(&context.world.active_world).unwrap().borrow();
+        let condition_context = RunConditionContext {
+            procedure: context.procedure.active_procedure,
+            world_uid: context.world.active_world,
+            world: &world,
+            input: context.input.manager,
+        };
+        let mut systems_to_run = Vec::new();
+        for system in systems {
+            if Self::check_conditions(&system.conditions, &condition_context, &registry)? {
+                systems_to_run.push(system);
+            }
+        }
+        let systems = systems_to_run;
+        let contexts = systems.iter().enumerate().map(|(index, system)| {
+            SendContext(ParallelSystemContext {
+                time: context.time.clone(),
+                world: ParallelWorldContext {
+                    uid: context.world.active_world,
+                    world: &world,
+                    registry: &registry.components,
+                    access: &system.access,
+                    last_run: world.last_run(system.uid),
+                    tick: world.change_tick + index as u64,
+                },
+            })
+        }).collect::<Vec<_>>();
+        let mut results = systems.iter().map(|_| Ok(())).collect::<Vec<_>>();
+        match pool {
+            Some(pool) if systems.len() > 1 && pool.current_num_threads() > 1 => {
+                pool.scope(|scope| {
+                    for ((context, system), result) in contexts.into_iter().zip(&systems).zip(&mut results) {
+                        scope.spawn(move |_| *result = context.run(system.callback));
+                    }
+                });
+            },
+            _ => {
+                for ((context, system), result) in contexts.into_iter().zip(&systems).zip(&mut results) {
+                    *result = context.run(system.callback);
+                }
+            },
+        }
+        Ok(systems.iter().map(|system| system.uid).zip(results).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use rayon::ThreadPoolBuilder;
+
+    use crate::{engine::Engine, event::Events, request::Requests, uid::UID, context::{SystemContext, ParallelSystemContext, world::ParallelWorldContext}, registry::system::SystemRegistry, ecs::{container::ComponentContainer, component::{Component, ComponentHooks}, procedure::Procedure, query::With, system::SystemAccess, condition::RunCondition, testing::{setup, Health, Position, Velocity, HEALTH, POSITION, VELOCITY}}, feature::asset::system_group::{SystemGroup, SystemPipeline as PipelineAsset}};
+
+    use super::{SystemPipeline, PipelineStage};
+
+    fn noop(_: &mut ParallelSystemContext) -> Result<()> {
+        Ok(())
+    }
+
+    fn build(systems: &[(&str, SystemAccess)], conditions: &[(&str, RunCondition)]) -> Vec<Vec<UID>> {
+        let mut registry = SystemRegistry::default();
+        for (name, access) in systems {
+            registry.define_parallel(name, noop, access.clone()).unwrap();
+        }
+        let pipeline = SystemPipeline::build(&registry, systems.iter().map(|(name, _)| {
+            let conditions = conditions.iter().filter(|(system, _)| system == name).map(|(_, condition)| condition.clone()).collect();
+            (UID::new(name), conditions)
+        })).unwrap();
+        pipeline.stages.iter().map(|stage| match stage {
+            PipelineStage::Exclusive(uid, ..) => vec![*uid],
+            PipelineStage::Parallel(systems) => systems.iter().map(|system| system.uid).collect(),
+        }).collect()
+    }
+
+    #[test]
+    fn readers_share_a_stage() {
+        let stages = build(&[
+            ("a", SystemAccess::new().read(POSITION)),
+            ("b", SystemAccess::new().read(POSITION).read(VELOCITY)),
+            ("c", SystemAccess::new().read(VELOCITY).write(HEALTH)),
+        ], &[]);
+        assert_eq!(stages, vec![vec![UID::new("a"), UID::new("b"), UID::new("c")]]);
+    }
+
+    #[test]
+    fn write_conflicts_split_stages() {
+        let stages = build(&[
+            ("a", SystemAccess::new().write(POSITION)),
+            ("b", SystemAccess::new().write(POSITION)),
+            ("c", SystemAccess::new().read(VELOCITY)),
+            ("d", SystemAccess::new().read(POSITION)),
+            ("e", SystemAccess::new().write(VELOCITY)),
+        ], &[]);
+        assert_eq!(stages, vec![vec![UID::new("a")], vec![UID::new("b"), UID::new("c")], vec![UID::new("d"), UID::new("e")]]);
+        // Components read by the conditions conflict like declared reads
+        let condition = RunCondition::Singleton { component: POSITION, field: "enabled".into(), value: true };
+        let stages = build(&[
+            ("a", SystemAccess::new().write(POSITION)),
+            ("b", SystemAccess::new().read(VELOCITY)),
+        ], &[("b", condition)]);
+        assert_eq!(stages, vec![vec![UID::new("a")], vec![UID::new("b")]]);
+    }
+
+    #[test]
+    fn undeclared_access_is_an_error() {
+        let (mut world, registry) = setup();
+        let entity = world.create();
+        world.add(&registry, entity, POSITION, Position(0)).unwrap();
+        world.add(&registry, entity, VELOCITY, Velocity(0)).unwrap();
+        let (writer, reader) = (SystemAccess::new().write(POSITION), SystemAccess::new().read(VELOCITY));
+        let context = |access| ParallelWorldContext { uid: UID::null(), world: &world, registry: &registry, access, last_run: 0, tick: 1 };
+        let (writer, reader) = (context(&writer), context(&reader));
+        // The writer holds the container while the reader reaches for it
+        let _positions = writer.view_mut::<Position>(POSITION).unwrap();
+        let err = reader.view::<Position>(POSITION).err().unwrap();
+        assert_eq!(err.to_string(), "Component 'position' is not declared as read by the system");
+        assert!(reader.query_mut::<&Position>().is_err());
+        assert!(reader.query_filtered::<&Velocity, With<Position>>().is_err());
+        let err = reader.view_mut::<Velocity>(VELOCITY).err().unwrap();
+        assert_eq!(err.to_string(), "Component 'velocity' is not declared as written by the system");
+        assert!(reader.query_mut::<&Velocity>().is_ok());
+    }
+
+    fn integrate(ctx: &mut ParallelSystemContext) -> Result<()> {
+        for (position, velocity) in &mut ctx.world.query_mut::<(&mut Position, &Velocity)>()? {
+            position.0 += velocity.0;
+        }
+        Ok(())
+    }
+
+    fn accelerate(ctx: &mut ParallelSystemContext) -> Result<()> {
+        for velocity in &mut ctx.world.query_mut::<&mut Velocity>()? {
+            velocity.0 += 1;
+        }
+        Ok(())
+    }
+
+    fn measure(ctx: &mut ParallelSystemContext) -> Result<()> {
+        for (health, position) in &mut ctx.world.query_mut::<(&mut Health, &Position)>()? {
+            health.0 += position.0.unsigned_abs();
+        }
+        Ok(())
+    }
+
+    fn init(ctx: &mut SystemContext) -> Result<()> {
+        ctx.registry.define_static_component::<Health>("health", ComponentHooks::default())?;
+        ctx.registry.define_static_component::<Position>("position", ComponentHooks::default())?;
+        ctx.registry.define_static_component::<Velocity>("velocity", ComponentHooks::default())?;
+        ctx.registry.define_parallel_system("integrate", integrate, SystemAccess::new().write(POSITION).read(VELOCITY))?;
+        ctx.registry.define_parallel_system("accelerate", accelerate, SystemAccess::new().write(VELOCITY))?;
+        ctx.registry.define_parallel_system("measure", measure, SystemAccess::new().write(HEALTH).read(POSITION))?;
+        let mut group = SystemGroup::empty();
+        group.insert(Procedure::UPDATE, PipelineAsset::new(&[UID::new("integrate"), UID::new("accelerate"), UID::new("measure")]), 0);
+        ctx.scheduler.add_group("test", group)?;
+        let world = ctx.world.add("test")?;
+        ctx.world.change(world)?;
+        let mut world = ctx.world.get(world)?;
+        for index in 0..16 {
+            let entity = world.create();
+            world.add(entity, HEALTH, Health(0))?;
+            world.add(entity, POSITION, Position(index - 8))?;
+            world.add(entity, VELOCITY, Velocity(index % 3 - 1))?;
+        }
+        Ok(())
+    }
+
+    /// Values and change ticks of a component
+    fn snapshot<C: Component + Copy>(engine: &Engine, component: UID) -> Vec<(C, u64)> {
+        let worlds = engine.ecs.worlds.borrow();
+        let world = worlds.get(&UID::new("test")).unwrap().borrow();
+        let container = world.container(component).unwrap().as_any().downcast_ref::<ComponentContainer<C>>().unwrap();
+        let components = container.components.borrow();
+        components.iter().copied().zip(container.changed.iter().map(|tick| tick.get())).collect()
+    }
+
+    #[test]
+    fn parallel_stages_match_sequential_runs() {
+        let run = |threads: Option<usize>| {
+            let mut engine = Engine::new(init).unwrap();
+            engine.ecs.thread_pool = threads.map(|threads| ThreadPoolBuilder::new().num_threads(threads).build().unwrap());
+            for _ in 0..4 {
+                engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap();
+            }
+            (snapshot::<Health>(&engine, HEALTH), snapshot::<Position>(&engine, POSITION), snapshot::<Velocity>(&engine, VELOCITY))
+        };
+        let sequential = run(None);
+        assert_eq!(sequential.0[0].0.0, 9 + 9 + 8 + 6);
+        assert_eq!(run(Some(4)), sequential);
+    }
+}
@@ -1,3 +1,7 @@
+use serde::{Serialize, Deserialize};
+
+use crate::uid::UID;
+
 pub struct Procedure;
 
 impl Procedure {
@@ -5,4 +9,97 @@ impl Procedure {
     pub const UPDATE: &'static str = "update";
     pub const FIXED_UPDATE: &'static str = "fixed_update";
     pub const WORLD_CHANGED: &'static str = "world_changed";
-}
\ No newline at end of file
+}
+
+/// Maximum number of runs of a fixed procedure in a single frame
+const MAX_CATCH_UP_STEPS: u32 = 16;
+
+/// Procedure invoked at its own fixed rate, with its own time accumulator
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FixedProcedure {
+    pub(crate) procedure: UID,
+    pub(crate) timestep: f64,
+    accumulator: f64,
+}
+
+impl FixedProcedure {
+
+    pub(crate) fn new(procedure: UID, timestep: f64) -> Self {
+        Self { procedure, timestep, accumulator: 0.0 }
+    }
+
+    /// Integrate the frame time and return the number of runs due this frame. Runs
+    /// past MAX_CATCH_UP_STEPS are dropped so a slow frame doesn't slow down the next ones.
+    pub(crate) fn advance(&mut self, delta_time: f64) -> u32 {
+        self.accumulator += delta_time;
+        let count = (self.accumulator / self.timestep) as u32;
+        self.accumulator -= count as f64 * self.timestep;
+        count.min(MAX_CATCH_UP_STEPS)
+    }
+}
+
+/// Procedure queued for the current frame
+#[derive(Clone, Copy)]
+pub(crate) struct FrameProcedure {
+    pub(crate) uid: UID,
+    /// Index of the run which invoked the procedure, none for procedures queued by the engine
+    pub(crate) invoker: Option<usize>,
+}
+
+impl From<UID> for FrameProcedure {
+    fn from(uid: UID) -> Self {
+        Self { uid, invoker: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{uid::UID, ecs::scheduler::Scheduler};
+
+    use super::FixedProcedure;
+
+    fn runs(fixed: &mut FixedProcedure, deltas: &[f64]) -> Vec<u32> {
+        deltas.iter().map(|delta| fixed.advance(*delta)).collect()
+    }
+
+    #[test]
+    fn accumulator_carries_the_remainder() {
+        let mut fixed = FixedProcedure::new(UID::new("fixed"), 0.25);
+        assert_eq!(runs(&mut fixed, &[0.125, 0.125, 0.125, 0.0, 0.375]), vec![0, 1, 0, 0, 2]);
+        // A long frame catches up every missed step
+        assert_eq!(runs(&mut fixed, &[1.0, 0.125]), vec![4, 0]);
+        assert_eq!(fixed.accumulator, 0.125);
+        // Steps past the limit are dropped, the remainder is kept
+        assert_eq!(runs(&mut fixed, &[10.0, 0.125]), vec![16, 1]);
+        assert_eq!(fixed.accumulator, 0.0);
+    }
+
+    #[test]
+    fn procedures_have_their_own_accumulator() {
+        let mut scheduler = Scheduler::default();
+        let slow = scheduler.add_fixed_procedure("slow", 2.0).unwrap();
+        let fast = scheduler.add_fixed_procedure("fast", 8.0).unwrap();
+        assert_eq!(scheduler.fixed_timestep(slow), Some(0.5));
+        assert_eq!(scheduler.fixed_timestep(fast), Some(0.125));
+        let mut counts = vec![0, 0];
+        for _ in 0..8 {
+            for (count, fixed) in counts.iter_mut().zip(scheduler.fixed_procedures_mut()) {
+                *count += fixed.advance(0.125);
+            }
+        }
+        assert_eq!(counts, vec![2, 8]);
+        scheduler.remove_fixed_procedure(slow).unwrap();
+        assert_eq!(scheduler.fixed_procedures_mut().map(|fixed| fixed.procedure).collect::<Vec<_>>(), vec![fast]);
+    }
+
+    #[test]
+    fn invalid_fixed_procedures_are_rejected() {
+        let mut scheduler = Scheduler::default();
+        scheduler.add_fixed_procedure("fixed", 60.0).unwrap();
+        assert!(scheduler.add_fixed_procedure("fixed", 30.0).is_err());
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(scheduler.add_fixed_procedure("other", rate).is_err());
+        }
+        assert!(scheduler.remove_fixed_procedure(UID::new("other")).is_err());
+    }
+}
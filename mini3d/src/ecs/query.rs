@@ -1,5 +1,11 @@
 
-use super::{container::AnyComponentContainer, entity::Entity};
+use std::{cell::RefMut, marker::PhantomData};
+
+use anyhow::{Result, Context, anyhow};
+
+use crate::{uid::UID, registry::component::ComponentRegistry};
+
+use super::{container::{AnyComponentContainer, ComponentContainer}, entity::Entity, component::{Component, SharedRef}, world::World, system::SystemAccess};
 
 pub struct Query<'a> {
     containers: Vec<&'a dyn AnyComponentContainer>,
@@ -63,4 +69,565 @@ impl<'a> IntoIterator for &'a Query<'a> {
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
-}
\ No newline at end of file
+}
+
+/// Typed access requested by a query, used to detect borrow conflicts before
+/// any container is borrowed.
+struct QueryAccess {
+    component: UID,
+    mutable: bool,
+}
+
+pub struct QueryBuilder<'a> {
+    world: &'a World,
+    registry: &'a ComponentRegistry,
+    access: Vec<QueryAccess>,
+    last_run: u64,
+    /// Declared access of the parallel system running the query
+    scheduled: Option<&'a SystemAccess>,
+    tick: u64,
+}
+
+impl<'a> QueryBuilder<'a> {
+
+    fn check_scheduled(&self, component: UID, mutable: bool) -> Result<()> {
+        if let Some(access) = self.scheduled {
+            let name = self.registry.get(component).unwrap().name.as_str();
+            if mutable && !access.can_write(component) {
+                return Err(anyhow!("Component '{}' is not declared as written by the system", name));
+            }
+            if !access.can_read(component) {
+                return Err(anyhow!("Component '{}' is not declared as read by the system", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Container lookup for filters, which never borrow component data
+    fn container<C: Component>(&self) -> Result<Option<&'a ComponentContainer<C>>> {
+        let component = self.registry.find::<C>().with_context(|| format!("Component type '{}' not registered", std::any::type_name::<C>()))?;
+        self.check_scheduled(component, false)?;
+        if let Some(container) = self.world.container(component) {
+            Ok(Some(container.as_any().downcast_ref::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn borrow<C: Component>(&mut self, mutable: bool) -> Result<Option<(&'a ComponentContainer<C>, &'a str, UID)>> {
+        let component = self.registry.find::<C>().with_context(|| format!("Component type '{}' not registered", std::any::type_name::<C>()))?;
+        let name = self.registry.get(component).unwrap().name.as_str();
+        self.check_scheduled(component, mutable)?;
+        if let Some(access) = self.access.iter().find(|access| access.component == component) {
+            if access.mutable || mutable {
+                return Err(anyhow!("Component '{}' is accessed mutably more than once in query", name));
+            }
+        }
+        self.access.push(QueryAccess { component, mutable });
+        if let Some(container) = self.world.container(component) {
+            Ok(Some((container.as_any().downcast_ref::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?, name, component)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn borrow_ref<C: Component>(&mut self) -> Result<FetchRef<'a, C>> {
+        if let Some((container, name, component)) = self.borrow::<C>(false)? {
+            let components = if self.scheduled.is_some_and(|access| !access.can_write(component)) {
+                // SAFETY: no other system of the parallel stage writes the component
+                unsafe { SharedRef::scheduled(&container.components) }
+            } else {
+                SharedRef::Tracked(container.components.try_borrow()
+                    .map_err(|_| anyhow!("Component '{}' is already borrowed mutably", name))?)
+            };
+            Ok(FetchRef { data: Some((container, components)) })
+        } else {
+            Ok(FetchRef { data: None })
+        }
+    }
+
+    fn borrow_mut<C: Component>(&mut self) -> Result<FetchMut<'a, C>> {
+        if let Some((container, name, _)) = self.borrow::<C>(true)? {
+            let mut components = container.components.try_borrow_mut()
+                .map_err(|_| anyhow!("Component '{}' is already borrowed", name))?;
+            let ptr = components.as_mut_ptr();
+            Ok(FetchMut { data: Some((container, components)), components: ptr, tick: self.tick })
+        } else {
+            Ok(FetchMut { data: None, components: std::ptr::null_mut(), tick: self.tick })
+        }
+    }
+}
+
+/// Element of a typed query, implemented for `Entity`, `&C`, `&mut C` and tuples of them.
+pub trait QueryParam {
+    type Fetch<'a>;
+    type Item<'q>;
+    #[doc(hidden)]
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>>;
+    /// Smallest set of entities the query has to iterate, None if the parameter does not restrict it
+    #[doc(hidden)]
+    fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]>;
+    #[doc(hidden)]
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool;
+    /// # Safety
+    /// The entity must match the query and must not be fetched twice while a previous item is alive.
+    #[doc(hidden)]
+    unsafe fn item<'q>(fetch: &'q Self::Fetch<'_>, entity: Entity) -> Self::Item<'q>;
+}
+
+pub struct FetchRef<'a, C: Component> {
+    data: Option<(&'a ComponentContainer<C>, SharedRef<'a, Vec<C>>)>,
+}
+
+pub struct FetchMut<'a, C: Component> {
+    data: Option<(&'a ComponentContainer<C>, RefMut<'a, Vec<C>>)>,
+    components: *mut C,
+    tick: u64,
+}
+
+pub struct FetchFilter<'a, C: Component> {
+    container: Option<&'a ComponentContainer<C>>,
+    last_run: u64,
+}
+
+impl QueryParam for Entity {
+    type Fetch<'a> = ();
+    type Item<'q> = Entity;
+    fn fetch<'a>(_builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> { Ok(()) }
+    fn candidates<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> { None }
+    fn matches(_fetch: &Self::Fetch<'_>, _entity: Entity) -> bool { true }
+    unsafe fn item<'q>(_fetch: &'q Self::Fetch<'_>, entity: Entity) -> Self::Item<'q> { entity }
+}
+
+impl<C: Component> QueryParam for &C {
+    type Fetch<'a> = FetchRef<'a, C>;
+    type Item<'q> = &'q C;
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+        builder.borrow_ref::<C>()
+    }
+    fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        Some(fetch.data.as_ref().map_or(&[], |(container, _)| &container.entities))
+    }
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        fetch.data.as_ref().is_some_and(|(container, _)| container.contains(entity))
+    }
+    unsafe fn item<'q>(fetch: &'q Self::Fetch<'_>, entity: Entity) -> Self::Item<'q> {
+        let (container, components) = fetch.data.as_ref().unwrap();
+        &components[*container.indices.get(entity.key()).unwrap()]
+    }
+}
+
+impl<C: Component> QueryParam for &mut C {
+    type Fetch<'a> = FetchMut<'a, C>;
+    type Item<'q> = &'q mut C;
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+        builder.borrow_mut::<C>()
+    }
+    fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        Some(fetch.data.as_ref().map_or(&[], |(container, _)| &container.entities))
+    }
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        fetch.data.as_ref().is_some_and(|(container, _)| container.contains(entity))
+    }
+    unsafe fn item<'q>(fetch: &'q Self::Fetch<'_>, entity: Entity) -> Self::Item<'q> {
+        let (container, _) = fetch.data.as_ref().unwrap();
+        let index = *container.indices.get(entity.key()).unwrap();
+        container.changed[index].set(fetch.tick);
+        // The vector is exclusively borrowed by the fetch and each entity owns a distinct slot
+        &mut *fetch.components.add(index)
+    }
+}
+
+impl<T: QueryParam> QueryParam for Option<T> {
+    type Fetch<'a> = T::Fetch<'a>;
+    type Item<'q> = Option<T::Item<'q>>;
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> { T::fetch(builder) }
+    fn candidates<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> { None }
+    fn matches(_fetch: &Self::Fetch<'_>, _entity: Entity) -> bool { true }
+    unsafe fn item<'q>(fetch: &'q Self::Fetch<'_>, entity: Entity) -> Self::Item<'q> {
+        if T::matches(fetch, entity) { Some(T::item(fetch, entity)) } else { None }
+    }
+}
+
+macro_rules! impl_query_param_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: QueryParam),*> QueryParam for ($($name,)*) {
+            type Fetch<'a> = ($($name::Fetch<'a>,)*);
+            type Item<'q> = ($($name::Item<'q>,)*);
+            fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+                Ok(($($name::fetch(builder)?,)*))
+            }
+            fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+                let ($($name,)*) = fetch;
+                smallest([$($name::candidates($name)),*])
+            }
+            fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+                let ($($name,)*) = fetch;
+                $($name::matches($name, entity))&&*
+            }
+            unsafe fn item<'q>(fetch: &'q Self::Fetch<'_>, entity: Entity) -> Self::Item<'q> {
+                let ($($name,)*) = fetch;
+                ($($name::item($name, entity),)*)
+            }
+        }
+    };
+}
+
+impl_query_param_tuple!(A);
+impl_query_param_tuple!(A, B);
+impl_query_param_tuple!(A, B, C);
+impl_query_param_tuple!(A, B, C, D);
+impl_query_param_tuple!(A, B, C, D, E);
+impl_query_param_tuple!(A, B, C, D, E, F);
+impl_query_param_tuple!(A, B, C, D, E, F, G);
+impl_query_param_tuple!(A, B, C, D, E, F, G, H);
+
+/// Restriction of a typed query which doesn't yield any data.
+pub trait QueryFilter {
+    type Fetch<'a>;
+    #[doc(hidden)]
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>>;
+    #[doc(hidden)]
+    fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]>;
+    #[doc(hidden)]
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool;
+}
+
+/// Entity must have the component
+pub struct With<C: Component>(PhantomData<C>);
+/// Entity must not have the component
+pub struct Without<C: Component>(PhantomData<C>);
+/// Component was added or accessed mutably since the last run of the current system
+pub struct Changed<C: Component>(PhantomData<C>);
+/// Component was added since the last run of the current system
+pub struct Added<C: Component>(PhantomData<C>);
+/// At least one of the filters must match
+pub struct Or<T>(PhantomData<T>);
+
+impl<C: Component> QueryFilter for With<C> {
+    type Fetch<'a> = FetchFilter<'a, C>;
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+        Ok(FetchFilter { container: builder.container::<C>()?, last_run: builder.last_run })
+    }
+    fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        Some(fetch.container.map_or(&[], |container| &container.entities))
+    }
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        fetch.container.is_some_and(|container| container.contains(entity))
+    }
+}
+
+impl<C: Component> QueryFilter for Without<C> {
+    type Fetch<'a> = FetchFilter<'a, C>;
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+        Ok(FetchFilter { container: builder.container::<C>()?, last_run: builder.last_run })
+    }
+    fn candidates<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> { None }
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        !fetch.container.is_some_and(|container| container.contains(entity))
+    }
+}
+
+impl<C: Component> QueryFilter for Changed<C> {
+    type Fetch<'a> = FetchFilter<'a, C>;
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+        Ok(FetchFilter { container: builder.container::<C>()?, last_run: builder.last_run })
+    }
+    fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        Some(fetch.container.map_or(&[], |container| &container.entities))
+    }
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        fetch.container.is_some_and(|container| {
+            container.contains(entity) && container.changed[*container.indices.get(entity.key()).unwrap()].get() > fetch.last_run
+        })
+    }
+}
+
+impl<C: Component> QueryFilter for Added<C> {
+    type Fetch<'a> = FetchFilter<'a, C>;
+    fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+        Ok(FetchFilter { container: builder.container::<C>()?, last_run: builder.last_run })
+    }
+    fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+        Some(fetch.container.map_or(&[], |container| &container.entities))
+    }
+    fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+        fetch.container.is_some_and(|container| {
+            container.contains(entity) && container.added[*container.indices.get(entity.key()).unwrap()].get() > fetch.last_run
+        })
+    }
+}
+
+impl QueryFilter for () {
+    type Fetch<'a> = ();
+    fn fetch<'a>(_builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> { Ok(()) }
+    fn candidates<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> { None }
+    fn matches(_fetch: &Self::Fetch<'_>, _entity: Entity) -> bool { true }
+}
+
+macro_rules! impl_query_filter_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            type Fetch<'a> = ($($name::Fetch<'a>,)*);
+            fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+                Ok(($($name::fetch(builder)?,)*))
+            }
+            fn candidates<'f>(fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> {
+                let ($($name,)*) = fetch;
+                smallest([$($name::candidates($name)),*])
+            }
+            fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+                let ($($name,)*) = fetch;
+                $($name::matches($name, entity))&&*
+            }
+        }
+    };
+}
+
+impl_query_filter_tuple!(A);
+impl_query_filter_tuple!(A, B);
+impl_query_filter_tuple!(A, B, C);
+impl_query_filter_tuple!(A, B, C, D);
+
+macro_rules! impl_query_filter_or {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: QueryFilter),*> QueryFilter for Or<($($name,)*)> {
+            type Fetch<'a> = ($($name::Fetch<'a>,)*);
+            fn fetch<'a>(builder: &mut QueryBuilder<'a>) -> Result<Self::Fetch<'a>> {
+                Ok(($($name::fetch(builder)?,)*))
+            }
+            fn candidates<'f>(_fetch: &'f Self::Fetch<'_>) -> Option<&'f [Entity]> { None }
+            fn matches(fetch: &Self::Fetch<'_>, entity: Entity) -> bool {
+                let ($($name,)*) = fetch;
+                $($name::matches($name, entity))||*
+            }
+        }
+    };
+}
+
+impl_query_filter_or!(A, B);
+impl_query_filter_or!(A, B, C);
+impl_query_filter_or!(A, B, C, D);
+
+fn smallest<const N: usize>(candidates: [Option<&[Entity]>; N]) -> Option<&[Entity]> {
+    candidates.into_iter().flatten().min_by_key(|entities| entities.len())
+}
+
+/// Typed query holding the borrows of its components until dropped.
+pub struct QueryMut<'a, Q: QueryParam, F: QueryFilter = ()> {
+    fetch: Q::Fetch<'a>,
+    filter: F::Fetch<'a>,
+}
+
+impl<'a, Q: QueryParam, F: QueryFilter> QueryMut<'a, Q, F> {
+
+    pub(crate) fn new(world: &'a World, registry: &'a ComponentRegistry, last_run: u64) -> Result<Self> {
+        Self::build(QueryBuilder { world, registry, access: Vec::new(), last_run, scheduled: None, tick: world.change_tick })
+    }
+
+    /// Query of a parallel system, restricted to its declared access
+    pub(crate) fn scheduled(world: &'a World, registry: &'a ComponentRegistry, access: &'a SystemAccess, last_run: u64, tick: u64) -> Result<Self> {
+        Self::build(QueryBuilder { world, registry, access: Vec::new(), last_run, scheduled: Some(access), tick })
+    }
+
+    fn build(mut builder: QueryBuilder<'a>) -> Result<Self> {
+        let fetch = Q::fetch(&mut builder)?;
+        let filter = F::fetch(&mut builder)?;
+        if Q::candidates(&fetch).is_none() && F::candidates(&filter).is_none() {
+            return Err(anyhow!("Query must require at least one component"));
+        }
+        Ok(Self { fetch, filter })
+    }
+
+    fn candidates(&self) -> &[Entity] {
+        smallest([Q::candidates(&self.fetch), F::candidates(&self.filter)]).unwrap_or(&[])
+    }
+
+    fn matches(&self, entity: Entity) -> bool {
+        Q::matches(&self.fetch, entity) && F::matches(&self.filter, entity)
+    }
+
+    pub fn iter_mut(&mut self) -> QueryMutIter<'_, 'a, Q, F> {
+        QueryMutIter {
+            entities: self.candidates(),
+            query: self,
+            index: 0,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        if self.matches(entity) {
+            // SAFETY: the returned item borrows the query mutably
+            Some(unsafe { Q::item(&self.fetch, entity) })
+        } else {
+            None
+        }
+    }
+}
+
+pub struct QueryMutIter<'q, 'a, Q: QueryParam, F: QueryFilter> {
+    query: &'q QueryMut<'a, Q, F>,
+    entities: &'q [Entity],
+    index: usize,
+}
+
+impl<'q, 'a, Q: QueryParam, F: QueryFilter> Iterator for QueryMutIter<'q, 'a, Q, F> {
+    type Item = Q::Item<'q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.entities.len() {
+            let entity = self.entities[self.index];
+            self.index += 1;
+            if self.query.matches(entity) {
+                // SAFETY: entities are unique in a container and the iterator borrows the query mutably
+                return Some(unsafe { Q::item(&self.query.fetch, entity) });
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.entities.len() - self.index))
+    }
+}
+
+impl<'q, 'a, Q: QueryParam, F: QueryFilter> IntoIterator for &'q mut QueryMut<'a, Q, F> {
+    type Item = Q::Item<'q>;
+    type IntoIter = QueryMutIter<'q, 'a, Q, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{uid::UID, ecs::{entity::Entity, testing::{setup, sorted, Position, Velocity, POSITION, VELOCITY}}};
+
+    use super::{Changed, Added, With, Without, Or};
+
+    const SYSTEM: UID = UID::new("system");
+
+    #[test]
+    fn changed_filter() {
+        let (mut world, registry) = setup();
+        let entities = (0..4).map(|index| {
+            let entity = world.create();
+            world.add(&registry, entity, POSITION, Position(index)).unwrap();
+            entity
+        }).collect::<Vec<_>>();
+        world.add(&registry, entities[1], VELOCITY, Velocity(1)).unwrap();
+        // Everything added since the first run counts as changed
+        let changed = world.query_mut::<Entity, Changed<Position>>(&registry, world.last_run(SYSTEM)).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(sorted(changed), entities);
+        world.end_system(SYSTEM);
+        let last_run = world.last_run(SYSTEM);
+        assert_eq!(world.query_mut::<Entity, Changed<Position>>(&registry, last_run).unwrap().iter_mut().count(), 0);
+        // Reading doesn't mark the component, writing through get_mut or a mutable query does
+        world.get::<Position>(entities[0], POSITION).unwrap();
+        world.get_mut::<Position>(entities[2], POSITION).unwrap().unwrap().0 += 1;
+        for (_, position) in &mut world.query_mut::<(Entity, &mut Position), With<Velocity>>(&registry, last_run).unwrap() {
+            position.0 += 1;
+        }
+        let changed = world.query_mut::<Entity, Changed<Position>>(&registry, last_run).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(sorted(changed), vec![entities[1], entities[2]]);
+        let changed = world.query_mut::<Entity, (Changed<Position>, Without<Velocity>)>(&registry, last_run).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(changed, vec![entities[2]]);
+        world.end_system(SYSTEM);
+        assert_eq!(world.query_mut::<Entity, Changed<Position>>(&registry, world.last_run(SYSTEM)).unwrap().iter_mut().count(), 0);
+    }
+
+    #[test]
+    fn added_filter() {
+        let (mut world, registry) = setup();
+        let first = world.create();
+        world.add(&registry, first, POSITION, Position(0)).unwrap();
+        world.end_system(SYSTEM);
+        let last_run = world.last_run(SYSTEM);
+        assert_eq!(world.query_mut::<Entity, Added<Position>>(&registry, last_run).unwrap().iter_mut().count(), 0);
+        let second = world.create();
+        world.add(&registry, second, POSITION, Position(1)).unwrap();
+        world.add(&registry, first, VELOCITY, Velocity(1)).unwrap();
+        // Changing a component doesn't make it added
+        world.get_mut::<Position>(first, POSITION).unwrap().unwrap().0 += 1;
+        let added = world.query_mut::<Entity, Added<Position>>(&registry, last_run).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(added, vec![second]);
+        let added = world.query_mut::<Entity, (With<Position>, Or<(Added<Position>, Added<Velocity>)>)>(&registry, last_run).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(sorted(added), vec![first, second]);
+        // A component removed and added again is added
+        world.end_system(SYSTEM);
+        let last_run = world.last_run(SYSTEM);
+        world.remove(first, POSITION).unwrap();
+        world.add(&registry, first, POSITION, Position(2)).unwrap();
+        let added = world.query_mut::<Entity, Added<Position>>(&registry, last_run).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(added, vec![first]);
+    }
+
+    #[test]
+    fn without_filter() {
+        let (mut world, registry) = setup();
+        let moving = world.create();
+        world.add(&registry, moving, POSITION, Position(0)).unwrap();
+        world.add(&registry, moving, VELOCITY, Velocity(1)).unwrap();
+        let still = world.create();
+        world.add(&registry, still, POSITION, Position(1)).unwrap();
+        let err = world.query_mut::<Entity, Without<Velocity>>(&registry, 0).err().unwrap();
+        assert_eq!(err.to_string(), "Query must require at least one component");
+        let entities = world.query_mut::<Entity, (With<Position>, Without<Velocity>)>(&registry, 0).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(entities, vec![still]);
+        let positions = world.query_mut::<&Position, Without<Velocity>>(&registry, 0).unwrap().iter_mut().map(|position| position.0).collect::<Vec<_>>();
+        assert_eq!(positions, vec![1]);
+    }
+
+    #[test]
+    fn optional_params() {
+        let (mut world, registry) = setup();
+        let moving = world.create();
+        world.add(&registry, moving, POSITION, Position(0)).unwrap();
+        world.add(&registry, moving, VELOCITY, Velocity(2)).unwrap();
+        let still = world.create();
+        world.add(&registry, still, POSITION, Position(1)).unwrap();
+        let velocities = world.query_mut::<(Entity, Option<&Velocity>), With<Position>>(&registry, 0).unwrap().iter_mut()
+            .map(|(entity, velocity)| (entity, velocity.map(|velocity| velocity.0))).collect::<Vec<_>>();
+        assert_eq!(velocities, vec![(moving, Some(2)), (still, None)]);
+        world.end_system(SYSTEM);
+        let last_run = world.last_run(SYSTEM);
+        for (position, velocity) in &mut world.query_mut::<(&Position, Option<&mut Velocity>), ()>(&registry, last_run).unwrap() {
+            if let Some(velocity) = velocity {
+                velocity.0 += position.0 + 1;
+            }
+        }
+        assert_eq!(world.get::<Velocity>(moving, VELOCITY).unwrap().unwrap().0, 3);
+        // Only the optional components present are marked as changed
+        let changed = world.query_mut::<Entity, Changed<Velocity>>(&registry, last_run).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(changed, vec![moving]);
+        // Optional parameters don't restrict the query
+        let err = world.query_mut::<Option<&Velocity>, ()>(&registry, 0).err().unwrap();
+        assert_eq!(err.to_string(), "Query must require at least one component");
+        let err = world.query_mut::<(Entity, Option<&mut Velocity>), Or<(With<Position>, With<Velocity>)>>(&registry, 0).err().unwrap();
+        assert_eq!(err.to_string(), "Query must require at least one component");
+    }
+
+    #[test]
+    fn conflicting_params_are_errors() {
+        let (mut world, registry) = setup();
+        let entity = world.create();
+        world.add(&registry, entity, POSITION, Position(0)).unwrap();
+        let err = world.query_mut::<(&mut Position, &Position), ()>(&registry, 0).err().unwrap();
+        assert_eq!(err.to_string(), "Component 'position' is accessed mutably more than once in query");
+        assert!(world.query_mut::<(&Position, &mut Position), ()>(&registry, 0).is_err());
+        assert!(world.query_mut::<(&mut Position, &mut Position), ()>(&registry, 0).is_err());
+        assert!(world.query_mut::<(&Position, &Position), ()>(&registry, 0).is_ok());
+        // Queries also conflict with the borrows of views and components
+        let view = world.view_mut::<Position>(POSITION).unwrap();
+        assert!(world.query_mut::<&Position, ()>(&registry, 0).is_err());
+        drop(view);
+        let position = world.get::<Position>(entity, POSITION).unwrap().unwrap();
+        assert!(world.query_mut::<&mut Position, ()>(&registry, 0).is_err());
+        assert!(world.query_mut::<&Position, ()>(&registry, 0).is_ok());
+        drop(position);
+    }
+}
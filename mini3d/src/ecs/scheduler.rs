@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::{HashMap, BinaryHeap}, cmp::Reverse};
 use core::cell::RefCell;
 use anyhow::{anyhow, Context, Result};
 use serde::{Serialize, Deserialize};
 
-use crate::{uid::UID, feature::asset::system_group::SystemGroup, registry::RegistryManager};
+use crate::{uid::UID, feature::asset::system_group::SystemGroup, ecs::condition::RunCondition, registry::{RegistryManager, system::SystemRegistry}};
 
-use super::pipeline::SystemPipeline;
+use super::{pipeline::SystemPipeline, procedure::{FixedProcedure, Procedure}};
 
 pub enum Invocation {
     Immediate,
@@ -15,6 +15,7 @@ pub enum Invocation {
 
 #[derive(Serialize, Deserialize)]
 struct SystemGroupEntry {
+    name: String,
     group: SystemGroup,
     enabled: bool,
 }
@@ -38,20 +39,177 @@ impl ProcedureEntry {
 pub(crate) struct Scheduler {
     groups: HashMap<UID, SystemGroupEntry>,
     procedures: HashMap<UID, ProcedureEntry>,
+    /// Run each frame in this order, before the update procedure
+    #[serde(default = "default_fixed_procedures")]
+    fixed_procedures: Vec<FixedProcedure>,
+}
+
+/// States saved without fixed procedures run the fixed update at the default rate
+fn default_fixed_procedures() -> Vec<FixedProcedure> {
+    vec![FixedProcedure::new(Procedure::FIXED_UPDATE.into(), 1.0 / super::FIXED_UPDATE_RATE)]
+}
+
+/// Topological order of `count` nodes, keeping the original order of unconstrained nodes.
+/// Returns a cycle, in order and closed by its first node, when the edges can't be satisfied.
+fn stable_order(count: usize, edges: &[(usize, usize)]) -> Result<Vec<usize>, Vec<usize>> {
+    let mut incoming = vec![0; count];
+    let mut successors = vec![Vec::new(); count];
+    for (before, after) in edges {
+        successors[*before].push(*after);
+        incoming[*after] += 1;
+    }
+    let mut ready = (0..count).filter(|node| incoming[*node] == 0).map(Reverse).collect::<BinaryHeap<_>>();
+    let mut order = Vec::with_capacity(count);
+    while let Some(Reverse(node)) = ready.pop() {
+        order.push(node);
+        for next in &successors[node] {
+            incoming[*next] -= 1;
+            if incoming[*next] == 0 {
+                ready.push(Reverse(*next));
+            }
+        }
+    }
+    if order.len() == count {
+        return Ok(order);
+    }
+    // Remaining nodes always have a remaining predecessor, walking back from one of them must loop
+    let mut path = Vec::new();
+    let mut node = (0..count).find(|node| incoming[*node] > 0).unwrap();
+    while !path.contains(&node) {
+        path.push(node);
+        node = edges.iter().find(|(before, after)| *after == node && incoming[*before] > 0).unwrap().0;
+    }
+    let start = path.iter().position(|other| *other == node).unwrap();
+    let mut cycle = path.split_off(start);
+    cycle.reverse();
+    cycle.push(cycle[0]);
+    Err(cycle)
 }
 
 impl Scheduler {
 
+    /// Enabled groups of the procedure ordered by priority, then by their constraints
+    fn sort_groups(&self, procedure: &ProcedureEntry) -> Result<Vec<(UID, &SystemGroupEntry)>> {
+        let groups = procedure.groups.iter()
+            .map(|(uid, _)| (*uid, self.groups.get(uid).unwrap()))
+            .filter(|(_, group)| group.enabled)
+            .collect::<Vec<_>>();
+        let mut edges = Vec::new();
+        for (index, (_, group)) in groups.iter().enumerate() {
+            for (others, before) in [(&group.group.before, true), (&group.group.after, false)] {
+                for other in others {
+                    let uid: UID = other.into();
+                    if !self.groups.contains_key(&uid) {
+                        return Err(anyhow!("Group '{}' is ordered relative to unknown group '{}'", group.name, other));
+                    }
+                    // Disabled groups and groups outside of the procedure are not ordered
+                    if let Some(other) = groups.iter().position(|(group, _)| *group == uid) {
+                        edges.push(if before { (index, other) } else { (other, index) });
+                    }
+                }
+            }
+        }
+        let order = stable_order(groups.len(), &edges).map_err(|cycle| {
+            anyhow!("Cycle in the group order of procedure '{}': {}", procedure.name,
+                cycle.iter().map(|index| groups[*index].1.name.as_str()).collect::<Vec<_>>().join(" -> "))
+        })?;
+        Ok(order.into_iter().map(|index| groups[index]).collect())
+    }
+
+    /// Systems of the groups in order, moved across pipelines by ordering constraints, with their run conditions
+    fn sort_systems(procedure: UID, entry: &ProcedureEntry, groups: &[(UID, &SystemGroupEntry)], registry: &SystemRegistry) -> Result<Vec<(UID, Vec<RunCondition>)>> {
+        let pipelines = groups.iter()
+            .map(|(_, group)| (group.name.as_str(), &group.group.procedures.get(&procedure).unwrap().pipeline))
+            .collect::<Vec<_>>();
+        let systems = pipelines.iter()
+            .flat_map(|(_, pipeline)| pipeline.systems.iter().copied())
+            .collect::<Vec<_>>();
+        // Systems of a pipeline keep their relative order
+        let mut conditions = Vec::with_capacity(systems.len());
+        for (group, pipeline) in &pipelines {
+            for (system, _) in &pipeline.conditions {
+                if !pipeline.systems.contains(&system.into()) {
+                    return Err(anyhow!("Group '{}' has a run condition on system '{}' outside of its pipeline", group, system));
+                }
+            }
+            conditions.extend(pipeline.systems.iter().map(|uid| {
+                pipeline.conditions.iter()
+                    .filter(|(system, _)| UID::from(system) == *uid)
+                    .map(|(_, condition)| condition.clone())
+                    .collect::<Vec<_>>()
+            }));
+        }
+        let mut edges = Vec::new();
+        let mut start = 0;
+        for (_, pipeline) in &pipelines {
+            edges.extend((start + 1..start + pipeline.systems.len()).map(|index| (index - 1, index)));
+            start += pipeline.systems.len();
+        }
+        for (group, pipeline) in &pipelines {
+            for order in &pipeline.order {
+                for name in [&order.before, &order.after] {
+                    if registry.get(&name.into()).is_none() {
+                        return Err(anyhow!("Group '{}' orders unknown system '{}'", group, name));
+                    }
+                }
+                let (before, after): (UID, UID) = (order.before.as_str().into(), order.after.as_str().into());
+                // Constraints on systems outside of the procedure are ignored
+                for (i, _) in systems.iter().enumerate().filter(|(_, system)| **system == before) {
+                    for (j, _) in systems.iter().enumerate().filter(|(_, system)| **system == after) {
+                        edges.push((i, j));
+                    }
+                }
+            }
+        }
+        let order = stable_order(systems.len(), &edges).map_err(|cycle| {
+            anyhow!("Cycle in the system order of procedure '{}': {}", entry.name,
+                cycle.iter().map(|index| registry.get(&systems[*index]).map_or(systems[*index].to_string(), |system| system.name.clone()))
+                    .collect::<Vec<_>>().join(" -> "))
+        })?;
+        Ok(order.into_iter().map(|index| (systems[index], std::mem::take(&mut conditions[index]))).collect())
+    }
+
     pub(crate) fn build_pipeline(&self, procedure: UID, registry: &RefCell<RegistryManager>) -> Result<Option<SystemPipeline>> {
         if let Some(entry) = self.procedures.get(&procedure) {
-            return Ok(Some(SystemPipeline::build(&registry.borrow().systems, entry.groups.iter()
-                .map(|(group, _)| self.groups.get(group).unwrap())
-                .filter(|group| group.enabled)
-                .flat_map(move |group| group.group.procedures.get(&procedure).unwrap().pipeline.systems.iter()))?));
+            let registry = registry.borrow();
+            let groups = self.sort_groups(entry)?;
+            let systems = Self::sort_systems(procedure, entry, &groups, &registry.systems)?;
+            return Ok(Some(SystemPipeline::build(&registry.systems, systems.into_iter())?));
         }
         Ok(None)
     }
 
+    pub(crate) fn add_fixed_procedure(&mut self, name: &str, rate: f64) -> Result<UID> {
+        let uid: UID = name.into();
+        if self.fixed_procedures.iter().any(|fixed| fixed.procedure == uid) {
+            return Err(anyhow!("Fixed procedure '{}' already exists", name));
+        }
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(anyhow!("Invalid rate of fixed procedure '{}': {}", name, rate));
+        }
+        self.fixed_procedures.push(FixedProcedure::new(uid, 1.0 / rate));
+        Ok(uid)
+    }
+
+    pub(crate) fn remove_fixed_procedure(&mut self, procedure: UID) -> Result<()> {
+        let index = self.fixed_procedures.iter().position(|fixed| fixed.procedure == procedure).with_context(|| "Fixed procedure not found")?;
+        self.fixed_procedures.remove(index);
+        Ok(())
+    }
+
+    pub(crate) fn fixed_procedures_mut(&mut self) -> impl Iterator<Item = &mut FixedProcedure> {
+        self.fixed_procedures.iter_mut()
+    }
+
+    /// Delta time given to the procedure, the timestep of fixed procedures
+    pub(crate) fn fixed_timestep(&self, procedure: UID) -> Option<f64> {
+        self.fixed_procedures.iter().find(|fixed| fixed.procedure == procedure).map(|fixed| fixed.timestep)
+    }
+
+    pub(crate) fn procedure_name(&self, procedure: UID) -> Option<&str> {
+        self.procedures.get(&procedure).map(|entry| entry.name.as_str())
+    }
+
     pub(crate) fn add_group(&mut self, name: &str, group: SystemGroup) -> Result<UID> {
         let uid: UID = name.into();
         // Check existing group
@@ -65,7 +223,7 @@ impl Scheduler {
             procedures.groups.sort_by_key(|(_, priority)| *priority);
         }
         // Insert group
-        self.groups.insert(uid, SystemGroupEntry { group, enabled: true });
+        self.groups.insert(uid, SystemGroupEntry { name: name.to_string(), group, enabled: true });
         Ok(uid)
     }
 
@@ -88,4 +246,106 @@ impl Scheduler {
         self.groups.get_mut(&group).with_context(|| "Group not found")?.enabled = false;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use anyhow::Result;
+
+    use crate::{uid::UID, context::SystemContext, feature::asset::system_group::{SystemGroup, SystemPipeline}, registry::RegistryManager};
+
+    use super::{Scheduler, Procedure, stable_order};
+
+    const PROCEDURE: &str = "procedure";
+
+    fn system(_: &mut SystemContext) -> Result<()> {
+        Ok(())
+    }
+
+    fn registry(systems: &[&str]) -> RefCell<RegistryManager> {
+        let mut registry = RegistryManager::default();
+        for name in systems {
+            registry.systems.define_static(name, system).unwrap();
+        }
+        RefCell::new(registry)
+    }
+
+    fn group(pipeline: SystemPipeline) -> SystemGroup {
+        let mut group = SystemGroup::empty();
+        group.insert(PROCEDURE, pipeline, 0);
+        group
+    }
+
+    fn system_order(scheduler: &Scheduler, registry: &RefCell<RegistryManager>) -> Result<Vec<UID>> {
+        let entry = scheduler.procedures.get(&PROCEDURE.into()).unwrap();
+        let groups = scheduler.sort_groups(entry)?;
+        let systems = Scheduler::sort_systems(PROCEDURE.into(), entry, &groups, &registry.borrow().systems)?;
+        Ok(systems.into_iter().map(|(uid, _)| uid).collect())
+    }
+
+    #[test]
+    fn stable_order_keeps_unconstrained_nodes() {
+        assert_eq!(stable_order(4, &[]), Ok(vec![0, 1, 2, 3]));
+        assert_eq!(stable_order(4, &[(3, 1)]), Ok(vec![0, 2, 3, 1]));
+        assert_eq!(stable_order(3, &[(0, 1), (1, 2), (2, 0)]).unwrap_err(), vec![1, 2, 0, 1]);
+        assert_eq!(stable_order(4, &[(0, 1), (2, 3), (3, 2)]).unwrap_err(), vec![3, 2, 3]);
+    }
+
+    #[test]
+    fn systems_are_moved_across_groups() {
+        let registry = registry(&["a", "b", "c"]);
+        let mut scheduler = Scheduler::default();
+        scheduler.add_group("first", group(SystemPipeline::new(&["a".into(), "b".into()]))).unwrap();
+        scheduler.add_group("second", group(SystemPipeline::new(&["c".into()]).before("c", "b"))).unwrap();
+        assert_eq!(system_order(&scheduler, &registry).unwrap(), vec!["a".into(), "c".into(), "b".into()]);
+    }
+
+    #[test]
+    fn system_cycles_are_reported() {
+        let registry = registry(&["a", "b", "c"]);
+        let mut scheduler = Scheduler::default();
+        scheduler.add_group("first", group(SystemPipeline::new(&["a".into(), "b".into()]))).unwrap();
+        scheduler.add_group("second", group(SystemPipeline::new(&["c".into()]).after("c", "a").before("c", "a"))).unwrap();
+        let err = system_order(&scheduler, &registry).unwrap_err();
+        assert_eq!(err.to_string(), "Cycle in the system order of procedure 'procedure': c -> a -> c");
+        assert!(scheduler.build_pipeline(PROCEDURE.into(), &registry).is_err());
+    }
+
+    #[test]
+    fn group_cycles_are_reported() {
+        let registry = registry(&["a", "b"]);
+        let mut scheduler = Scheduler::default();
+        let mut first = group(SystemPipeline::single("a".into()));
+        first.before("second");
+        let mut second = group(SystemPipeline::single("b".into()));
+        second.before("first");
+        scheduler.add_group("first", first).unwrap();
+        let second = scheduler.add_group("second", second).unwrap();
+        let err = system_order(&scheduler, &registry).unwrap_err();
+        assert_eq!(err.to_string(), "Cycle in the group order of procedure 'procedure': second -> first -> second");
+        // Disabled groups are not ordered
+        scheduler.disable_group(second).unwrap();
+        assert_eq!(system_order(&scheduler, &registry).unwrap(), vec!["a".into()]);
+    }
+
+    #[test]
+    fn unknown_references_are_reported() {
+        let registry = registry(&["a"]);
+        let mut scheduler = Scheduler::default();
+        scheduler.add_group("first", group(SystemPipeline::single("a".into()).before("a", "missing"))).unwrap();
+        assert!(system_order(&scheduler, &registry).unwrap_err().to_string().contains("unknown system 'missing'"));
+        let mut scheduler = Scheduler::default();
+        let mut first = group(SystemPipeline::single("a".into()));
+        first.after("missing");
+        scheduler.add_group("first", first).unwrap();
+        assert!(system_order(&scheduler, &registry).unwrap_err().to_string().contains("unknown group 'missing'"));
+    }
+
+    #[test]
+    fn states_without_fixed_procedures_load_the_fixed_update() {
+        let scheduler: Scheduler = serde_json::from_str(r#"{ "groups": {}, "procedures": {} }"#).unwrap();
+        assert_eq!(scheduler.fixed_timestep(Procedure::FIXED_UPDATE.into()), Some(1.0 / 60.0));
+    }
+}
@@ -1,28 +1,32 @@
 use std::any::Any;
-use std::cell::{RefCell, Ref, RefMut};
+use std::cell::{RefCell, RefMut};
 use std::ops::{Deref, DerefMut};
-use super::component::Component;
+use super::component::{Component, SharedRef};
 
 pub(crate) struct Singleton<C: Component> {
     pub(crate) component: RefCell<C>,
+    /// Serialized component inspected by run conditions, reset by mutable accesses
+    value: RefCell<Option<serde_json::Value>>,
 }
 
 impl<C: Component> Singleton<C> {
     pub(crate) fn new(component: C) -> Self {
-        Self { component: RefCell::new(component) }
+        Self { component: RefCell::new(component), value: RefCell::new(None) }
     }
 }
 
 pub(crate) trait AnySingleton {
     fn as_any(&self) -> &dyn Any;
+    fn value(&self) -> &RefCell<Option<serde_json::Value>>;
 }
 
 impl<C: Component> AnySingleton for Singleton<C> {
     fn as_any(&self) -> &dyn Any { self }
+    fn value(&self) -> &RefCell<Option<serde_json::Value>> { &self.value }
 }
 
 pub struct SingletonRef<'a, C: Component> {
-    pub(crate) component: Ref<'a, C>,
+    pub(crate) component: SharedRef<'a, C>,
 }
 
 impl<C: Component> Deref for SingletonRef<'_, C> {
@@ -1,5 +1,50 @@
 use anyhow::Result;
 
-use crate::context::SystemContext;
+use crate::{context::{SystemContext, ParallelSystemContext}, uid::UID};
 
 pub type SystemCallback = fn(&mut SystemContext) -> Result<()>;
+pub type ParallelSystemCallback = fn(&mut ParallelSystemContext) -> Result<()>;
+
+/// Components and singletons a parallel system reads and writes, identified by
+/// their UID. Systems of a pipeline whose accesses don't conflict run side by side.
+#[derive(Default, Clone)]
+pub struct SystemAccess {
+    reads: Vec<UID>,
+    writes: Vec<UID>,
+}
+
+impl SystemAccess {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(mut self, component: UID) -> Self {
+        if !self.reads.contains(&component) {
+            self.reads.push(component);
+        }
+        self
+    }
+
+    /// Writing a component also allows reading it
+    pub fn write(mut self, component: UID) -> Self {
+        if !self.writes.contains(&component) {
+            self.writes.push(component);
+        }
+        self
+    }
+
+    pub fn can_read(&self, component: UID) -> bool {
+        self.reads.contains(&component) || self.writes.contains(&component)
+    }
+
+    pub fn can_write(&self, component: UID) -> bool {
+        self.writes.contains(&component)
+    }
+
+    /// True if one of the systems writes a component the other one accesses
+    pub fn conflicts(&self, other: &SystemAccess) -> bool {
+        self.writes.iter().any(|component| other.can_read(*component))
+            || other.writes.iter().any(|component| self.can_read(*component))
+    }
+}
@@ -0,0 +1,71 @@
+use std::{rc::Rc, cell::Cell};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{uid::UID, registry::component::ComponentRegistry};
+
+use super::{world::World, entity::Entity, component::{Component, ComponentHooks}};
+
+pub(crate) const HEALTH: UID = UID::new("health");
+pub(crate) const POSITION: UID = UID::new("position");
+pub(crate) const VELOCITY: UID = UID::new("velocity");
+pub(crate) const LINK: UID = UID::new("link");
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Health(pub(crate) u32);
+
+impl Component for Health {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Position(pub(crate) i32);
+
+impl Component for Position {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Velocity(pub(crate) i32);
+
+impl Component for Velocity {}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Link {
+    pub(crate) target: Entity,
+}
+
+impl Component for Link {}
+
+/// Sum of the health values seen by the hooks
+#[derive(Default, Clone)]
+pub(crate) struct HookLog {
+    pub(crate) added: Rc<Cell<u32>>,
+    pub(crate) removed: Rc<Cell<u32>>,
+}
+
+impl HookLog {
+
+    pub(crate) fn hooks(&self) -> ComponentHooks<Health> {
+        let (added, removed) = (self.added.clone(), self.removed.clone());
+        ComponentHooks {
+            on_add: Some(Rc::new(move |_, health: &mut Health| added.set(added.get() + health.0))),
+            on_remove: Some(Rc::new(move |_, health: &Health| removed.set(removed.get() + health.0))),
+        }
+    }
+}
+
+/// Empty world with the test components defined
+pub(crate) fn setup() -> (World, ComponentRegistry) {
+    setup_with_hooks(ComponentHooks::default())
+}
+
+pub(crate) fn setup_with_hooks(hooks: ComponentHooks<Health>) -> (World, ComponentRegistry) {
+    let mut registry = ComponentRegistry::default();
+    registry.define_static::<Health>("health", hooks).unwrap();
+    registry.define_static::<Position>("position", ComponentHooks::default()).unwrap();
+    registry.define_static::<Velocity>("velocity", ComponentHooks::default()).unwrap();
+    registry.define_static::<Link>("link", ComponentHooks::default()).unwrap();
+    (World::new("test"), registry)
+}
+
+pub(crate) fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+    entities.sort_by_key(|entity| entity.key());
+    entities
+}
@@ -1,13 +1,15 @@
-use std::{ops::{Index, IndexMut}, cell::{Ref, RefMut}};
+use std::{ops::{Index, IndexMut}, cell::{RefMut, Cell}};
 
-use super::{entity::Entity, container::ComponentContainer, sparse::PagedVector, component::Component};
+use anyhow::{Result, anyhow};
+
+use super::{entity::Entity, container::ComponentContainer, sparse::PagedVector, component::{Component, SharedRef}};
 
 pub trait ComponentView<C: Component> {
     fn get(&self, entity: Entity) -> Option<&C>;
 }
 
 struct ComponentViewRefData<'a, C: Component> {
-    components: Ref<'a, Vec<C>>,
+    components: SharedRef<'a, Vec<C>>,
     entities: &'a [Entity],
     indices: &'a PagedVector<usize>,
 }
@@ -18,10 +20,22 @@ pub struct ComponentViewRef<'a, C: Component> {
 
 impl<'a, C: Component> ComponentViewRef<'a, C> {
 
-    pub(crate) fn new(container: &'a ComponentContainer<C>) -> Self {
+    pub(crate) fn new(container: &'a ComponentContainer<C>) -> Result<Self> {
+        Ok(Self {
+            view: Some(ComponentViewRefData {
+                components: SharedRef::Tracked(container.components.try_borrow().map_err(|_| anyhow!("Component already borrowed mutably"))?),
+                entities: &container.entities,
+                indices: &container.indices,
+            })
+        })
+    }
+
+    /// # Safety
+    /// The components must not be borrowed mutably while the view is alive.
+    pub(crate) unsafe fn scheduled(container: &'a ComponentContainer<C>) -> Self {
         Self {
             view: Some(ComponentViewRefData {
-                components: container.components.borrow(),
+                components: SharedRef::scheduled(&container.components),
                 entities: &container.entities,
                 indices: &container.indices,
             })
@@ -45,7 +59,7 @@ impl<'a, C: Component> ComponentView<C> for ComponentViewRef<'a, C> {
     fn get(&self, entity: Entity) -> Option<&C> {
         self.view.as_ref().and_then(|data| {
             data.indices.get(entity.key()).copied().and_then(|index| {
-                if data.entities[index] == entity {
+                if data.entities.get(index) == Some(&entity) {
                     Some(&data.components[index])
                 } else {
                     None
@@ -67,6 +81,8 @@ struct ComponentViewMutData<'a, C: Component> {
     components: RefMut<'a, Vec<C>>,
     entities: &'a [Entity],
     indices: &'a PagedVector<usize>,
+    changed: &'a [Cell<u64>],
+    tick: u64,
 }
 
 pub struct ComponentViewMut<'a, C: Component> {
@@ -75,32 +91,38 @@ pub struct ComponentViewMut<'a, C: Component> {
 
 impl<'a, C: Component> ComponentViewMut<'a, C> {
 
-    pub(crate) fn new(container: &'a ComponentContainer<C>) -> Self {
-        Self {
+    pub(crate) fn new(container: &'a ComponentContainer<C>, tick: u64) -> Result<Self> {
+        Ok(Self {
             view: Some(ComponentViewMutData {
-                components: container.components.borrow_mut(),
+                components: container.components.try_borrow_mut().map_err(|_| anyhow!("Component already borrowed"))?,
                 entities: &container.entities,
                 indices: &container.indices,
+                changed: &container.changed,
+                tick,
             })
-        }
+        })
     }
 
     pub(crate) fn none() -> Self {
         Self { view: None }
     }
 
+    /// Mark every component as changed
     pub fn iter(&mut self) -> impl Iterator<Item = &mut C> {
         if let Some(data) = &mut self.view {
+            data.changed.iter().for_each(|changed| changed.set(data.tick));
             data.components.iter_mut()
         } else {
             [].iter_mut()
         }
     }
 
+    /// Mark the component as changed
     pub fn get_mut(&mut self, entity: Entity) -> Option<&mut C> {
         self.view.as_mut().and_then(|data| {
             data.indices.get(entity.key()).and_then(|index| {
-                if data.entities[*index] == entity {
+                if data.entities.get(*index) == Some(&entity) {
+                    data.changed[*index].set(data.tick);
                     Some(&mut data.components[*index])
                 } else {
                     None
@@ -114,7 +136,7 @@ impl<'a, C: Component> ComponentView<C> for ComponentViewMut<'a, C> {
     fn get(&self, entity: Entity) -> Option<&C> {
         self.view.as_ref().and_then(|data| {
             data.indices.get(entity.key()).and_then(|index| {
-                if data.entities[*index] == entity {
+                if data.entities.get(*index) == Some(&entity) {
                     Some(&data.components[*index])
                 } else {
                     None
@@ -1,18 +1,34 @@
-use std::{collections::{HashMap, hash_map}};
+use std::{collections::{HashMap, HashSet, hash_map}, cell::{Cell, Ref, RefCell}};
 
 use anyhow::{Context, Result, anyhow};
 use serde::{Deserializer, Serializer, Serialize, de::{Visitor, DeserializeSeed}};
 
-use crate::{uid::UID, registry::component::{ComponentRegistry, AnyComponentDefinitionReflection}};
+use crate::{uid::UID, registry::component::{ComponentRegistry, AnyComponentDefinitionReflection, ComponentKind, DynamicComponentDefinition}, feature::asset::runtime_component::FieldValue};
 
-use super::{entity::Entity, container::{AnyComponentContainer, ComponentContainer}, view::{ComponentViewRef, ComponentViewMut}, query::Query, component::{Component, ComponentRef, ComponentMut}, singleton::{AnySingleton, Singleton, SingletonRef, SingletonMut}};
+use super::{entity::{Entity, EntityVersion}, sparse::PagedVector, container::{AnyComponentContainer, ComponentContainer}, view::{ComponentViewRef, ComponentViewMut}, query::{Query, QueryMut, QueryParam, QueryFilter}, component::{Component, ComponentRef, ComponentMut, SharedRef}, singleton::{AnySingleton, Singleton, SingletonRef, SingletonMut}, command::WorldCommand};
+
+/// Current version of an entity key, the key is free once its entity is destroyed
+#[derive(Default, Clone, Copy)]
+struct EntityState {
+    version: EntityVersion,
+    free: bool,
+}
 
 pub(crate) struct World {
     pub(crate) name: String,
     containers: HashMap<UID, Box<dyn AnyComponentContainer>>,
     singletons: HashMap<UID, Box<dyn AnySingleton>>,
     free_entities: Vec<Entity>,
-    next_entity: Entity,
+    next_entity: Cell<Entity>,
+    /// Keys below next_entity without state are alive with version 0
+    entity_states: PagedVector<EntityState>,
+    /// Optional unique names of entities
+    names: HashMap<String, Entity>,
+    entity_names: HashMap<Entity, String>,
+    /// Incremented after each system run, used for change detection. 64 bits never wrap around.
+    pub(crate) change_tick: u64,
+    system_ticks: HashMap<UID, u64>,
+    pub(crate) commands: RefCell<Vec<WorldCommand>>,
 }
 
 impl World {
@@ -51,12 +67,18 @@ impl World {
             }
         }
         use serde::ser::SerializeTuple;
-        let mut tuple = serializer.serialize_tuple(5)?;
+        // Only alive entities of reused keys have a version to keep, free ones are in free_entities
+        let recycled_entities = (1..self.next_entity.get().key())
+            .filter_map(|key| self.entity_states.get(key).filter(|state| !state.free && state.version > 0).map(|state| Entity::new(key, state.version)))
+            .collect::<Vec<_>>();
+        let mut tuple = serializer.serialize_tuple(7)?;
         tuple.serialize_element(&self.name)?;
         tuple.serialize_element(&ContainersSerializer { containers: &self.containers, registry })?;
         tuple.serialize_element(&SingletonsSerializer { singletons: &self.singletons, registry })?;
         tuple.serialize_element(&self.free_entities)?;
-        tuple.serialize_element(&self.next_entity)?;
+        tuple.serialize_element(&self.next_entity.get())?;
+        tuple.serialize_element(&self.names)?;
+        tuple.serialize_element(&recycled_entities)?;
         tuple.end()
     }
 
@@ -67,7 +89,7 @@ impl World {
         impl<'a, 'de> Visitor<'de> for WorldVisitor<'a> {
             type Value = World;
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a tuple of (containers, free_entities)")
+                formatter.write_str("a tuple of (name, containers, singletons, free_entities, next_entity, names, recycled_entities)")
             }
             fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
                 use serde::de::Error;
@@ -149,12 +171,29 @@ impl World {
                 let name: String = seq.next_element()?.with_context(|| "Missing name").map_err(Error::custom)?;
                 let containers = seq.next_element_seed(ContainersDeserializeSeed { registry: self.registry })?.with_context(|| "Missing containers").map_err(Error::custom)?;
                 let singletons = seq.next_element_seed(SingletonsDeserializeSeed { registry: self.registry })?.with_context(|| "Missing singletons").map_err(Error::custom)?;
-                let free_entities = seq.next_element()?.with_context(|| "Missing free_entities").map_err(Error::custom)?;
+                let free_entities: Vec<Entity> = seq.next_element()?.with_context(|| "Missing free_entities").map_err(Error::custom)?;
                 let next_entity = seq.next_element()?.with_context(|| "Missing next_entity").map_err(Error::custom)?;
-                Ok(World { name, containers, singletons, free_entities, next_entity })
+                let names: HashMap<String, Entity> = seq.next_element()?.with_context(|| "Missing names").map_err(Error::custom)?;
+                let mut entity_names = HashMap::new();
+                for (name, entity) in &names {
+                    if entity_names.insert(*entity, name.clone()).is_some() {
+                        return Err(Error::custom(format!("Entity {:?} has more than one name", entity)));
+                    }
+                }
+                let recycled_entities: Vec<Entity> = seq.next_element()?.with_context(|| "Missing recycled_entities").map_err(Error::custom)?;
+                let mut entity_states = PagedVector::new();
+                for entity in &free_entities {
+                    entity_states.set(entity.key(), EntityState { version: entity.version(), free: true });
+                }
+                for entity in &recycled_entities {
+                    entity_states.set(entity.key(), EntityState { version: entity.version(), free: false });
+                }
+                let world = World { name, containers, singletons, free_entities, next_entity: Cell::new(next_entity), entity_states, names, entity_names, change_tick: 1, system_ticks: HashMap::new(), commands: RefCell::new(Vec::new()) };
+                world.validate().map_err(|err| Error::custom(format!("Invalid world '{}': {}", world.name, err)))?;
+                Ok(world)
             }
         }
-        deserializer.deserialize_tuple(5, WorldVisitor { registry })
+        deserializer.deserialize_tuple(7, WorldVisitor { registry })
     }
 
     pub(crate) fn new(name: &str) -> World {
@@ -163,62 +202,291 @@ impl World {
             containers: HashMap::new(),
             singletons: HashMap::new(),
             free_entities: Vec::new(),
-            next_entity: Entity::new(1, 0),
+            next_entity: Cell::new(Entity::new(1, 0)),
+            entity_states: PagedVector::new(),
+            names: HashMap::new(),
+            entity_names: HashMap::new(),
+            change_tick: 1,
+            system_ticks: HashMap::new(),
+            commands: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Tick at which the system last ran on this world, 0 if it never did
+    pub(crate) fn last_run(&self, system: UID) -> u64 {
+        self.system_ticks.get(&system).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn end_system(&mut self, system: UID) {
+        self.system_ticks.insert(system, self.change_tick);
+        self.change_tick += 1;
+    }
+
+    fn container_entry(&mut self, registry: &ComponentRegistry, component: UID) -> Result<&mut Box<dyn AnyComponentContainer>> {
+        if let hash_map::Entry::Vacant(e) = self.containers.entry(component) {
+            let container = registry
+                .get(component).with_context(|| "Component not registered")?
+                .reflection.create_container();
+            e.insert(container);
         }
+        Ok(self.containers.get_mut(&component).unwrap())
     }
 
     pub(crate) fn create(&mut self) -> Entity {
         if let Some(entity) = self.free_entities.pop() {
+            self.entity_states.set(entity.key(), EntityState { version: entity.version(), free: false });
             return entity;
         }
-        let entity = self.next_entity;
-        self.next_entity = Entity::new(entity.key() + 1, 0);
+        self.reserve()
+    }
+
+    /// Allocate a new entity without exclusive access, free entities are only reused by create
+    pub(crate) fn reserve(&self) -> Entity {
+        let entity = self.next_entity.get();
+        self.next_entity.set(Entity::new(entity.key() + 1, 0));
         entity
     }
 
+    pub(crate) fn apply_commands(&mut self, registry: &ComponentRegistry) -> Result<()> {
+        let component_name = |component: UID| registry.get(component).map_or("unknown", |definition| definition.name.as_str()).to_owned();
+        let commands = self.commands.take();
+        let mut errors = Vec::new();
+        for command in commands {
+            let result = match command {
+                WorldCommand::Destroy(entity) => self.destroy(entity)
+                    .with_context(|| format!("Failed to destroy entity {} (version {})", entity.key(), entity.version())),
+                WorldCommand::Add(entity, component, add) => add(self, registry)
+                    .with_context(|| format!("Failed to add component '{}' to entity {} (version {})", component_name(component), entity.key(), entity.version())),
+                WorldCommand::Remove(entity, component) => self.remove(entity, component)
+                    .with_context(|| format!("Failed to remove component '{}' from entity {} (version {})", component_name(component), entity.key(), entity.version())),
+            };
+            if let Err(err) = result {
+                errors.push(format!("{:#}", err));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("{} world command(s) failed: {}", errors.len(), errors.join("; ")))
+        }
+    }
+
+    pub(crate) fn is_alive(&self, entity: Entity) -> bool {
+        if entity.key() == 0 || entity.key() >= self.next_entity.get().key() {
+            return false;
+        }
+        let state = self.entity_states.get(entity.key()).copied().unwrap_or_default();
+        !state.free && state.version == entity.version()
+    }
+
+    fn check_alive(&self, entity: Entity) -> Result<()> {
+        if self.is_alive(entity) {
+            return Ok(());
+        }
+        if entity.key() == 0 || entity.key() >= self.next_entity.get().key() {
+            return Err(anyhow!("Entity {} (version {}) does not exist", entity.key(), entity.version()));
+        }
+        let state = self.entity_states.get(entity.key()).copied().unwrap_or_default();
+        if state.free {
+            Err(anyhow!("Stale entity {} (version {}), it was destroyed", entity.key(), entity.version()))
+        } else {
+            Err(anyhow!("Stale entity {} (version {}), it was destroyed and its key reused by version {}", entity.key(), entity.version(), state.version))
+        }
+    }
+
     pub(crate) fn destroy(&mut self, entity: Entity) -> Result<()> {
+        self.check_alive(entity)?;
         for container in self.containers.values_mut() {
             container.remove(entity);
         }
-        self.free_entities.push(Entity::new(entity.key(), entity.version() + 1));
+        self.remove_name(entity);
+        let next = Entity::new(entity.key(), entity.version() + 1);
+        self.entity_states.set(entity.key(), EntityState { version: next.version(), free: true });
+        self.free_entities.push(next);
+        Ok(())
+    }
+
+    /// Remove every component, running their remove hooks. Called before the world is dropped.
+    pub(crate) fn clear(&mut self) {
+        for container in self.containers.values_mut() {
+            while container.len() > 0 {
+                let entity = container.entity(container.len() - 1);
+                container.remove(entity);
+            }
+        }
+    }
+
+    /// Name the entity, replacing its previous name. Names are unique in the world.
+    pub(crate) fn set_name(&mut self, entity: Entity, name: &str) -> Result<()> {
+        self.check_alive(entity)?;
+        if let Some(other) = self.names.get(name) {
+            if *other == entity {
+                return Ok(());
+            }
+            return Err(anyhow!("Entity name '{}' already used by {:?}", name, other));
+        }
+        self.remove_name(entity);
+        self.names.insert(name.to_string(), entity);
+        self.entity_names.insert(entity, name.to_string());
         Ok(())
     }
 
+    pub(crate) fn remove_name(&mut self, entity: Entity) {
+        if let Some(name) = self.entity_names.remove(&entity) {
+            self.names.remove(&name);
+        }
+    }
+
+    pub(crate) fn find(&self, name: &str) -> Option<Entity> {
+        self.names.get(name).copied()
+    }
+
+    pub(crate) fn name(&self, entity: Entity) -> Option<&str> {
+        self.entity_names.get(&entity).map(|name| name.as_str())
+    }
+
     pub(crate) fn add<C: Component>(&mut self, registry: &ComponentRegistry, entity: Entity, component: UID, data: C) -> Result<()> {
-        if let hash_map::Entry::Vacant(e) = self.containers.entry(component) {
-            let container = registry
-                .get(component).with_context(|| "Component not registered")?
-                .reflection.create_container();
-            e.insert(container);
+        self.check_alive(entity)?;
+        let tick = self.change_tick;
+        let container = self.container_entry(registry, component)?;
+        if container.contains(entity) {
+            return Err(anyhow!("Component already added"));
         }
-        let container = self.containers.get_mut(&component).unwrap();
         container.as_any_mut()
             .downcast_mut::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?
             .add(entity, data)?;
+        container.mark_added(entity, tick);
         Ok(())
     }
     
     pub(crate) fn remove(&mut self, entity: Entity, component: UID) -> Result<()> {
+        self.check_alive(entity)?;
         let container = self.containers.get_mut(&component).with_context(|| "Component container not found")?;
         container.remove(entity);
         Ok(())
     }
 
+    /// Entities whose component was removed and not drained yet
+    pub(crate) fn removed(&self, component: UID) -> Vec<Entity> {
+        self.containers.get(&component).map_or(Vec::new(), |container| container.removed())
+    }
+
+    pub(crate) fn drain_removed<C: Component>(&mut self, component: UID) -> Result<Vec<(Entity, C)>> {
+        if let Some(container) = self.containers.get_mut(&component) {
+            Ok(container.as_any_mut()
+                .downcast_mut::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?
+                .drain_removed())
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    pub(crate) fn clear_removed(&mut self) {
+        for container in self.containers.values_mut() {
+            container.clear_removed();
+        }
+    }
+
+    pub(crate) fn contains(&self, entity: Entity, component: UID) -> Result<bool> {
+        self.check_alive(entity)?;
+        Ok(self.containers.get(&component).is_some_and(|container| container.contains(entity)))
+    }
+
+    pub(crate) fn serialize_component<'a>(&'a self, registry: &'a ComponentRegistry, entity: Entity, component: UID) -> Result<Option<Box<dyn erased_serde::Serialize + 'a>>> {
+        self.check_alive(entity)?;
+        if let Some(container) = self.containers.get(&component) {
+            let reflection = &registry.get(component).with_context(|| "Component not registered")?.reflection;
+            reflection.serialize_component(container.as_ref(), entity)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn deserialize_component(&mut self, registry: &ComponentRegistry, entity: Entity, component: UID, deserializer: &mut dyn erased_serde::Deserializer) -> Result<()> {
+        self.check_alive(entity)?;
+        let tick = self.change_tick;
+        let container = self.container_entry(registry, component)?;
+        let added = !container.contains(entity);
+        registry.get(component).unwrap().reflection.deserialize_component(container.as_mut(), entity, deserializer)?;
+        if added {
+            container.mark_added(entity, tick);
+        } else {
+            container.mark_changed(entity, tick);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn add_from_json(&mut self, registry: &ComponentRegistry, entity: Entity, component: UID, value: serde_json::Value) -> Result<()> {
+        self.check_alive(entity)?;
+        let tick = self.change_tick;
+        let container = self.container_entry(registry, component)?;
+        registry.get(component).unwrap().reflection.add_from_json(container.as_mut(), entity, value)?;
+        container.mark_added(entity, tick);
+        Ok(())
+    }
+
+    fn dynamic_definition(registry: &ComponentRegistry, component: UID) -> Result<&DynamicComponentDefinition> {
+        match &registry.get(component).with_context(|| "Component not registered")?.kind {
+            ComponentKind::Dynamic(definition) => Ok(definition),
+            ComponentKind::Static => Err(anyhow!("Component is not dynamic")),
+        }
+    }
+
+    pub(crate) fn add_dynamic(&mut self, registry: &ComponentRegistry, entity: Entity, component: UID) -> Result<()> {
+        self.check_alive(entity)?;
+        let definition = Self::dynamic_definition(registry, component)?;
+        let tick = self.change_tick;
+        let container = self.container_entry(registry, component)?;
+        if container.contains(entity) {
+            return Err(anyhow!("Component already added"));
+        }
+        registry.get(component).unwrap().reflection.write_fields(container.as_mut(), entity, definition.default_values())?;
+        container.mark_added(entity, tick);
+        Ok(())
+    }
+
+    pub(crate) fn get_field(&self, registry: &ComponentRegistry, entity: Entity, component: UID, field: &str) -> Result<Option<FieldValue>> {
+        self.check_alive(entity)?;
+        let (index, _) = Self::dynamic_definition(registry, component)?.field(field)?;
+        if let Some(container) = self.containers.get(&component) {
+            let reflection = &registry.get(component).unwrap().reflection;
+            Ok(reflection.read_fields(container.as_ref(), entity)?.map(|mut fields| fields.swap_remove(index)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn set_field(&mut self, registry: &ComponentRegistry, entity: Entity, component: UID, field: &str, value: FieldValue) -> Result<()> {
+        self.check_alive(entity)?;
+        let (index, _) = Self::dynamic_definition(registry, component)?.field(field)?;
+        let container = self.containers.get_mut(&component).with_context(|| "Component not found")?;
+        let reflection = &registry.get(component).unwrap().reflection;
+        let mut fields = reflection.read_fields(container.as_ref(), entity)?.with_context(|| "Component not found")?;
+        fields[index] = value;
+        reflection.write_fields(container.as_mut(), entity, fields)?;
+        container.mark_changed(entity, self.change_tick);
+        Ok(())
+    }
+
     pub(crate) fn get<C: Component>(&self, entity: Entity, component: UID) -> Result<Option<ComponentRef<'_, C>>> {
+        self.check_alive(entity)?;
         if let Some(container) = self.containers.get(&component) {
             Ok(container.as_any()
                 .downcast_ref::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?
-                .get(entity))
+                .get(entity)?)
         } else {
             Ok(None)
         }
     }
 
     pub(crate) fn get_mut<C: Component>(&self, entity: Entity, component: UID) -> Result<Option<ComponentMut<'_, C>>> {
+        self.check_alive(entity)?;
         if let Some(container) = self.containers.get(&component) {
-            Ok(container.as_any()
+            let data = container.as_any()
                 .downcast_ref::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?
-                .get_mut(entity))
+                .get_mut(entity)?;
+            container.mark_changed(entity, self.change_tick);
+            Ok(data)
         } else {
             Ok(None)
         }
@@ -228,17 +496,35 @@ impl World {
         if let Some(container) = self.containers.get(&component) {
             let container = container.as_any()
                 .downcast_ref::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?;
-            Ok(ComponentViewRef::new(container))
+            ComponentViewRef::new(container)
         } else {
             Ok(ComponentViewRef::none())
         } 
     }
 
+    /// Read-only view which doesn't track its borrow, used by parallel systems
+    /// # Safety
+    /// The component must not be borrowed mutably while the view is alive.
+    pub(crate) unsafe fn view_scheduled<C: Component>(&self, component: UID) -> Result<ComponentViewRef<'_, C>> {
+        if let Some(container) = self.containers.get(&component) {
+            let container = container.as_any()
+                .downcast_ref::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?;
+            Ok(ComponentViewRef::scheduled(container))
+        } else {
+            Ok(ComponentViewRef::none())
+        }
+    }
+
     pub(crate) fn view_mut<C: Component>(&self, component: UID) -> Result<ComponentViewMut<'_, C>> {
+        self.view_mut_at(component, self.change_tick)
+    }
+
+    /// Mutable view marking changes with the given tick instead of the current one
+    pub(crate) fn view_mut_at<C: Component>(&self, component: UID, tick: u64) -> Result<ComponentViewMut<'_, C>> {
         if let Some(container) = self.containers.get(&component) {
             let container = container.as_any()
                 .downcast_ref::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?;
-            Ok(ComponentViewMut::new(container))
+            ComponentViewMut::new(container, tick)
         } else {
             Ok(ComponentViewMut::none())
         }
@@ -258,6 +544,14 @@ impl World {
         Query::new(containers)
     }
 
+    pub(crate) fn query_mut<'a, Q: QueryParam, F: QueryFilter>(&'a self, registry: &'a ComponentRegistry, last_run: u64) -> Result<QueryMut<'a, Q, F>> {
+        QueryMut::new(self, registry, last_run)
+    }
+
+    pub(crate) fn container(&self, component: UID) -> Option<&dyn AnyComponentContainer> {
+        self.containers.get(&component).map(|container| container.as_ref())
+    }
+
     pub(crate) fn add_singleton<C: Component>(&mut self, component: UID, data: C) -> Result<()> {
         if self.singletons.contains_key(&component) {
             return Err(anyhow!("Singleton already exists"));
@@ -274,9 +568,38 @@ impl World {
     pub(crate) fn get_singleton<C: Component>(&self, component: UID) -> Result<Option<SingletonRef<'_, C>>> {
         if let Some(singleton) = self.singletons.get(&component) {
             Ok(Some(SingletonRef {
-                component: singleton.as_any()
+                component: SharedRef::Tracked(singleton.as_any()
+                    .downcast_ref::<Singleton<C>>().with_context(|| "Singleton type mismatch")?
+                    .component.borrow())
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Singleton serialized to JSON, used to inspect its fields without knowing its type.
+    /// The value is serialized again only after the singleton was accessed mutably.
+    pub(crate) fn singleton_value(&self, registry: &ComponentRegistry, component: UID) -> Result<Option<Ref<'_, serde_json::Value>>> {
+        if let Some(singleton) = self.singletons.get(&component) {
+            if singleton.value().borrow().is_none() {
+                let definition = registry.get(component).with_context(|| "Component definition not found")?;
+                let value = serde_json::to_value(definition.reflection.serialize_singleton(singleton.as_ref()))?;
+                *singleton.value().borrow_mut() = Some(value);
+            }
+            Ok(Some(Ref::map(singleton.value().borrow(), |value| value.as_ref().unwrap())))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// # Safety
+    /// The singleton must not be borrowed mutably while the reference is alive.
+    pub(crate) unsafe fn get_singleton_scheduled<C: Component>(&self, component: UID) -> Result<Option<SingletonRef<'_, C>>> {
+        if let Some(singleton) = self.singletons.get(&component) {
+            Ok(Some(SingletonRef {
+                component: SharedRef::scheduled(&singleton.as_any()
                     .downcast_ref::<Singleton<C>>().with_context(|| "Singleton type mismatch")?
-                    .component.borrow()
+                    .component)
             }))
         } else {
             Ok(None)
@@ -285,6 +608,7 @@ impl World {
 
     pub(crate) fn get_singleton_mut<C: Component>(&self, component: UID) -> Result<Option<SingletonMut<'_, C>>> {
         if let Some(singleton) = self.singletons.get(&component) {
+            singleton.value().take();
             Ok(Some(SingletonMut {
                 component: singleton.as_any()
                     .downcast_ref::<Singleton<C>>().with_context(|| "Singleton type mismatch")?
@@ -294,4 +618,212 @@ impl World {
             Ok(None)
         }
     }
-}
\ No newline at end of file
+
+    /// Check the consistency of entities, containers and names
+    pub(crate) fn validate(&self) -> Result<()> {
+        let mut free_keys = HashSet::new();
+        for entity in &self.free_entities {
+            if !free_keys.insert(entity.key()) {
+                return Err(anyhow!("Entity key {} is free more than once", entity.key()));
+            }
+            let state = self.entity_states.get(entity.key()).copied().unwrap_or_default();
+            if entity.key() == 0 || entity.key() >= self.next_entity.get().key() || !state.free || state.version != entity.version() {
+                return Err(anyhow!("Free entity {:?} does not match its key state", entity));
+            }
+        }
+        for key in 1..self.next_entity.get().key() {
+            if self.entity_states.get(key).is_some_and(|state| state.free) && !free_keys.contains(&key) {
+                return Err(anyhow!("Entity key {} is free but missing from the free list", key));
+            }
+        }
+        for (uid, container) in &self.containers {
+            container.validate().map_err(|err| anyhow!("Invalid container {:?}: {}", uid, err))?;
+            for index in 0..container.len() {
+                let entity = container.entity(index);
+                if !self.is_alive(entity) {
+                    return Err(anyhow!("Container {:?} holds dead entity {:?}", uid, entity));
+                }
+            }
+        }
+        if self.names.len() != self.entity_names.len() {
+            return Err(anyhow!("Entity names are not bijective"));
+        }
+        for (name, entity) in &self.names {
+            if self.entity_names.get(entity) != Some(name) {
+                return Err(anyhow!("Entity name '{}' does not match entity {:?}", name, entity));
+            }
+            if !self.is_alive(*entity) {
+                return Err(anyhow!("Entity name '{}' refers to dead entity {:?}", name, entity));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{uid::UID, registry::component::DynamicComponentDefinition, ecs::{entity::Entity, query::Changed, command::WorldCommands, view::ComponentView, testing::{setup, setup_with_hooks, Health, HookLog, HEALTH}}, feature::asset::runtime_component::FieldType};
+
+    #[test]
+    fn commands_on_reserved_entities() {
+        let (mut world, registry) = setup();
+        let (a, b) = {
+            let commands = WorldCommands { world: &world };
+            let a = commands.create();
+            let b = commands.create();
+            commands.add(a, HEALTH, Health(10));
+            commands.add(b, HEALTH, Health(20));
+            commands.remove(b, HEALTH);
+            (a, b)
+        };
+        assert!(world.is_alive(a) && world.is_alive(b));
+        world.apply_commands(&registry).unwrap();
+        assert_eq!(world.get::<Health>(a, HEALTH).unwrap().unwrap().0, 10);
+        assert!(!world.contains(b, HEALTH).unwrap());
+        world.validate().unwrap();
+    }
+
+    #[test]
+    fn failing_commands_do_not_drop_the_next_ones() {
+        let (mut world, registry) = setup();
+        let (a, b) = {
+            let commands = WorldCommands { world: &world };
+            let a = commands.create();
+            let b = commands.create();
+            commands.destroy(a);
+            commands.add(a, HEALTH, Health(1));
+            commands.add(b, HEALTH, Health(2));
+            commands.add(b, HEALTH, Health(3));
+            (a, b)
+        };
+        let err = world.apply_commands(&registry).unwrap_err().to_string();
+        assert!(err.starts_with("2 world command(s) failed"), "{}", err);
+        assert!(err.contains(&format!("Failed to add component 'health' to entity {} (version 0)", a.key())), "{}", err);
+        assert!(err.contains("Component already added"), "{}", err);
+        assert!(!world.is_alive(a));
+        assert_eq!(world.get::<Health>(b, HEALTH).unwrap().unwrap().0, 2);
+        world.validate().unwrap();
+    }
+
+    #[test]
+    fn clear_runs_remove_hooks() {
+        let log = HookLog::default();
+        let (mut world, registry) = setup_with_hooks(log.hooks());
+        for value in [1, 2, 4] {
+            let entity = world.create();
+            world.add(&registry, entity, HEALTH, Health(value)).unwrap();
+        }
+        world.clear();
+        assert_eq!(log.removed.get(), 7);
+        world.validate().unwrap();
+    }
+
+    #[test]
+    fn replacing_runs_hooks() {
+        let log = HookLog::default();
+        let (mut world, registry) = setup_with_hooks(log.hooks());
+        let entity = world.create();
+        world.add(&registry, entity, HEALTH, Health(1)).unwrap();
+        world.end_system(UID::new("system"));
+        let last_run = world.last_run(UID::new("system"));
+        world.deserialize_component(&registry, entity, HEALTH, &mut <dyn erased_serde::Deserializer>::erase(serde_json::json!(4))).unwrap();
+        assert_eq!((log.added.get(), log.removed.get()), (5, 1));
+        assert_eq!(world.get::<Health>(entity, HEALTH).unwrap().unwrap().0, 4);
+        let changed = world.query_mut::<Entity, Changed<Health>>(&registry, last_run).unwrap().iter_mut().collect::<Vec<_>>();
+        assert_eq!(changed, vec![entity]);
+        world.validate().unwrap();
+    }
+
+    #[test]
+    fn conflicting_borrows_are_errors() {
+        let (mut world, registry) = setup();
+        let entity = world.create();
+        world.add(&registry, entity, HEALTH, Health(1)).unwrap();
+        {
+            let _view = world.view_mut::<Health>(HEALTH).unwrap();
+            assert!(world.get::<Health>(entity, HEALTH).is_err());
+            assert!(world.get_mut::<Health>(entity, HEALTH).is_err());
+        }
+        {
+            let _view = world.view::<Health>(HEALTH).unwrap();
+            assert!(world.get::<Health>(entity, HEALTH).unwrap().is_some());
+            assert!(world.get_mut::<Health>(entity, HEALTH).is_err());
+        }
+        {
+            let _health = world.get_mut::<Health>(entity, HEALTH).unwrap().unwrap();
+            assert!(world.get::<Health>(entity, HEALTH).is_err());
+            assert!(world.view::<Health>(HEALTH).is_err());
+        }
+        assert_eq!(world.get_mut::<Health>(entity, HEALTH).unwrap().unwrap().0, 1);
+    }
+
+    #[test]
+    fn stale_handles_are_rejected() {
+        let (mut world, mut registry) = setup();
+        let mut definition = DynamicComponentDefinition::default();
+        definition.add_field("value", FieldType::Integer).unwrap();
+        let score = registry.define_dynamic("score", definition).unwrap();
+        let stale = world.create();
+        world.add(&registry, stale, HEALTH, Health(1)).unwrap();
+        world.add_dynamic(&registry, stale, score).unwrap();
+        world.destroy(stale).unwrap();
+        let err = world.get::<Health>(stale, HEALTH).err().unwrap();
+        assert!(err.to_string().ends_with("it was destroyed"), "{}", err);
+        // The key is reused with the next version
+        let entity = world.create();
+        assert_eq!(entity.key(), stale.key());
+        assert_ne!(entity.version(), stale.version());
+        world.add(&registry, entity, HEALTH, Health(2)).unwrap();
+        world.add_dynamic(&registry, entity, score).unwrap();
+        let err = world.get::<Health>(stale, HEALTH).err().unwrap();
+        assert!(err.to_string().contains("its key reused"), "{}", err);
+        assert!(world.get_mut::<Health>(stale, HEALTH).is_err());
+        assert!(world.contains(stale, HEALTH).is_err());
+        assert!(world.get_field(&registry, stale, score, "value").is_err());
+        assert!(world.serialize_component(&registry, stale, HEALTH).is_err());
+        assert!(world.serialize_component(&registry, entity, HEALTH).unwrap().is_some());
+        assert!(world.remove(stale, HEALTH).is_err());
+        assert!(world.destroy(stale).is_err());
+        assert!(world.view::<Health>(HEALTH).unwrap().get(stale).is_none());
+        assert!(world.view_mut::<Health>(HEALTH).unwrap().get_mut(stale).is_none());
+        assert_eq!(world.view::<Health>(HEALTH).unwrap().get(entity).unwrap().0, 2);
+        assert!(world.contains(entity, HEALTH).unwrap());
+        world.validate().unwrap();
+    }
+
+    #[test]
+    fn validate_after_churn() {
+        let (mut world, registry) = setup();
+        let mut alive = Vec::new();
+        for step in 0..500u32 {
+            match step % 7 {
+                0 | 3 | 5 => {
+                    let entity = world.create();
+                    world.add(&registry, entity, HEALTH, Health(step)).unwrap();
+                    if step % 2 == 0 {
+                        world.set_name(entity, &format!("entity{}", step)).unwrap();
+                    }
+                    alive.push(entity);
+                },
+                1 | 4 if !alive.is_empty() => {
+                    let entity = alive.swap_remove((step as usize * 31) % alive.len());
+                    world.destroy(entity).unwrap();
+                    assert!(!world.is_alive(entity));
+                },
+                2 if !alive.is_empty() => {
+                    let entity = alive[(step as usize * 17) % alive.len()];
+                    if world.contains(entity, HEALTH).unwrap() {
+                        world.remove(entity, HEALTH).unwrap();
+                    } else {
+                        world.add(&registry, entity, HEALTH, Health(step)).unwrap();
+                    }
+                },
+                _ => {},
+            }
+            world.validate().unwrap();
+        }
+        for entity in alive {
+            assert!(world.is_alive(entity));
+        }
+    }
+}
@@ -5,7 +5,7 @@ use serde::{Serializer, Deserializer, Serialize};
 
 use crate::asset::AssetManager;
 use crate::ecs::ECSManager;
-use crate::ecs::system::SystemCallback;
+use crate::ecs::system::{SystemCallback, SystemAccess};
 use crate::feature::asset::input_table::{InputTable, InputAction, InputAxis};
 use crate::feature::{asset, component, system};
 use crate::physics::PhysicsManager;
@@ -21,17 +21,15 @@ use core::cell::RefCell;
 use std::cell::Ref;
 
 const MAXIMUM_TIMESTEP: f64 = 1.0 / 20.0;
-const FIXED_TIMESTEP: f64 = 1.0 / 60.0;
 
 pub struct Engine {
     pub(crate) registry: RefCell<RegistryManager>,
     pub(crate) asset: AssetManager,
     pub(crate) input: InputManager,
-    pub(crate) script: ScriptManager,
+    pub(crate) script: RefCell<ScriptManager>,
     pub(crate) ecs: ECSManager,
     pub(crate) renderer: RendererManager,
     pub(crate) physics: PhysicsManager,
-    accumulator: f64,
     time: f64,
 }
 
@@ -44,6 +42,7 @@ impl Engine {
         // Assets
         registry.assets.define_static::<asset::font::Font>(asset::font::Font::NAME)?;
         registry.assets.define_static::<asset::input_table::InputTable>(asset::input_table::InputTable::NAME)?;
+        registry.assets.define_static::<asset::lua_script::LuaScript>(asset::lua_script::LuaScript::NAME)?;
         registry.assets.define_static::<asset::material::Material>(asset::material::Material::NAME)?;
         registry.assets.define_static::<asset::mesh::Mesh>(asset::mesh::Mesh::NAME)?;
         registry.assets.define_static::<asset::model::Model>(asset::model::Model::NAME)?;
@@ -56,27 +55,23 @@ impl Engine {
         registry.assets.define_static::<asset::world_template::WorldTemplate>(asset::world_template::WorldTemplate::NAME)?;
 
         // Components
-        registry.components.define_static::<component::camera::Camera>(component::camera::Camera::NAME)?;
-        registry.components.define_static::<component::free_fly::FreeFly>(component::free_fly::FreeFly::NAME)?;
-        registry.components.define_static::<component::lifecycle::Lifecycle>(component::lifecycle::Lifecycle::NAME)?;
-        registry.components.define_static::<component::static_mesh::StaticMesh>(component::static_mesh::StaticMesh::NAME)?;
-        registry.components.define_static::<component::rhai_scripts::RhaiScripts>(component::rhai_scripts::RhaiScripts::NAME)?;
-        registry.components.define_static::<component::rigid_body::RigidBody>(component::rigid_body::RigidBody::NAME)?;
-        registry.components.define_static::<component::rotator::Rotator>(component::rotator::Rotator::NAME)?;
-        registry.components.define_static::<component::script_storage::ScriptStorage>(component::script_storage::ScriptStorage::NAME)?;
-        registry.components.define_static::<component::transform::Transform>(component::transform::Transform::NAME)?;
-        registry.components.define_static::<component::local_to_world::LocalToWorld>(component::local_to_world::LocalToWorld::NAME)?;
-        registry.components.define_static::<component::hierarchy::Hierarchy>(component::hierarchy::Hierarchy::NAME)?;
-        registry.components.define_static::<component::ui::UIComponent>(component::ui::UIComponent::NAME)?;
-        registry.components.define_static::<component::viewport::Viewport>(component::viewport::Viewport::NAME)?;
-        registry.components.define_static::<component::canvas::Canvas>(component::canvas::Canvas::NAME)?;
+        registry.components.define_static::<component::free_fly::FreeFly>(component::free_fly::FreeFly::NAME, Default::default())?;
+        registry.components.define_static::<component::lifecycle::Lifecycle>(component::lifecycle::Lifecycle::NAME, Default::default())?;
+        registry.components.define_static::<component::rotator::Rotator>(component::rotator::Rotator::NAME, Default::default())?;
+        registry.components.define_static::<component::script_storage::ScriptStorage>(component::script_storage::ScriptStorage::NAME, Default::default())?;
+        registry.components.define_static::<component::transform::Transform>(component::transform::Transform::NAME, Default::default())?;
+        registry.components.define_static::<component::local_to_world::LocalToWorld>(component::local_to_world::LocalToWorld::NAME, Default::default())?;
+        registry.components.define_static::<component::hierarchy::Hierarchy>(component::hierarchy::Hierarchy::NAME, Default::default())?;
+        registry.components.define_static::<component::ui::UIComponent>(component::ui::UIComponent::NAME, Default::default())?;
+        self.script.borrow().define_components(&mut registry.components)?;
+        self.renderer.define_components(&mut registry.components)?;
+        self.physics.define_components(&mut registry.components)?;
 
         // Systems
         registry.systems.define_static("despawn_entities", system::despawn::run)?;
-        registry.systems.define_static("renderer", system::renderer::despawn_renderer_entities)?;
         registry.systems.define_static("free_fly", system::free_fly::run)?;
         registry.systems.define_static("rhai_update_scripts", system::rhai::update_scripts)?;
-        registry.systems.define_static("rotator", system::rotator::run)?;
+        registry.systems.define_parallel("rotator", system::rotator::run, SystemAccess::new().read(component::rotator::Rotator::UID).write(component::transform::Transform::UID))?;
         registry.systems.define_static("transform_propagate", system::transform::propagate)?;
         registry.systems.define_static("ui_update", system::ui::update)?;
         registry.systems.define_static("ui_render", system::ui::render)?;
@@ -93,7 +88,6 @@ impl Engine {
             ecs: Default::default(),
             renderer: Default::default(),
             physics: Default::default(),
-            accumulator: 0.0,
             time: 0.0,
         };
         engine.define_core_features()?;
@@ -139,12 +133,21 @@ impl Engine {
                 self.manager.save_state(serializer)
             }
         }
+        struct ComponentRegistrySerialize<'a> {
+            registry: &'a RegistryManager,
+        }
+        impl<'a> Serialize for ComponentRegistrySerialize<'a> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where S: Serializer {
+                self.registry.components.save_state(serializer)
+            }
+        }
         let mut tuple = serializer.serialize_tuple(6)?;
+        tuple.serialize_element(&ComponentRegistrySerialize { registry: &self.registry.borrow() })?;
         tuple.serialize_element(&AssetManagerSerialize { manager: &self.asset })?;
         tuple.serialize_element(&RendererManagerSerialize { manager: &self.renderer })?;
         tuple.serialize_element(&ECSManagerSerialize { manager: &self.ecs, registry: &self.registry.borrow() })?;
         tuple.serialize_element(&InputManagerSerialize { manager: &self.input })?;
-        tuple.serialize_element(&self.accumulator)?;
         tuple.serialize_element(&self.time)?;
         tuple.end()
     }
@@ -161,6 +164,16 @@ impl Engine {
             fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
                 where A: serde::de::SeqAccess<'de> {
                 use serde::de::Error;
+                struct ComponentRegistryDeserializeSeed<'a> {
+                    registry: &'a mut RegistryManager,
+                }
+                impl<'de, 'a> DeserializeSeed<'de> for ComponentRegistryDeserializeSeed<'a> {
+                    type Value = ();
+                    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+                        where D: Deserializer<'de> {
+                        self.registry.components.load_state(deserializer)
+                    }
+                }
                 struct AssetManagerDeserializeSeed<'a> {
                     manager: &'a mut AssetManager,
                     registry: Ref<'a, RegistryManager>,
@@ -203,11 +216,11 @@ impl Engine {
                         self.manager.load_state(deserializer)
                     }
                 }
+                seq.next_element_seed(ComponentRegistryDeserializeSeed { registry: self.engine.registry.get_mut() })?;
                 seq.next_element_seed(AssetManagerDeserializeSeed { manager: &mut self.engine.asset, registry: self.engine.registry.borrow() })?;
                 seq.next_element_seed(RendererManagerDeserializeSeed { manager: &mut self.engine.renderer })?;
                 seq.next_element_seed(ECSManagerDeserializeSeed { manager: &mut self.engine.ecs, registry: self.engine.registry.borrow() })?;
                 seq.next_element_seed(InputManagerDeserializeSeed { manager: &mut self.engine.input })?;
-                self.engine.accumulator = seq.next_element()?.with_context(|| "Expect accumulator").map_err(Error::custom)?;
                 self.engine.time = seq.next_element()?.with_context(|| "Expect time").map_err(Error::custom)?;
                 self.engine.renderer.reset(&mut self.engine.ecs).map_err(Error::custom)?;
                 Ok(())
@@ -229,6 +242,12 @@ impl Engine {
         self.input.iter_axis()
     }
 
+    /// Maximum number of procedures invoked by systems in a frame, `progress` fails when procedures keep invoking each other.
+    /// Procedures queued by the engine, including the catch-up runs of fixed procedures, are not counted.
+    pub fn set_procedure_budget(&mut self, budget: u32) {
+        self.ecs.procedure_budget = budget;
+    }
+
     pub fn progress(&mut self, events: &Events, requests: &mut Requests, mut delta_time: f64) -> Result<()> {
 
         // ================= PREPARE STAGE ================== //
@@ -240,12 +259,8 @@ impl Engine {
         if delta_time > MAXIMUM_TIMESTEP {
             delta_time = MAXIMUM_TIMESTEP; // Slowing down
         }
-        // Integrate time
-        self.accumulator += delta_time;
+        // Integrate time, fixed procedures integrate it in their own accumulator
         self.time += delta_time;
-        // Compute number of fixed updates
-        let fixed_update_count = (self.accumulator / FIXED_TIMESTEP) as u32;
-        self.accumulator -= fixed_update_count as f64 * FIXED_TIMESTEP;
 
         // ================= DISPATCH STAGE ================= //
 
@@ -265,6 +280,16 @@ impl Engine {
             }
         }
 
+        // Dispatch asset events
+        for event in &events.asset {
+            self.asset.dispatch_import(event)?;
+        }
+
+        // Invalidate scripts of modified assets
+        for (asset, uid) in self.asset.drain_modified() {
+            self.script.get_mut().on_asset_modified(asset, uid);
+        }
+
         // TODO: dispatch more events ...
 
         // ============ UPDATE/FIXED-UPDATE STAGE =========== //
@@ -274,14 +299,15 @@ impl Engine {
             &mut self.asset,
             &mut self.input, 
             &mut self.renderer,
-            &mut self.script,
+            &self.script,
             events,
             delta_time, 
-            self.time, 
-            FIXED_TIMESTEP, 
-            fixed_update_count
+            self.time,
         )?;
 
+        // Release physics bodies of removed components
+        self.physics.update()?;
+
         // ================= REQUESTS STAGE ================= //
 
         // Check input requests
@@ -298,7 +324,58 @@ impl Engine {
             backend.reset()?;
             self.renderer.reset(&mut self.ecs)?;
         }
-        self.renderer.update_backend(backend, &self.asset, &mut self.ecs)?;
+        self.renderer.update_backend(backend, &self.asset, &self.registry.borrow().components, &mut self.ecs)?;
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::{context::SystemContext, event::Events, request::Requests, ecs::procedure::Procedure, uid::UID, feature::asset::{rhai_script::RhaiScript, runtime_component::FieldValue, system_group::{SystemGroup, SystemPipeline}}};
+
+    use super::Engine;
+
+    const SCRIPT: &str = r#"
+fn run() {
+    if WORLD.find_entity("player") == () {
+        REGISTRY.define_component("score", [["points", "Integer"], ["name", "String"]]);
+        let player = WORLD.create_entity();
+        WORLD.set_entity_name(player, "player");
+        WORLD.set_component(player, "score", #{ points: 42, name: "alice" });
+    }
+}
+"#;
+
+    fn init(ctx: &mut SystemContext) -> Result<()> {
+        let bundle = ctx.asset.add_bundle("test")?;
+        ctx.asset.add(RhaiScript::UID, "score", bundle, RhaiScript { source: SCRIPT.into() })?;
+        ctx.registry.define_rhai_system("score", "score".into())?;
+        let mut group = SystemGroup::empty();
+        group.insert(Procedure::UPDATE, SystemPipeline::single("score".into()), 0);
+        ctx.scheduler.add_group("test", group)?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn score(engine: &Engine) -> Vec<FieldValue> {
+        let registry = engine.registry.borrow();
+        let worlds = engine.ecs.worlds.borrow();
+        let world = worlds.get(&engine.ecs.active_world).unwrap().borrow();
+        let player = world.find("player").unwrap();
+        ["points", "name"].iter().map(|field| world.get_field(&registry.components, player, UID::new("score"), field).unwrap().unwrap()).collect()
+    }
+
+    #[test]
+    fn script_components_survive_save_and_load() {
+        let mut engine = Engine::new(init).unwrap();
+        engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap();
+        assert_eq!(engine.script.borrow().rhai.iter_faults().count(), 0);
+        let expected = vec![FieldValue::Integer(42), FieldValue::String("alice".into())];
+        assert_eq!(score(&engine), expected);
+        let mut state = Vec::new();
+        engine.save_state(&mut serde_json::Serializer::new(&mut state)).unwrap();
+        let mut loaded = Engine::new(|_| Ok(())).unwrap();
+        loaded.load_state(&mut serde_json::Deserializer::from_reader(state.as_slice())).unwrap();
+        assert_eq!(score(&loaded), expected);
+    }
+}
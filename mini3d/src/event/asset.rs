@@ -1,6 +1,6 @@
 use serde::{Serialize, Deserialize};
 
-use crate::feature::asset::{font::Font, mesh::Mesh, material::Material, model::Model, rhai_script::RhaiScript, texture::Texture};
+use crate::feature::asset::{font::Font, lua_script::LuaScript, mesh::Mesh, material::Material, model::Model, rhai_script::RhaiScript, texture::Texture};
 
 #[derive(Serialize, Deserialize)]
 pub struct AssetImportEntry<T> {
@@ -16,4 +16,5 @@ pub enum ImportAssetEvent {
     Model(AssetImportEntry<Model>),
     RhaiScript(AssetImportEntry<RhaiScript>),
     Texture(AssetImportEntry<Texture>),
+    LuaScript(AssetImportEntry<LuaScript>),
 }
\ No newline at end of file
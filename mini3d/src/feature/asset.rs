@@ -1,6 +1,7 @@
 pub mod runtime_component;
 pub mod font;
 pub mod input_table;
+pub mod lua_script;
 pub mod material;
 pub mod mesh;
 pub mod model;
@@ -0,0 +1,15 @@
+use serde::{Serialize, Deserialize};
+
+use crate::{registry::asset::Asset, uid::UID};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LuaScript {
+    pub source: String,
+}
+
+impl Asset for LuaScript {}
+
+impl LuaScript {
+    pub const NAME: &'static str = "lua_script";
+    pub const UID: UID = UID::new(LuaScript::NAME);
+}
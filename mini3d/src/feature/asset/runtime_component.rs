@@ -3,7 +3,7 @@ use serde::{Serialize, Deserialize};
 
 use crate::ecs::entity::Entity;
 
-#[derive(Serialize, Deserialize, Clone, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum FieldType {
     String,
     Integer,
@@ -16,7 +16,7 @@ pub enum FieldType {
     Array,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum FieldValue {
     String(String),
     Integer(i32),
@@ -27,4 +27,38 @@ pub enum FieldValue {
     Vec4(Vec4),
     Entity(Entity),
     Array(Vec<FieldValue>),
-}
\ No newline at end of file
+}
+
+impl FieldType {
+
+    pub fn default_value(&self) -> FieldValue {
+        match self {
+            FieldType::String => FieldValue::String(String::new()),
+            FieldType::Integer => FieldValue::Integer(0),
+            FieldType::Float => FieldValue::Float(0.0),
+            FieldType::Boolean => FieldValue::Boolean(false),
+            FieldType::Vec2 => FieldValue::Vec2(Vec2::ZERO),
+            FieldType::Vec3 => FieldValue::Vec3(Vec3::ZERO),
+            FieldType::Vec4 => FieldValue::Vec4(Vec4::ZERO),
+            FieldType::Entity => FieldValue::Entity(Entity::null()),
+            FieldType::Array => FieldValue::Array(Vec::new()),
+        }
+    }
+}
+
+impl FieldValue {
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldValue::String(_) => FieldType::String,
+            FieldValue::Integer(_) => FieldType::Integer,
+            FieldValue::Float(_) => FieldType::Float,
+            FieldValue::Boolean(_) => FieldType::Boolean,
+            FieldValue::Vec2(_) => FieldType::Vec2,
+            FieldValue::Vec3(_) => FieldType::Vec3,
+            FieldValue::Vec4(_) => FieldType::Vec4,
+            FieldValue::Entity(_) => FieldType::Entity,
+            FieldValue::Array(_) => FieldType::Array,
+        }
+    }
+}
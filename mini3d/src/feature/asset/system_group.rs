@@ -1,21 +1,51 @@
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 
-use crate::{uid::UID, registry::asset::Asset};
+use crate::{uid::UID, registry::asset::Asset, ecs::condition::RunCondition};
+
+/// The system named `before` runs before the system named `after`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SystemOrder {
+    pub(crate) before: String,
+    pub(crate) after: String,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemPipeline {
     pub(crate) systems: Vec<UID>,
+    #[serde(default)]
+    pub(crate) order: Vec<SystemOrder>,
+    /// Conditions of the systems of the pipeline, by system name
+    #[serde(default)]
+    pub(crate) conditions: Vec<(String, RunCondition)>,
 }
 
 impl SystemPipeline {
 
     pub fn single(system: UID) -> Self {
-        Self { systems: vec![system] }
+        Self { systems: vec![system], order: Vec::new(), conditions: Vec::new() }
     }
 
     pub fn new(systems: &[UID]) -> Self {
-        Self { systems: systems.to_vec() }
+        Self { systems: systems.to_vec(), order: Vec::new(), conditions: Vec::new() }
+    }
+
+    /// Run `system` before `other`. Both can belong to any group of the procedure.
+    pub fn before(mut self, system: &str, other: &str) -> Self {
+        self.order.push(SystemOrder { before: system.to_string(), after: other.to_string() });
+        self
+    }
+
+    /// Run `system` after `other`. Both can belong to any group of the procedure.
+    pub fn after(mut self, system: &str, other: &str) -> Self {
+        self.order.push(SystemOrder { before: other.to_string(), after: system.to_string() });
+        self
+    }
+
+    /// Skip `system` of this pipeline unless the condition holds. All conditions of a system must hold.
+    pub fn run_if(mut self, system: &str, condition: RunCondition) -> Self {
+        self.conditions.push((system.to_string(), condition));
+        self
     }
 }
 
@@ -28,7 +58,13 @@ pub(crate) struct ProcedureEntry {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SystemGroup {
-    pub(crate) procedures: HashMap<UID, ProcedureEntry>
+    pub(crate) procedures: HashMap<UID, ProcedureEntry>,
+    /// Groups this one must run before
+    #[serde(default)]
+    pub(crate) before: Vec<String>,
+    /// Groups this one must run after
+    #[serde(default)]
+    pub(crate) after: Vec<String>,
 }
 
 impl Asset for SystemGroup {}
@@ -39,7 +75,7 @@ impl SystemGroup {
     pub const UID: UID = UID::new(SystemGroup::NAME);
 
     pub fn empty() -> Self {
-        Self { procedures: Default::default() }
+        Self { procedures: Default::default(), before: Vec::new(), after: Vec::new() }
     }
 
     pub fn insert(&mut self, procedure: &str, pipeline: SystemPipeline, priority: i32) {
@@ -50,4 +86,16 @@ impl SystemGroup {
     pub fn remove(&mut self, procedure: UID) {
         self.procedures.remove(&procedure);
     }
+
+    /// Run the systems of this group before the ones of `group` in the procedures they share.
+    /// Constraints take precedence over priorities.
+    pub fn before(&mut self, group: &str) {
+        self.before.push(group.to_string());
+    }
+
+    /// Run the systems of this group after the ones of `group` in the procedures they share.
+    /// Constraints take precedence over priorities.
+    pub fn after(&mut self, group: &str) {
+        self.after.push(group.to_string());
+    }
 }
\ No newline at end of file
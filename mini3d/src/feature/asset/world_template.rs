@@ -1,9 +1,13 @@
-use anyhow::{Result, Context};
+use std::collections::HashMap;
+
+use anyhow::{Result, Context, anyhow};
 use serde::{Serialize, Deserialize};
 use serde_json::{Map, Value};
 
-use crate::{ecs::world::World, uid::UID, registry::{component::ComponentRegistry, asset::Asset}};
+use crate::{ecs::{world::World, entity::Entity}, uid::UID, registry::{component::ComponentRegistry, asset::Asset}};
 
+/// Entities are written as a map of name to components. Components can reference
+/// other entities of the template with `{ "$entity": "<name>" }`.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct WorldTemplate {
     entities: Map<String, Value>,
@@ -11,23 +15,134 @@ pub struct WorldTemplate {
 
 impl Asset for WorldTemplate {}
 
+const ENTITY_REFERENCE: &str = "$entity";
+
+fn resolve_references(value: &Value, entities: &HashMap<&str, Entity>) -> Result<Value> {
+    match value {
+        Value::Object(values) => {
+            if let (1, Some(reference)) = (values.len(), values.get(ENTITY_REFERENCE)) {
+                let name = reference.as_str().with_context(|| "Entity reference must be a name")?;
+                let entity = entities.get(name).with_context(|| format!("Entity '{}' not found in template", name))?;
+                return Ok(serde_json::to_value(entity)?);
+            }
+            let mut resolved = Map::new();
+            for (key, value) in values {
+                resolved.insert(key.clone(), resolve_references(value, entities)?);
+            }
+            Ok(Value::Object(resolved))
+        },
+        Value::Array(values) => Ok(Value::Array(values.iter().map(|value| resolve_references(value, entities)).collect::<Result<_>>()?)),
+        _ => Ok(value.clone()),
+    }
+}
+
 impl WorldTemplate {
 
     pub const NAME: &'static str = "world_template";
     pub const UID: UID = UID::new(WorldTemplate::NAME);
-    
-    pub(crate) fn instantiate(&self, world: &mut World, registry: &ComponentRegistry) -> Result<()> {
-        for (_name, components) in &self.entities {
-            let components = components.as_object().with_context(|| "Entity components must be an object")?;
-            let entity = world.create(); // TODO: Add name to entity
-            for (name, data) in components {
-                let uid: UID = name.into();
-                // let component = &registry.get(uid)
-                //     .with_context(|| format!("Component not registered: {}", name))?
-                //     .
-                // component.instantiate(entity, data, world)?;
+
+    /// Create the entities of the template and return them by template name. Nothing is
+    /// kept if one of them fails. References are resolved within the instance, entities
+    /// are only named in the world when a prefix is given, as `<prefix><name>`.
+    pub(crate) fn instantiate(&self, world: &mut World, registry: &ComponentRegistry, prefix: Option<&str>) -> Result<HashMap<String, Entity>> {
+        let mut entities = HashMap::new();
+        let result = (|| -> Result<()> {
+            // Entities are created first so that components can reference any of them
+            for name in self.entities.keys() {
+                let entity = world.create();
+                entities.insert(name.as_str(), entity);
+                if let Some(prefix) = prefix {
+                    world.set_name(entity, &format!("{}{}", prefix, name)).map_err(|err| anyhow!("Failed to create entity '{}': {}", name, err))?;
+                }
+            }
+            for (name, components) in &self.entities {
+                let components = components.as_object().with_context(|| format!("Components of entity '{}' must be an object", name))?;
+                for (component, data) in components {
+                    resolve_references(data, &entities)
+                        .and_then(|data| world.add_from_json(registry, entities[name.as_str()], component.into(), data))
+                        .map_err(|err| anyhow!("Failed to add component '{}' to entity '{}': {}", component, name, err))?;
+                }
             }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => Ok(entities.into_iter().map(|(name, entity)| (name.to_owned(), entity)).collect()),
+            Err(err) => {
+                // The entities were just created, rolling back cannot hide the original error
+                for entity in entities.values() {
+                    let _ = world.destroy(*entity);
+                }
+                Err(err)
+            },
         }
-        Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::ecs::{entity::Entity, testing::{setup, Link, LINK}};
+
+    use super::WorldTemplate;
+
+    fn template() -> WorldTemplate {
+        serde_json::from_value(json!({
+            "entities": {
+                "a": { "link": { "target": { "$entity": "b" } } },
+                "b": { "link": { "target": { "$entity": "a" } } },
+            }
+        })).unwrap()
+    }
+
+    #[test]
+    fn references_are_resolved_within_the_instance() {
+        let (mut world, registry) = setup();
+        let template = template();
+        let first = template.instantiate(&mut world, &registry, None).unwrap();
+        let second = template.instantiate(&mut world, &registry, None).unwrap();
+        for instance in [&first, &second] {
+            assert_eq!(world.get::<Link>(instance["a"], LINK).unwrap().unwrap().target, instance["b"]);
+            assert_eq!(world.get::<Link>(instance["b"], LINK).unwrap().unwrap().target, instance["a"]);
+        }
+        assert_ne!(first["a"], second["a"]);
+        assert!(world.find("a").is_none());
+        world.validate().unwrap();
+    }
+
+    #[test]
+    fn names_are_scoped_by_prefix() {
+        let (mut world, registry) = setup();
+        let template = template();
+        let first = template.instantiate(&mut world, &registry, Some("first.")).unwrap();
+        let second = template.instantiate(&mut world, &registry, Some("second.")).unwrap();
+        assert_eq!(world.find("first.a"), Some(first["a"]));
+        assert_eq!(world.find("second.b"), Some(second["b"]));
+        world.validate().unwrap();
+    }
+
+    #[test]
+    fn failures_are_rolled_back() {
+        let (mut world, registry) = setup();
+        let template = template();
+        let kept = template.instantiate(&mut world, &registry, Some("kept.")).unwrap();
+        let err = template.instantiate(&mut world, &registry, Some("kept.")).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to create entity"), "{}", err);
+        let broken: WorldTemplate = serde_json::from_value(json!({
+            "entities": {
+                "a": { "link": { "target": { "$entity": "missing" } } },
+                "b": {},
+            }
+        })).unwrap();
+        let err = broken.instantiate(&mut world, &registry, None).unwrap_err();
+        assert!(err.to_string().contains("Entity 'missing' not found in template"), "{}", err);
+        // Failed instances may reuse the keys they freed, only the kept entities remain
+        let alive = (1..8).flat_map(|key| (0..4).map(move |version| Entity::new(key, version)))
+            .filter(|entity| world.is_alive(*entity))
+            .count();
+        assert_eq!(alive, 2);
+        assert_eq!(world.find("kept.a"), Some(kept["a"]));
+        assert!(world.is_alive(kept["b"]));
+        world.validate().unwrap();
+    }
+}
@@ -6,8 +6,6 @@ use crate::{uid::UID, ecs::component::Component};
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct LocalToWorld {
     pub matrix: Mat4,
-    #[serde(skip)]
-    pub(crate) dirty: bool,
 }
 
 impl Component for LocalToWorld {}
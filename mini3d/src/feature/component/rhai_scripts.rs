@@ -1,10 +1,8 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, Context};
 use serde::{Serialize, Deserialize};
 
 use crate::{uid::UID, ecs::component::Component};
 
-pub const MAX_RHAI_SCRIPT_COUNT: usize = 16;
-
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RhaiScriptStatus {
     Starting,
@@ -16,11 +14,15 @@ pub enum RhaiScriptStatus {
 pub struct RhaiScriptInstance {
     pub uid: UID,
     pub status: RhaiScriptStatus,
+    /// Script revision seen by the instance, used to detect reloads. Revisions are
+    /// not kept by saved states, a loaded instance takes the current revision.
+    #[serde(skip)]
+    pub(crate) revision: Option<u32>,
 }
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct RhaiScripts {
-    pub instances: [Option<RhaiScriptInstance>; MAX_RHAI_SCRIPT_COUNT],
+    pub instances: Vec<RhaiScriptInstance>,
 }
 
 impl Component for RhaiScripts {}
@@ -31,17 +33,31 @@ impl RhaiScripts {
     pub const UID: UID = UID::new(RhaiScripts::NAME);
 
     pub fn add(&mut self, uid: UID) -> Result<()> {
-        if self.instances.iter().any(|instance| match instance {
-            Some(instance) => { instance.uid == uid },
-            None => false
-        }) {
+        if self.instances.iter().any(|instance| instance.uid == uid) {
             return Err(anyhow!("Trying to add existing rhai script"))
         }
-        if let Some(instance) = self.instances.iter_mut().find(|instance| instance.is_none()) {
-            *instance = Some(RhaiScriptInstance { uid, status: RhaiScriptStatus::Starting });
-            Ok(())
+        self.instances.push(RhaiScriptInstance { uid, status: RhaiScriptStatus::Starting, revision: None });
+        Ok(())
+    }
+
+    /// Instances of a component added to an entity were never started on it
+    pub(crate) fn restart(&mut self) {
+        self.instances.retain(|instance| instance.status != RhaiScriptStatus::Stopping);
+        for instance in &mut self.instances {
+            instance.status = RhaiScriptStatus::Starting;
+            instance.revision = None;
+        }
+    }
+
+    /// The script is stopped during the next update before being removed
+    pub fn remove(&mut self, uid: UID) -> Result<()> {
+        let index = self.instances.iter().position(|instance| instance.uid == uid)
+            .with_context(|| "Trying to remove non-existing rhai script")?;
+        if self.instances[index].status == RhaiScriptStatus::Starting {
+            self.instances.remove(index);
         } else {
-            Err(anyhow!("No script slot available"))
+            self.instances[index].status = RhaiScriptStatus::Stopping;
         }
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -6,16 +6,17 @@ use crate::{uid::UID, renderer::backend::SceneModelHandle, ecs::component::Compo
 pub struct StaticMesh {
     pub model: UID,
     #[serde(skip)]
-    pub changed: bool,
-    #[serde(skip)]
     pub(crate) handle: Option<SceneModelHandle>,
+    /// Model of the handle, the handle is recreated when the model changes
+    #[serde(skip)]
+    pub(crate) handle_model: UID,
 }
 
 impl Component for StaticMesh {}
 
 impl StaticMesh {
     pub fn new(model: UID) -> Self {
-        Self { model, changed: false, handle: None }
+        Self { model, handle: None, handle_model: UID::null() }
     }
 }
 
@@ -3,7 +3,7 @@ use serde::{Serialize, Deserialize};
 
 use crate::{uid::UID, ecs::component::Component};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Transform {
     pub translation: Vec3,
     pub rotation: Quat,
@@ -1,6 +1,5 @@
 pub mod despawn;
 pub mod free_fly;
-pub mod renderer;
 pub mod rhai;
 pub mod rotator;
 pub mod transform;
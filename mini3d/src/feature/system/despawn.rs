@@ -1,43 +1,26 @@
 use anyhow::Result;
 
-use crate::{context::SystemContext, ecs::entity::Entity, feature::component::{lifecycle::Lifecycle, hierarchy::Hierarchy}};
+use crate::{context::SystemContext, ecs::{entity::Entity, view::ComponentView}, feature::component::{lifecycle::Lifecycle, hierarchy::Hierarchy}};
 
 pub fn run(ctx: &mut SystemContext) -> Result<()> {
 
-    let mut despawn_entities: Vec<Entity> = Vec::new();
-    let mut detach_entities = Vec::new();
-    
-    let mut world = ctx.world.active();
-    
-    {
-        let mut hierarchies = world.view_mut::<Hierarchy>(Hierarchy::UID)?;
-        let lifecycles = world.view::<Lifecycle>(Lifecycle::UID)?;
-        
-        // Collect despawned entities
-        for e in &world.query(&[Lifecycle::UID, Hierarchy::UID]) {
-            if !lifecycles[e].alive {
-                despawn_entities.push(e);
-                if let Some(hierarchy) = hierarchies.get_mut(e) {
-                    if let Some(parent) = hierarchy.parent() {
-                        detach_entities.push((parent, e));
-                    }
-                }
-            }
-        }
+    let world = ctx.world.active();
+    let commands = world.commands();
+    let mut hierarchies = world.view_mut::<Hierarchy>(Hierarchy::UID)?;
 
-        // Detach entities
-        for (parent, entity) in detach_entities {
-            for child in Hierarchy::collect_childs(entity, &hierarchies)? {
-                Hierarchy::detach(entity, child, &mut hierarchies)?;
+    for (e, lifecycle) in &mut world.query_mut::<(Entity, &Lifecycle)>()? {
+        if !lifecycle.alive {
+            // Detach entity
+            if let Some(parent) = hierarchies.get(e).and_then(|hierarchy| hierarchy.parent()) {
+                for child in Hierarchy::collect_childs(e, &hierarchies)? {
+                    Hierarchy::detach(e, child, &mut hierarchies)?;
+                }
+                Hierarchy::detach(parent, e, &mut hierarchies)?;
             }
-            Hierarchy::detach(parent, entity, &mut hierarchies)?;
+            // Despawned when the system returns
+            commands.destroy(e);
         }
     }
 
-    // Despawn entities
-    for entity in despawn_entities {
-        world.destroy(entity)?;
-    }
-
     Ok(())
-}
\ No newline at end of file
+}
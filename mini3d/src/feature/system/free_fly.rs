@@ -6,12 +6,9 @@ use crate::{feature::component::{free_fly::FreeFly, transform::Transform}, conte
 pub fn run(ctx: &mut SystemContext) -> Result<()> {
 
     let world = ctx.world.active();
-    let mut transforms = world.view_mut::<Transform>(Transform::UID)?;
-    let mut free_flies = world.view_mut::<FreeFly>(FreeFly::UID)?;
+    let mut query = world.query_mut::<(&mut Transform, &mut FreeFly)>()?;
 
-    for e in &world.query(&[Transform::UID, FreeFly::UID]) {
-        let transform = transforms.get_mut(e).unwrap();
-        let free_fly = free_flies.get_mut(e).unwrap();
+    for (transform, free_fly) in &mut query {
         
         // Check active
         if !free_fly.active { continue; }
@@ -1,31 +1,184 @@
 use anyhow::Result;
 
-use crate::{feature::component::{rhai_scripts::RhaiScripts, script_storage::ScriptStorage}, context::SystemContext};
+use crate::{feature::component::{rhai_scripts::{RhaiScripts, RhaiScriptStatus}, script_storage::ScriptStorage, lifecycle::Lifecycle}, context::{SystemContext, time::TimeContext, world::WorldContext}, rhai::{binding::BindingScope, input::InputManagerHandle, graphics::GraphicsHandle, script_storage::ScriptStorageHandle, world::WorldHandle}, input::InputManager, renderer::graphics::Graphics, ecs::entity::Entity};
+
+/// Scope of the callbacks of the scripts of an entity
+fn entity_scope<'a, 'b>(bindings: &mut BindingScope<'a>, input: &'a mut InputManager, screen: &'a mut Graphics, world: &'a mut WorldContext<'b>, time: &TimeContext, entity: Entity) -> rhai::Scope<'static> {
+    let mut scope = rhai::Scope::new();
+    let world = WorldHandle::bind(bindings, world);
+    scope.push_constant("INPUT", InputManagerHandle::bind(bindings, input));
+    scope.push_constant("GFX", GraphicsHandle::bind(bindings, screen, Some(&world)));
+    scope.push_constant("WORLD", world);
+    scope.push_constant("TIME", time.clone());
+    scope.push_constant("ENTITY", entity);
+    scope
+}
 
 pub fn update_scripts(ctx: &mut SystemContext) -> Result<()> {
-    let world = ctx.world.active();
-    let scripts = world.view::<RhaiScripts>(RhaiScripts::UID)?;
-    let storages = world.view::<ScriptStorage>(ScriptStorage::UID)?;
-
-    // for e in &world.query(&[RhaiScripts::UID]) {
-    //     let mut scope = rhai::Scope::new();
-    //     scope.push_constant("INPUT", <InputManagerHandle>::from(&mut *ctx));
-    //     if let Some(storage) = storages.get(e) {
-    //         scope.push_constant("STORAGE", <ScriptStorageHandle>::from(storage));
-    //     }
-    //     for instance in &mut scripts.instances {
-    //         match instance {
-    //             Some(instance) => {
-    //                 if instance.status == RhaiScriptStatus::Starting {
-    //                     ctx.script.rhai.call(instance.uid, ctx.asset, &mut scope, "start")?;
-    //                     instance.status = RhaiScriptStatus::Updating;
-    //                 }
-    //                 ctx.script.rhai.call(instance.uid, ctx.asset, &mut scope, "update")?;
-    //             },
-    //             None => {}
-    //         }
-    //     }
-    // }
+    let mut script = ctx.script.manager.borrow_mut();
+    let script = &mut *script;
+    let asset = &*ctx.asset.manager;
+    let entities = ctx.world.active().query(&[RhaiScripts::UID]).iter().collect::<Vec<_>>();
+
+    for e in entities {
+        // Instances and storage are taken out of the world while the callbacks can access it
+        let (mut instances, mut storage, alive) = {
+            let world = ctx.world.active();
+            if !world.is_alive(e) {
+                continue;
+            }
+            let Some(mut scripts) = world.get_mut::<RhaiScripts>(e, RhaiScripts::UID)? else {
+                continue;
+            };
+            let instances = std::mem::take(&mut scripts.instances);
+            drop(scripts);
+            let storage = world.get_mut::<ScriptStorage>(e, ScriptStorage::UID)?.map(|mut storage| std::mem::take(&mut *storage));
+            let alive = world.get::<Lifecycle>(e, Lifecycle::UID)?.is_none_or(|lifecycle| lifecycle.alive);
+            (instances, storage, alive)
+        };
+        {
+            let mut bindings = BindingScope::new();
+            let mut scope = entity_scope(&mut bindings, &mut *ctx.input.manager, ctx.renderer.graphics(), &mut ctx.world, &ctx.time, e);
+            if let Some(storage) = &mut storage {
+                scope.push_constant("STORAGE", ScriptStorageHandle::bind(&mut bindings, storage));
+            }
+            for instance in &mut instances {
+                // Stop running scripts of despawned entities
+                if !alive {
+                    if instance.status != RhaiScriptStatus::Starting {
+                        script.rhai.call(instance.uid, asset, &mut scope, "stop");
+                    }
+                    continue;
+                }
+                match instance.status {
+                    RhaiScriptStatus::Starting => {
+                        script.rhai.call(instance.uid, asset, &mut scope, "start");
+                        instance.status = RhaiScriptStatus::Updating;
+                        instance.revision = Some(script.rhai.revision(instance.uid));
+                        script.rhai.call(instance.uid, asset, &mut scope, "update");
+                    },
+                    RhaiScriptStatus::Updating => {
+                        // Notify running instances of reloaded scripts
+                        let revision = script.rhai.revision(instance.uid);
+                        if instance.revision.replace(revision).is_some_and(|previous| previous != revision) {
+                            script.rhai.call_optional(instance.uid, asset, &mut scope, "on_reload");
+                        }
+                        script.rhai.call(instance.uid, asset, &mut scope, "update");
+                    },
+                    RhaiScriptStatus::Stopping => {
+                        script.rhai.call(instance.uid, asset, &mut scope, "stop");
+                    },
+                }
+            }
+        }
+        if alive {
+            instances.retain(|instance| instance.status != RhaiScriptStatus::Stopping);
+        } else {
+            instances.clear();
+        }
+        // Give the instances back, unless the callbacks removed the component
+        let world = ctx.world.active();
+        let scripts = if world.is_alive(e) { world.get_mut::<RhaiScripts>(e, RhaiScripts::UID)? } else { None };
+        if let Some(mut scripts) = scripts {
+            // Scripts added by the callbacks run after the existing ones
+            instances.append(&mut scripts.instances);
+            scripts.instances = instances;
+            if let (Some(storage), Some(mut current)) = (storage, world.get_mut::<ScriptStorage>(e, ScriptStorage::UID)?) {
+                *current = storage;
+            }
+        } else {
+            script.removed_rhai_scripts.borrow_mut().extend(instances.into_iter()
+                .filter(|instance| instance.status != RhaiScriptStatus::Starting)
+                .map(|instance| (e, instance)));
+        }
+    }
+
+    // Stop the scripts of removed components, their entity may no longer be alive
+    loop {
+        let removed = std::mem::take(&mut *script.removed_rhai_scripts.borrow_mut());
+        if removed.is_empty() {
+            break;
+        }
+        for (e, instance) in removed {
+            let mut bindings = BindingScope::new();
+            let mut scope = entity_scope(&mut bindings, &mut *ctx.input.manager, ctx.renderer.graphics(), &mut ctx.world, &ctx.time, e);
+            script.rhai.call(instance.uid, asset, &mut scope, "stop");
+        }
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::{engine::Engine, context::SystemContext, event::Events, request::Requests, registry::component::DynamicComponentDefinition, ecs::procedure::Procedure, uid::UID, feature::{asset::{rhai_script::RhaiScript, system_group::{SystemGroup, SystemPipeline}, runtime_component::{FieldType, FieldValue}}, component::rhai_scripts::RhaiScripts}};
+
+    const LOG: &str = "log";
+    const SCRIPT: &str = r#"
+// Script functions don't see the scope constants, they are given by the callbacks
+fn count(world, field) {
+    let log = world.find_entity("log");
+    world.set_field(log, "log", field, world.get_field(log, "log", field) + 1);
+}
+fn start() { if WORLD.is_alive(ENTITY) { count(WORLD, "starts"); } }
+fn update() {}
+fn stop() { count(WORLD, "stops"); }
+"#;
+
+    fn init(ctx: &mut SystemContext) -> Result<()> {
+        let bundle = ctx.asset.add_bundle("test")?;
+        ctx.asset.add(RhaiScript::UID, "script", bundle, RhaiScript { source: SCRIPT.into() })?;
+        let mut definition = DynamicComponentDefinition::default();
+        definition.add_field("starts", FieldType::Integer)?;
+        definition.add_field("stops", FieldType::Integer)?;
+        ctx.registry.define_dynamic_component(LOG, definition)?;
+        ctx.registry.define_static_system("destroy", destroy)?;
+        let mut group = SystemGroup::empty();
+        group.insert(Procedure::UPDATE, SystemPipeline::new(&[UID::new("rhai_update_scripts"), UID::new("destroy")]), 0);
+        ctx.scheduler.add_group("test", group)?;
+        let world = ctx.world.add("test")?;
+        ctx.world.change(world)?;
+        let mut world = ctx.world.get(world)?;
+        let log = world.create();
+        world.set_name(log, LOG)?;
+        world.add_dynamic(log, LOG.into())?;
+        for _ in 0..2 {
+            let entity = world.create();
+            let mut scripts = RhaiScripts::default();
+            scripts.add("script".into())?;
+            world.add(entity, RhaiScripts::UID, scripts)?;
+        }
+        Ok(())
+    }
+
+    // Destroy one scripted entity immediately and the other one with a command
+    fn destroy(ctx: &mut SystemContext) -> Result<()> {
+        let mut world = ctx.world.active();
+        let entities = world.query(&[RhaiScripts::UID]).iter().collect::<Vec<_>>();
+        if let [immediate, command] = entities[..] {
+            world.commands().destroy(command);
+            world.destroy(immediate)?;
+        }
+        Ok(())
+    }
+
+    fn field(engine: &Engine, name: &str) -> FieldValue {
+        let worlds = engine.ecs.worlds.borrow();
+        let world = worlds.get(&UID::new("test")).unwrap().borrow();
+        let log = world.find(LOG).unwrap();
+        world.get_field(&engine.registry.borrow().components, log, LOG.into(), name).unwrap().unwrap()
+    }
+
+    #[test]
+    fn destroyed_entities_stop_their_scripts() {
+        let mut engine = Engine::new(init).unwrap();
+        for _ in 0..3 {
+            engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap();
+        }
+        assert_eq!(engine.script.borrow().rhai.iter_faults().map(|(_, fault)| fault.to_owned()).collect::<Vec<_>>(), Vec::<String>::new());
+        assert_eq!(field(&engine, "starts"), FieldValue::Integer(2));
+        assert_eq!(field(&engine, "stops"), FieldValue::Integer(2));
+    }
+}
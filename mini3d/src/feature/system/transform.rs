@@ -1,67 +1,125 @@
+use std::collections::HashSet;
+
 use anyhow::Result;
 use glam::Mat4;
 
-use crate::{feature::component::{local_to_world::LocalToWorld, hierarchy::Hierarchy, transform::Transform}, ecs::{entity::Entity, view::{ComponentViewRef, ComponentViewMut, ComponentView}}, context::SystemContext};
+use crate::{feature::component::{local_to_world::LocalToWorld, hierarchy::Hierarchy, transform::Transform}, ecs::{entity::Entity, view::{ComponentViewRef, ComponentViewMut, ComponentView}, query::{With, Or, Changed, Added}}, context::SystemContext};
 
 pub fn recursive_propagate(
-    entity: Entity, 
+    entity: Entity,
+    parent_matrix: Mat4,
     transforms: &ComponentViewRef<Transform>,
     local_to_worlds: &mut ComponentViewMut<LocalToWorld>,
     hierarchies: &ComponentViewRef<Hierarchy>,
-) -> Mat4 {
-    if let Some(mut local_to_world) = local_to_worlds.get_mut(entity).cloned() {
-        if !local_to_world.dirty {
-            return local_to_world.matrix;
-        } else if let Some(hierarchy) = hierarchies.get(entity) {
-            if let Some(parent) = hierarchy.parent() {
-                let parent_matrix = recursive_propagate(parent, transforms, local_to_worlds, hierarchies);
-                local_to_world.matrix = parent_matrix * transforms[entity].matrix();
-            } else {
-                local_to_world.matrix = transforms[entity].matrix();
+    updated: &mut HashSet<Entity>,
+) -> Result<()> {
+    let matrix = parent_matrix * transforms.get(entity).map_or(Mat4::IDENTITY, Transform::matrix);
+    local_to_worlds[entity].matrix = matrix;
+    updated.insert(entity);
+    if hierarchies.get(entity).is_some() {
+        for child in Hierarchy::collect_childs(entity, hierarchies)? {
+            if local_to_worlds.get(child).is_some() {
+                recursive_propagate(child, matrix, transforms, local_to_worlds, hierarchies, updated)?;
             }
-        } else {
-            local_to_world.matrix = transforms[entity].matrix();
         }
-        local_to_world.dirty = false;
-        let matrix = local_to_world.matrix;
-        local_to_worlds[entity] = local_to_world;
-        matrix
-    } else {
-        panic!("Entity not found");
     }
+    Ok(())
 }
 
 pub fn propagate(ctx: &mut SystemContext) -> Result<()> {
-    
+
     let world = ctx.world.active();
+
+    // Collect entities moved or re-parented since the last run. Removed components are
+    // taken from the removal log, which only holds the removals of the current frame.
+    let mut dirty = world.query_filtered::<Entity, (With<LocalToWorld>, Or<(Changed<Transform>, Changed<Hierarchy>, Added<LocalToWorld>)>)>()?
+        .iter_mut().collect::<HashSet<_>>();
+    for e in world.removed(Hierarchy::UID).into_iter().chain(world.removed(Transform::UID)) {
+        if world.is_alive(e) && world.contains(e, LocalToWorld::UID)? {
+            dirty.insert(e);
+        }
+    }
+
     let transforms = world.view::<Transform>(Transform::UID)?;
     let hierarchies = world.view::<Hierarchy>(Hierarchy::UID)?;
     let mut local_to_worlds = world.view_mut::<LocalToWorld>(LocalToWorld::UID)?;
 
-    // Reset all flags
-    let mut entities = Vec::new();
-    for e in &world.query(&[LocalToWorld::UID]) {
-        local_to_worlds[e].dirty = true;
-        entities.push(e);
-    }
-
-    for e in entities {
-        let mut local_to_world = local_to_worlds.get_mut(e).cloned().unwrap();
-        if local_to_world.dirty {
-            if let Some(hierarcy) = hierarchies.get(e) {
-                if let Some(parent) = hierarcy.parent() {
-                    let parent_matrix = recursive_propagate(parent, &transforms, &mut local_to_worlds, &hierarchies);
-                    local_to_world.matrix = parent_matrix * transforms[e].matrix();
-                } else {
-                    local_to_world.matrix = transforms[e].matrix();
-                }
-            } else {
-                local_to_world.matrix = transforms[e].matrix();
+    let mut updated = HashSet::new();
+    for e in &dirty {
+        // Start from the highest dirty ancestor, its childs are updated recursively
+        let mut root = *e;
+        let mut current = *e;
+        while let Some(parent) = hierarchies.get(current).and_then(|hierarchy| hierarchy.parent()) {
+            if dirty.contains(&parent) {
+                root = parent;
             }
-            local_to_world.dirty = false;
-            local_to_worlds[e] = local_to_world;
+            current = parent;
         }
+        if updated.contains(&root) {
+            continue;
+        }
+        let parent_matrix = hierarchies.get(root)
+            .and_then(|hierarchy| hierarchy.parent())
+            .and_then(|parent| local_to_worlds.get(parent))
+            .map_or(Mat4::IDENTITY, |local_to_world| local_to_world.matrix);
+        recursive_propagate(root, parent_matrix, &transforms, &mut local_to_worlds, &hierarchies, &mut updated)?;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use glam::Vec3;
+
+    use crate::{engine::Engine, context::SystemContext, event::Events, request::Requests, ecs::procedure::Procedure, uid::UID, feature::{asset::system_group::{SystemGroup, SystemPipeline}, component::{hierarchy::Hierarchy, local_to_world::LocalToWorld, transform::Transform}}};
+
+    fn init(ctx: &mut SystemContext) -> Result<()> {
+        ctx.registry.define_static_system("detach", detach)?;
+        let mut group = SystemGroup::empty();
+        group.insert(Procedure::UPDATE, SystemPipeline::new(&[UID::new("detach"), UID::new("transform_propagate")]), 0);
+        ctx.scheduler.add_group("test", group)?;
+        let world = ctx.world.add("test")?;
+        ctx.world.change(world)?;
+        let mut world = ctx.world.get(world)?;
+        let parent = world.create();
+        let child = world.create();
+        world.set_name(child, "child")?;
+        for (entity, translation) in [(parent, Vec3::X), (child, Vec3::Y)] {
+            world.add(entity, Transform::UID, Transform::from_translation(translation))?;
+            world.add(entity, LocalToWorld::UID, LocalToWorld::default())?;
+            world.add(entity, Hierarchy::UID, Hierarchy::default())?;
+        }
+        Hierarchy::attach(parent, child, &mut world.view_mut(Hierarchy::UID)?)?;
+        Ok(())
+    }
+
+    // Remove the hierarchy of the child once it was placed under its parent
+    fn detach(ctx: &mut SystemContext) -> Result<()> {
+        let mut world = ctx.world.active();
+        let child = world.find("child").unwrap();
+        let placed = world.get::<LocalToWorld>(child, LocalToWorld::UID)?.unwrap().matrix.w_axis.x == 1.0;
+        if placed && world.contains(child, Hierarchy::UID)? {
+            world.remove(child, Hierarchy::UID)?;
+        }
+        Ok(())
+    }
+
+    fn child_translation(engine: &Engine) -> Vec3 {
+        let worlds = engine.ecs.worlds.borrow();
+        let world = worlds.get(&UID::new("test")).unwrap().borrow();
+        let child = world.find("child").unwrap();
+        let local_to_world = world.get::<LocalToWorld>(child, LocalToWorld::UID).unwrap().unwrap();
+        local_to_world.matrix.w_axis.truncate()
+    }
+
+    #[test]
+    fn removed_hierarchies_detach_children() {
+        let mut engine = Engine::new(init).unwrap();
+        engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap();
+        assert_eq!(child_translation(&engine), Vec3::X + Vec3::Y);
+        engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap();
+        assert_eq!(child_translation(&engine), Vec3::Y);
+    }
+}
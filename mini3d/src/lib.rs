@@ -6,6 +6,7 @@ pub mod event;
 pub mod feature;
 pub mod renderer;
 pub mod input;
+pub mod lua;
 pub mod math;
 pub mod registry;
 pub mod physics;
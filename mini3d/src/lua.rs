@@ -0,0 +1,185 @@
+use std::{collections::{HashMap, hash_map}, cell::{Cell, RefCell}, rc::Rc};
+
+use anyhow::{Result, anyhow, Context};
+
+use crate::{asset::AssetManager, uid::UID, feature::asset::lua_script::LuaScript, context::SystemContext, rhai::RhaiScriptLimits};
+
+use self::{input::LuaInputHandle, world::LuaWorldHandle, graphics::LuaGraphicsHandle, registry::LuaRegistryHandle};
+
+pub mod graphics;
+pub mod input;
+pub mod math;
+pub mod registry;
+pub mod transform;
+pub mod world;
+
+const SYSTEM_ENTRY_POINT: &str = "run";
+/// Instructions run between two checks of the instruction budget
+const BUDGET_CHECK_INTERVAL: u32 = 1000;
+
+pub(crate) fn runtime_error(err: impl ToString) -> mlua::Error {
+    mlua::Error::RuntimeError(err.to_string())
+}
+
+struct CompiledLuaScript {
+    name: String,
+    environment: mlua::RegistryKey,
+}
+
+pub struct LuaScriptCache {
+    lua: mlua::Lua,
+    scripts: HashMap<UID, CompiledLuaScript>,
+    faults: HashMap<UID, String>,
+    /// Maximum number of instructions of a single script call (0 for unlimited)
+    max_instructions: u64,
+    /// Instructions left to the running call
+    remaining_instructions: Rc<Cell<u64>>,
+}
+
+impl Default for LuaScriptCache {
+    fn default() -> Self {
+        let lua = mlua::Lua::new();
+        (|| -> mlua::Result<()> {
+            graphics::register_globals(&lua)?;
+            transform::register_globals(&lua)?;
+            Ok(())
+        })().expect("Failed to register lua globals");
+        // Scripts stuck in a loop fail like rhai scripts exceeding their operations
+        let remaining_instructions = Rc::new(Cell::new(0u64));
+        let remaining = remaining_instructions.clone();
+        lua.set_hook(mlua::HookTriggers::new().every_nth_instruction(BUDGET_CHECK_INTERVAL), move |_, _| {
+            let instructions = remaining.get().checked_sub(BUDGET_CHECK_INTERVAL as u64).ok_or_else(|| runtime_error("Too many instructions"))?;
+            remaining.set(instructions);
+            Ok(())
+        });
+        Self {
+            lua,
+            scripts: Default::default(),
+            faults: Default::default(),
+            max_instructions: RhaiScriptLimits::default().max_operations,
+            remaining_instructions,
+        }
+    }
+}
+
+impl LuaScriptCache {
+
+    pub fn max_instructions(&self) -> u64 {
+        self.max_instructions
+    }
+
+    pub fn set_max_instructions(&mut self, max_instructions: u64) {
+        self.max_instructions = max_instructions;
+    }
+
+    fn reset_budget(&self) {
+        self.remaining_instructions.set(if self.max_instructions == 0 { u64::MAX } else { self.max_instructions });
+    }
+
+    pub fn fault(&self, uid: UID) -> Option<&str> {
+        self.faults.get(&uid).map(String::as_str)
+    }
+
+    pub fn iter_faults(&self) -> impl Iterator<Item = (UID, &str)> {
+        self.faults.iter().map(|(uid, fault)| (*uid, fault.as_str()))
+    }
+
+    /// Allow a faulted script to run again
+    pub fn reset_fault(&mut self, uid: UID) {
+        self.faults.remove(&uid);
+    }
+
+    /// Drop the compiled script and its fault. The script is reloaded
+    /// from the current asset on the next run.
+    pub(crate) fn invalidate(&mut self, uid: UID) {
+        if let Some(script) = self.scripts.remove(&uid) {
+            let _ = self.lua.remove_registry_value(script.environment);
+        }
+        self.faults.remove(&uid);
+    }
+
+    fn compile(&mut self, uid: UID, asset: &AssetManager) -> Result<()> {
+        // The top level code of the script has its own budget
+        self.reset_budget();
+        if let hash_map::Entry::Vacant(e) = self.scripts.entry(uid) {
+            let entry = asset.entry::<LuaScript>(LuaScript::UID, uid)?
+                .with_context(|| "Lua script not found")?;
+            // Each script runs in its own environment with access to the globals
+            let environment = (|| -> mlua::Result<mlua::RegistryKey> {
+                let environment = self.lua.create_table()?;
+                let metatable = self.lua.create_table()?;
+                metatable.set("__index", self.lua.globals())?;
+                environment.set_metatable(Some(metatable));
+                self.lua.load(entry.asset.source.as_str())
+                    .set_name(entry.name.as_str())
+                    .set_environment(environment.clone())
+                    .exec()?;
+                self.lua.create_registry_value(environment)
+            })().map_err(|err| anyhow!("Failed to load lua script '{}': {}", entry.name, err))?;
+            e.insert(CompiledLuaScript { name: entry.name.clone(), environment });
+        }
+        Ok(())
+    }
+
+    /// Like rhai systems, a lua system that fails is marked as faulted and
+    /// skipped by later runs until its fault is reset.
+    pub(crate) fn run_system(&mut self, uid: UID, ctx: &mut SystemContext) {
+        if self.faults.contains_key(&uid) {
+            return;
+        }
+        if let Err(err) = self.try_run_system(uid, ctx) {
+            self.faults.insert(uid, err.to_string());
+        }
+    }
+
+    fn try_run_system(&mut self, uid: UID, ctx: &mut SystemContext) -> Result<()> {
+        // Lazy script compilation
+        self.compile(uid, ctx.asset.manager)?;
+        // Call script
+        let script = self.scripts.get(&uid).unwrap();
+        // Shared by the world and the canvas targets of the graphics
+        let world = RefCell::new(&mut ctx.world);
+        self.reset_budget();
+        self.lua.scope(|scope| {
+            let environment: mlua::Table = self.lua.registry_value(&script.environment)?;
+            let time = self.lua.create_table()?;
+            time.set("delta", ctx.time.delta())?;
+            time.set("global", ctx.time.global())?;
+            environment.set("INPUT", scope.create_nonstatic_userdata(LuaInputHandle { manager: ctx.input.manager })?)?;
+            environment.set("WORLD", scope.create_nonstatic_userdata(LuaWorldHandle { world: &world })?)?;
+            environment.set("GFX", scope.create_nonstatic_userdata(LuaGraphicsHandle { screen: ctx.renderer.graphics(), world: &world, canvas: None })?)?;
+            environment.set("REGISTRY", scope.create_nonstatic_userdata(LuaRegistryHandle { registry: &ctx.registry })?)?;
+            environment.set("TIME", time)?;
+            let function: mlua::Function = environment.get(SYSTEM_ENTRY_POINT)?;
+            function.call::<_, ()>(())
+        }).map_err(|err| {
+            anyhow!("Lua script '{}' failed in '{}': {}", script.name, SYSTEM_ENTRY_POINT, err)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::{engine::Engine, context::SystemContext, event::Events, request::Requests, ecs::procedure::Procedure, uid::UID, feature::asset::{lua_script::LuaScript, system_group::{SystemGroup, SystemPipeline}}};
+
+    fn init(ctx: &mut SystemContext) -> Result<()> {
+        let bundle = ctx.asset.add_bundle("test")?;
+        ctx.asset.add(LuaScript::UID, "loop", bundle, LuaScript { source: "function run() while true do end end".into() })?;
+        ctx.registry.define_lua_system("loop", "loop".into())?;
+        let mut group = SystemGroup::empty();
+        group.insert(Procedure::UPDATE, SystemPipeline::single("loop".into()), 0);
+        ctx.scheduler.add_group("test", group)?;
+        Ok(())
+    }
+
+    #[test]
+    fn endless_loops_exceed_the_budget() {
+        let mut engine = Engine::new(init).unwrap();
+        engine.progress(&Events::new(), &mut Requests::default(), 0.016).unwrap();
+        let script = engine.script.borrow();
+        let fault = script.lua.fault(UID::new("loop")).unwrap();
+        assert!(fault.contains("Too many instructions"), "{}", fault);
+    }
+}
@@ -0,0 +1,146 @@
+use std::cell::RefCell;
+
+use glam::IVec2;
+use mlua::{UserData, UserDataFields, UserDataMethods, FromLua, Lua, MetaMethod, Value};
+
+use crate::{renderer::{graphics::Graphics, color::Color}, math::rect::IRect, context::world::WorldContext, feature::component::canvas::Canvas, ecs::entity::Entity};
+
+use super::runtime_error;
+
+const DEFAULT_FONT: &str = "default";
+
+/// Records graphics commands into the screen or into the canvas component selected with `canvas`
+pub(crate) struct LuaGraphicsHandle<'a, 'b> {
+    pub(crate) screen: &'a mut Graphics,
+    pub(crate) world: &'a RefCell<&'a mut WorldContext<'b>>,
+    pub(crate) canvas: Option<Entity>,
+}
+
+impl<'a, 'b> LuaGraphicsHandle<'a, 'b> {
+
+    fn record(&mut self, command: impl FnOnce(&mut Graphics)) -> mlua::Result<()> {
+        if let Some(entity) = self.canvas {
+            let mut world = self.world.borrow_mut();
+            let world = world.active();
+            let mut canvas = world.get_mut::<Canvas>(entity, Canvas::UID).map_err(runtime_error)?
+                .ok_or_else(|| runtime_error(format!("Canvas not found for {:?}", entity)))?;
+            command(&mut canvas.graphics);
+        } else {
+            command(self.screen);
+        }
+        Ok(())
+    }
+}
+
+fn channel(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+fn size(value: i32) -> u32 {
+    value.max(0) as u32
+}
+
+impl UserData for Color {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("r", |_, color| Ok(color.r()));
+        fields.add_field_method_get("g", |_, color| Ok(color.g()));
+        fields.add_field_method_get("b", |_, color| Ok(color.b()));
+        fields.add_field_method_get("a", |_, color| Ok(color.a()));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Eq, |_, color, other: Color| Ok(*color == other));
+        methods.add_meta_method(MetaMethod::ToString, |_, color, ()| Ok(format!("{:?}", color)));
+    }
+}
+
+impl<'lua> FromLua<'lua> for Color {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+        match &value {
+            Value::UserData(data) => Ok(*data.borrow::<Color>()?),
+            _ => Err(mlua::Error::FromLuaConversionError { from: value.type_name(), to: "Color", message: None }),
+        }
+    }
+}
+
+impl UserData for IRect {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("x", |_, rect| Ok(rect.left()));
+        fields.add_field_method_get("y", |_, rect| Ok(rect.top()));
+        fields.add_field_method_get("width", |_, rect| Ok(rect.width()));
+        fields.add_field_method_get("height", |_, rect| Ok(rect.height()));
+        fields.add_field_method_get("left", |_, rect| Ok(rect.left()));
+        fields.add_field_method_get("top", |_, rect| Ok(rect.top()));
+        fields.add_field_method_get("right", |_, rect| Ok(rect.right()));
+        fields.add_field_method_get("bottom", |_, rect| Ok(rect.bottom()));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("contains", |_, rect, (x, y): (i32, i32)| Ok(rect.contains(IVec2::new(x, y))));
+        methods.add_method_mut("translate", |_, rect, (x, y): (i32, i32)| {
+            rect.translate(IVec2::new(x, y));
+            Ok(())
+        });
+        methods.add_meta_method(MetaMethod::ToString, |_, rect, ()| Ok(format!("{:?}", rect)));
+    }
+}
+
+impl<'lua> FromLua<'lua> for IRect {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+        match &value {
+            Value::UserData(data) => Ok(*data.borrow::<IRect>()?),
+            _ => Err(mlua::Error::FromLuaConversionError { from: value.type_name(), to: "Rect", message: None }),
+        }
+    }
+}
+
+pub(crate) fn register_globals(lua: &Lua) -> mlua::Result<()> {
+    lua.globals().set("color", lua.create_function(|_, (r, g, b, a): (i32, i32, i32, Option<i32>)| {
+        Ok(Color::rgba(channel(r), channel(g), channel(b), a.map_or(255, channel)))
+    })?)?;
+    lua.globals().set("rect", lua.create_function(|_, (x, y, width, height): (i32, i32, i32, i32)| {
+        Ok(IRect::new(x, y, size(width), size(height)))
+    })?)?;
+    Ok(())
+}
+
+impl<'a, 'b> UserData for LuaGraphicsHandle<'a, 'b> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Handles only live for the script call, the target is selected on the handle itself
+        methods.add_method_mut("canvas", |_, gfx, entity: Entity| {
+            gfx.canvas = Some(entity);
+            Ok(())
+        });
+        methods.add_method_mut("screen", |_, gfx, ()| {
+            gfx.canvas = None;
+            Ok(())
+        });
+        methods.add_method_mut("print_text", |_, gfx, (x, y, text, font): (i32, i32, String, Option<String>)| {
+            gfx.record(|graphics| graphics.print(IVec2::new(x, y), &text, font.as_deref().unwrap_or(DEFAULT_FONT).into()))
+        });
+        methods.add_method_mut("blit_texture", |_, gfx, (texture, extent, x, y, filtering, alpha_threshold): (String, IRect, i32, i32, Option<Color>, Option<i32>)| {
+            gfx.record(|graphics| graphics.blit_texture(texture.as_str().into(), extent, IVec2::new(x, y), filtering.unwrap_or(Color::WHITE), alpha_threshold.map_or(0, channel)))
+        });
+        methods.add_method_mut("fill_rect", |_, gfx, (extent, color): (IRect, Color)| {
+            gfx.record(|graphics| graphics.fill_rect(extent, color))
+        });
+        methods.add_method_mut("draw_rect", |_, gfx, (extent, color): (IRect, Color)| {
+            gfx.record(|graphics| graphics.draw_rect(extent, color))
+        });
+        methods.add_method_mut("draw_line", |_, gfx, (x0, y0, x1, y1, color): (i32, i32, i32, i32, Color)| {
+            gfx.record(|graphics| graphics.draw_line(IVec2::new(x0, y0), IVec2::new(x1, y1), color))
+        });
+        methods.add_method_mut("draw_vline", |_, gfx, (x, y0, y1, color): (i32, i32, i32, Color)| {
+            gfx.record(|graphics| graphics.draw_vline(x, y0, y1, color))
+        });
+        methods.add_method_mut("draw_hline", |_, gfx, (y, x0, x1, color): (i32, i32, i32, Color)| {
+            gfx.record(|graphics| graphics.draw_hline(y, x0, x1, color))
+        });
+        methods.add_method_mut("scissor", |_, gfx, extent: IRect| {
+            gfx.record(|graphics| graphics.scissor(Some(extent)))
+        });
+        methods.add_method_mut("clear_scissor", |_, gfx, ()| {
+            gfx.record(|graphics| graphics.scissor(None))
+        });
+    }
+}
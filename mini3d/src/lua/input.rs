@@ -0,0 +1,29 @@
+use mlua::{UserData, UserDataMethods};
+
+use crate::input::InputManager;
+
+use super::runtime_error;
+
+pub(crate) struct LuaInputHandle<'a> {
+    pub(crate) manager: &'a InputManager,
+}
+
+impl<'a> UserData for LuaInputHandle<'a> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("action_pressed", |_, input, name: String| {
+            Ok(input.manager.action(name.as_str().into()).map_err(runtime_error)?.is_pressed())
+        });
+        methods.add_method("action_released", |_, input, name: String| {
+            Ok(input.manager.action(name.as_str().into()).map_err(runtime_error)?.is_released())
+        });
+        methods.add_method("action_just_pressed", |_, input, name: String| {
+            Ok(input.manager.action(name.as_str().into()).map_err(runtime_error)?.is_just_pressed())
+        });
+        methods.add_method("action_just_released", |_, input, name: String| {
+            Ok(input.manager.action(name.as_str().into()).map_err(runtime_error)?.is_just_released())
+        });
+        methods.add_method("axis_value", |_, input, name: String| {
+            Ok(input.manager.axis(name.as_str().into()).map_err(runtime_error)?.value)
+        });
+    }
+}
\ No newline at end of file
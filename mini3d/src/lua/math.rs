@@ -0,0 +1,48 @@
+use glam::{Vec2, Vec3, Vec4, Quat};
+use mlua::{Lua, Table, Value};
+
+/// Vectors and quaternions are sequences of numbers in scripts, like in serialized components
+fn to_table<'lua>(lua: &'lua Lua, values: &[f32]) -> mlua::Result<Table<'lua>> {
+    lua.create_sequence_from(values.iter().copied())
+}
+
+fn from_table<const N: usize>(value: Value, name: &'static str) -> mlua::Result<[f32; N]> {
+    let error = || mlua::Error::FromLuaConversionError { from: value.type_name(), to: name, message: Some(format!("expected a sequence of {} numbers", N)) };
+    let values = match &value {
+        Value::Table(table) => table.clone().sequence_values::<f32>().collect::<mlua::Result<Vec<_>>>()?,
+        _ => return Err(error()),
+    };
+    values.try_into().map_err(|_| error())
+}
+
+pub(crate) fn vec2_to_lua(lua: &Lua, value: Vec2) -> mlua::Result<Table<'_>> {
+    to_table(lua, &value.to_array())
+}
+
+pub(crate) fn vec3_to_lua(lua: &Lua, value: Vec3) -> mlua::Result<Table<'_>> {
+    to_table(lua, &value.to_array())
+}
+
+pub(crate) fn vec4_to_lua(lua: &Lua, value: Vec4) -> mlua::Result<Table<'_>> {
+    to_table(lua, &value.to_array())
+}
+
+pub(crate) fn quat_to_lua(lua: &Lua, value: Quat) -> mlua::Result<Table<'_>> {
+    to_table(lua, &value.to_array())
+}
+
+pub(crate) fn lua_to_vec2(value: Value) -> mlua::Result<Vec2> {
+    from_table(value, "Vec2").map(Vec2::from_array)
+}
+
+pub(crate) fn lua_to_vec3(value: Value) -> mlua::Result<Vec3> {
+    from_table(value, "Vec3").map(Vec3::from_array)
+}
+
+pub(crate) fn lua_to_vec4(value: Value) -> mlua::Result<Vec4> {
+    from_table(value, "Vec4").map(Vec4::from_array)
+}
+
+pub(crate) fn lua_to_quat(value: Value) -> mlua::Result<Quat> {
+    from_table(value, "Quat").map(Quat::from_array)
+}
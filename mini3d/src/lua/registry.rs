@@ -0,0 +1,36 @@
+use mlua::{UserData, UserDataMethods, Table};
+
+use crate::{context::registry::RegistryContext, registry::component::DynamicComponentDefinition, feature::asset::runtime_component::FieldType};
+
+use super::runtime_error;
+
+pub(crate) struct LuaRegistryHandle<'a, 'b> {
+    pub(crate) registry: &'a RegistryContext<'b>,
+}
+
+fn parse_field(field: Table) -> mlua::Result<(String, FieldType)> {
+    if field.raw_len() != 2 {
+        return Err(runtime_error("Field must be declared as {name, type}"));
+    }
+    let name: String = field.get(1)?;
+    let ty: String = field.get(2)?;
+    let ty = serde_json::from_value::<FieldType>(serde_json::Value::String(ty.clone()))
+        .map_err(|_| runtime_error(format!("Unknown field type '{}'", ty)))?;
+    Ok((name, ty))
+}
+
+impl<'a, 'b> UserData for LuaRegistryHandle<'a, 'b> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Define a dynamic component from a list of {name, type} fields
+        methods.add_method("define_component", |_, handle, (name, fields): (String, Table)| {
+            let mut definition = DynamicComponentDefinition::default();
+            for field in fields.sequence_values::<Table>() {
+                let (field, ty) = parse_field(field?)?;
+                definition.add_field(&field, ty).map_err(runtime_error)?;
+            }
+            handle.registry.define_dynamic_component(&name, definition)
+                .map_err(|err| runtime_error(format!("Failed to define component '{}': {}", name, err)))?;
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,73 @@
+use glam::Quat;
+use mlua::{UserData, UserDataFields, UserDataMethods, FromLua, Lua, MetaMethod, Value};
+
+use crate::feature::component::transform::Transform;
+
+use super::math::{vec3_to_lua, quat_to_lua, lua_to_vec3, lua_to_quat};
+
+impl UserData for Transform {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("translation", |lua, t| vec3_to_lua(lua, t.translation));
+        fields.add_field_method_set("translation", |_, t, value: Value| { t.translation = lua_to_vec3(value)?; Ok(()) });
+        fields.add_field_method_get("rotation", |lua, t| quat_to_lua(lua, t.rotation));
+        fields.add_field_method_set("rotation", |_, t, value: Value| { t.rotation = lua_to_quat(value)?; Ok(()) });
+        fields.add_field_method_get("scale", |lua, t| vec3_to_lua(lua, t.scale));
+        fields.add_field_method_set("scale", |_, t, value: Value| { t.scale = lua_to_vec3(value)?; Ok(()) });
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("forward", |lua, t, ()| vec3_to_lua(lua, t.forward()));
+        methods.add_method("backward", |lua, t, ()| vec3_to_lua(lua, t.backward()));
+        methods.add_method("up", |lua, t, ()| vec3_to_lua(lua, t.up()));
+        methods.add_method("down", |lua, t, ()| vec3_to_lua(lua, t.down()));
+        methods.add_method("left", |lua, t, ()| vec3_to_lua(lua, t.left()));
+        methods.add_method("right", |lua, t, ()| vec3_to_lua(lua, t.right()));
+        methods.add_method_mut("translate", |_, t, translation: Value| {
+            t.translation += lua_to_vec3(translation)?;
+            Ok(())
+        });
+        methods.add_method_mut("rotate", |_, t, rotation: Value| {
+            t.rotation = (lua_to_quat(rotation)? * t.rotation).normalize();
+            Ok(())
+        });
+        methods.add_method_mut("rotate_x", |_, t, angle: f32| {
+            t.rotation = (Quat::from_rotation_x(angle) * t.rotation).normalize();
+            Ok(())
+        });
+        methods.add_method_mut("rotate_y", |_, t, angle: f32| {
+            t.rotation = (Quat::from_rotation_y(angle) * t.rotation).normalize();
+            Ok(())
+        });
+        methods.add_method_mut("rotate_z", |_, t, angle: f32| {
+            t.rotation = (Quat::from_rotation_z(angle) * t.rotation).normalize();
+            Ok(())
+        });
+        // Rotate around the transform local axis
+        methods.add_method_mut("rotate_local", |_, t, rotation: Value| {
+            t.rotation = (t.rotation * lua_to_quat(rotation)?).normalize();
+            Ok(())
+        });
+        methods.add_meta_method(MetaMethod::ToString, |_, t, ()| {
+            Ok(format!("Transform {{ translation: {:?}, rotation: {:?}, scale: {:?} }}", t.translation, t.rotation, t.scale))
+        });
+    }
+}
+
+impl<'lua> FromLua<'lua> for Transform {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+        match &value {
+            Value::UserData(data) => Ok(data.borrow::<Transform>()?.clone()),
+            _ => Err(mlua::Error::FromLuaConversionError { from: value.type_name(), to: "Transform", message: None }),
+        }
+    }
+}
+
+pub(crate) fn register_globals(lua: &Lua) -> mlua::Result<()> {
+    lua.globals().set("transform", lua.create_function(|_, (translation, rotation, scale): (Value, Value, Value)| {
+        Ok(Transform { translation: lua_to_vec3(translation)?, rotation: lua_to_quat(rotation)?, scale: lua_to_vec3(scale)? })
+    })?)?;
+    lua.globals().set("transform_from_translation", lua.create_function(|_, translation: Value| {
+        Ok(Transform::from_translation(lua_to_vec3(translation)?))
+    })?)?;
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use std::cell::RefCell;
+
+use mlua::{UserData, UserDataMethods, FromLua, IntoLua, Lua, MetaMethod, Table, Value as LuaValue};
+use serde_json::{Value, Number};
+
+use crate::{context::world::{WorldContext, WorldInstanceContext}, ecs::entity::Entity, feature::{component::transform::Transform, asset::runtime_component::{FieldType, FieldValue}}, uid::UID};
+
+use super::{runtime_error, math::{vec2_to_lua, vec3_to_lua, vec4_to_lua, lua_to_vec2, lua_to_vec3, lua_to_vec4}};
+
+impl UserData for Entity {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Eq, |_, entity, other: Entity| Ok(*entity == other));
+        methods.add_meta_method(MetaMethod::ToString, |_, entity, ()| Ok(format!("{:?}", entity)));
+    }
+}
+
+impl<'lua> FromLua<'lua> for Entity {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+        match &value {
+            LuaValue::UserData(data) => Ok(*data.borrow::<Entity>()?),
+            _ => Err(mlua::Error::FromLuaConversionError { from: value.type_name(), to: "Entity", message: None }),
+        }
+    }
+}
+
+pub(crate) struct LuaWorldHandle<'a, 'b> {
+    pub(crate) world: &'a RefCell<&'a mut WorldContext<'b>>,
+}
+
+fn value_to_lua(lua: &Lua, value: Value) -> mlua::Result<LuaValue<'_>> {
+    Ok(match value {
+        Value::Null => LuaValue::Nil,
+        Value::Bool(value) => LuaValue::Boolean(value),
+        Value::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                LuaValue::Integer(value)
+            } else if let Some(value) = number.as_f64().filter(|_| number.is_f64()) {
+                LuaValue::Number(value)
+            } else {
+                // Integers out of the script range (e.g. UID) are kept as strings
+                LuaValue::String(lua.create_string(number.to_string())?)
+            }
+        },
+        Value::String(value) => LuaValue::String(lua.create_string(value)?),
+        Value::Array(values) => {
+            let values = values.into_iter().map(|value| value_to_lua(lua, value)).collect::<mlua::Result<Vec<_>>>()?;
+            LuaValue::Table(lua.create_sequence_from(values)?)
+        },
+        Value::Object(values) => {
+            let table = lua.create_table()?;
+            for (key, value) in values {
+                table.set(key, value_to_lua(lua, value)?)?;
+            }
+            LuaValue::Table(table)
+        },
+    })
+}
+
+/// Tables with a sequence part are arrays, other tables are objects
+fn lua_to_value(value: LuaValue) -> mlua::Result<Value> {
+    match value {
+        LuaValue::Nil => Ok(Value::Null),
+        LuaValue::Boolean(value) => Ok(value.into()),
+        LuaValue::Integer(value) => Ok(value.into()),
+        LuaValue::Number(value) => Number::from_f64(value).map(Value::Number).ok_or_else(|| runtime_error("Invalid float value")),
+        LuaValue::String(value) => Ok(value.to_str()?.into()),
+        LuaValue::Table(table) if table.raw_len() > 0 => {
+            Ok(Value::Array(table.sequence_values::<LuaValue>().map(|value| lua_to_value(value?)).collect::<mlua::Result<_>>()?))
+        },
+        LuaValue::Table(table) => {
+            let mut values = serde_json::Map::new();
+            for pair in table.pairs::<String, LuaValue>() {
+                let (key, value) = pair?;
+                values.insert(key, lua_to_value(value)?);
+            }
+            Ok(Value::Object(values))
+        },
+        value => Err(runtime_error(format!("Unsupported component field type '{}'", value.type_name()))),
+    }
+}
+
+fn field_to_lua(lua: &Lua, value: FieldValue) -> mlua::Result<LuaValue<'_>> {
+    match value {
+        FieldValue::String(value) => value.into_lua(lua),
+        FieldValue::Integer(value) => value.into_lua(lua),
+        FieldValue::Float(value) => value.into_lua(lua),
+        FieldValue::Boolean(value) => value.into_lua(lua),
+        FieldValue::Vec2(value) => vec2_to_lua(lua, value)?.into_lua(lua),
+        FieldValue::Vec3(value) => vec3_to_lua(lua, value)?.into_lua(lua),
+        FieldValue::Vec4(value) => vec4_to_lua(lua, value)?.into_lua(lua),
+        FieldValue::Entity(value) => value.into_lua(lua),
+        FieldValue::Array(values) => {
+            let values = values.into_iter().map(|value| field_to_lua(lua, value)).collect::<mlua::Result<Vec<_>>>()?;
+            lua.create_sequence_from(values)?.into_lua(lua)
+        },
+    }
+}
+
+/// Array elements are not typed by the definition, the type is inferred from the value
+fn infer_field_type(value: &LuaValue) -> mlua::Result<FieldType> {
+    match value {
+        LuaValue::Boolean(_) => Ok(FieldType::Boolean),
+        LuaValue::Integer(_) => Ok(FieldType::Integer),
+        LuaValue::Number(_) => Ok(FieldType::Float),
+        LuaValue::String(_) => Ok(FieldType::String),
+        LuaValue::UserData(data) if data.is::<Entity>() => Ok(FieldType::Entity),
+        LuaValue::Table(_) => Ok(FieldType::Array),
+        value => Err(runtime_error(format!("Unsupported field value type '{}'", value.type_name()))),
+    }
+}
+
+fn lua_to_field(lua: &Lua, value: LuaValue, ty: FieldType) -> mlua::Result<FieldValue> {
+    let type_name = value.type_name();
+    let field = match ty {
+        FieldType::String => String::from_lua(value, lua).map(FieldValue::String),
+        FieldType::Integer => i32::from_lua(value, lua).map(FieldValue::Integer),
+        FieldType::Float => f32::from_lua(value, lua).map(FieldValue::Float),
+        FieldType::Boolean => match value {
+            LuaValue::Boolean(value) => Ok(FieldValue::Boolean(value)),
+            _ => Err(runtime_error("not a boolean")),
+        },
+        FieldType::Vec2 => lua_to_vec2(value).map(FieldValue::Vec2),
+        FieldType::Vec3 => lua_to_vec3(value).map(FieldValue::Vec3),
+        FieldType::Vec4 => lua_to_vec4(value).map(FieldValue::Vec4),
+        FieldType::Entity => Entity::from_lua(value, lua).map(FieldValue::Entity),
+        FieldType::Array => {
+            let values = Table::from_lua(value, lua)?.sequence_values::<LuaValue>()
+                .map(|value| value.and_then(|value| infer_field_type(&value).and_then(|ty| lua_to_field(lua, value, ty))))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            return Ok(FieldValue::Array(values));
+        },
+    };
+    field.map_err(|_| runtime_error(format!("Expected {:?} but got '{}'", ty, type_name)))
+}
+
+fn get_dynamic_component<'lua>(lua: &'lua Lua, world: &WorldInstanceContext, entity: Entity, component: &str) -> mlua::Result<LuaValue<'lua>> {
+    let definition = world.dynamic_definition(component.into()).unwrap();
+    let values = lua.create_table()?;
+    for field in &definition.fields {
+        match world.get_field(entity, component.into(), &field.name).map_err(runtime_error)? {
+            Some(value) => values.set(field.name.as_str(), field_to_lua(lua, value)?)?,
+            None => return Ok(LuaValue::Nil),
+        }
+    }
+    Ok(LuaValue::Table(values))
+}
+
+fn set_dynamic_component(lua: &Lua, world: &mut WorldInstanceContext, entity: Entity, component: &str, data: LuaValue) -> mlua::Result<()> {
+    let definition = world.dynamic_definition(component.into()).cloned().unwrap();
+    let data = match data {
+        LuaValue::Table(data) => data,
+        _ => return Err(runtime_error(format!("Expected a table to write component '{}'", component))),
+    };
+    if !world.contains(entity, component.into()).map_err(runtime_error)? {
+        world.add_dynamic(entity, component.into()).map_err(runtime_error)?;
+    }
+    for pair in data.pairs::<String, LuaValue>() {
+        let (field, value) = pair?;
+        let (_, ty) = definition.field(&field).map_err(runtime_error)?;
+        let value = lua_to_field(lua, value, ty).map_err(|err| runtime_error(format!("Invalid field '{}': {}", field, err)))?;
+        world.set_field(entity, component.into(), &field, value).map_err(runtime_error)?;
+    }
+    Ok(())
+}
+
+/// Apply the script value on the serialized component. The current value
+/// is used as schema to restore arrays and integers given as strings.
+fn patch_value(value: &mut Value, patch: LuaValue) -> mlua::Result<()> {
+    match patch {
+        LuaValue::Table(patch) if value.is_array() || (!value.is_object() && patch.raw_len() > 0) => {
+            if !value.is_array() {
+                *value = Value::Array(Default::default());
+            }
+            let patches = patch.sequence_values::<LuaValue>().collect::<mlua::Result<Vec<_>>>()?;
+            let values = value.as_array_mut().unwrap();
+            values.resize(patches.len(), Value::Null);
+            for (value, patch) in values.iter_mut().zip(patches) {
+                patch_value(value, patch)?;
+            }
+        },
+        LuaValue::Table(patch) => {
+            if !value.is_object() {
+                *value = Value::Object(Default::default());
+            }
+            let values = value.as_object_mut().unwrap();
+            for pair in patch.pairs::<String, LuaValue>() {
+                let (key, patch) = pair?;
+                patch_value(values.entry(key).or_insert(Value::Null), patch)?;
+            }
+        },
+        LuaValue::String(patch) if value.is_number() => {
+            *value = Value::Number(patch.to_str()?.parse::<Number>().map_err(runtime_error)?);
+        },
+        patch => {
+            *value = lua_to_value(patch)?;
+        },
+    }
+    Ok(())
+}
+
+impl<'a, 'b> UserData for LuaWorldHandle<'a, 'b> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("create_entity", |_, handle, ()| {
+            Ok(handle.world.borrow_mut().active().create())
+        });
+        methods.add_method("destroy_entity", |_, handle, entity: Entity| {
+            handle.world.borrow_mut().active().destroy(entity).map_err(runtime_error)
+        });
+        methods.add_method("is_alive", |_, handle, entity: Entity| {
+            Ok(handle.world.borrow_mut().active().is_alive(entity))
+        });
+        methods.add_method("find_entity", |_, handle, name: String| {
+            Ok(handle.world.borrow_mut().active().find(&name))
+        });
+        methods.add_method("entity_name", |_, handle, entity: Entity| {
+            Ok(handle.world.borrow_mut().active().name(entity).map(|name| name.to_string()))
+        });
+        methods.add_method("set_entity_name", |_, handle, (entity, name): (Entity, String)| {
+            handle.world.borrow_mut().active().set_name(entity, &name).map_err(runtime_error)
+        });
+        methods.add_method("remove_entity_name", |_, handle, entity: Entity| {
+            handle.world.borrow_mut().active().remove_name(entity);
+            Ok(())
+        });
+        methods.add_method("query", |_, handle, components: Vec<String>| {
+            let components = components.iter().map(|component| component.as_str().into()).collect::<Vec<UID>>();
+            Ok(handle.world.borrow_mut().active().query(&components).iter().collect::<Vec<_>>())
+        });
+        methods.add_method("has_component", |_, handle, (entity, component): (Entity, String)| {
+            handle.world.borrow_mut().active().contains(entity, component.as_str().into()).map_err(runtime_error)
+        });
+        methods.add_method("get_component", |lua, handle, (entity, component): (Entity, String)| {
+            let mut world = handle.world.borrow_mut();
+            let world = world.active();
+            if world.dynamic_definition(component.as_str().into()).is_some() {
+                return get_dynamic_component(lua, &world, entity, &component);
+            }
+            let value = match world.serialize_component(entity, component.as_str().into()).map_err(runtime_error)? {
+                Some(data) => value_to_lua(lua, serde_json::to_value(&*data).map_err(runtime_error)?)?,
+                None => LuaValue::Nil,
+            };
+            Ok(value)
+        });
+        methods.add_method("set_component", |lua, handle, (entity, component, data): (Entity, String, LuaValue)| {
+            let mut world = handle.world.borrow_mut();
+            let mut world = world.active();
+            if world.dynamic_definition(component.as_str().into()).is_some() {
+                return set_dynamic_component(lua, &mut world, entity, &component, data);
+            }
+            let mut value = match world.serialize_component(entity, component.as_str().into()).map_err(runtime_error)? {
+                Some(current) => serde_json::to_value(&*current).map_err(runtime_error)?,
+                None => Value::Null,
+            };
+            patch_value(&mut value, data)?;
+            world.deserialize_component(entity, component.as_str().into(), &mut <dyn erased_serde::Deserializer>::erase(value))
+                .map_err(|err| runtime_error(format!("Failed to write component '{}': {}", component, err)))
+        });
+        methods.add_method("get_field", |lua, handle, (entity, component, field): (Entity, String, String)| {
+            let mut world = handle.world.borrow_mut();
+            let value = world.active().get_field(entity, component.as_str().into(), &field).map_err(runtime_error)?;
+            value.map_or(Ok(LuaValue::Nil), |value| field_to_lua(lua, value))
+        });
+        methods.add_method("set_field", |lua, handle, (entity, component, field, value): (Entity, String, String, LuaValue)| {
+            let mut world = handle.world.borrow_mut();
+            let mut world = world.active();
+            let (_, ty) = world.dynamic_definition(component.as_str().into())
+                .ok_or_else(|| runtime_error(format!("Component '{}' is not dynamic", component)))?
+                .field(&field).map_err(runtime_error)?;
+            let value = lua_to_field(lua, value, ty)?;
+            world.set_field(entity, component.as_str().into(), &field, value).map_err(runtime_error)
+        });
+        methods.add_method("get_transform", |_, handle, entity: Entity| {
+            let mut world = handle.world.borrow_mut();
+            let world = world.active();
+            let transform = world.get::<Transform>(entity, Transform::UID).map_err(runtime_error)?;
+            Ok(transform.map(|transform| transform.clone()))
+        });
+        methods.add_method("set_transform", |_, handle, (entity, transform): (Entity, Transform)| {
+            let mut world = handle.world.borrow_mut();
+            let mut world = world.active();
+            if let Some(mut current) = world.get_mut::<Transform>(entity, Transform::UID).map_err(runtime_error)? {
+                *current = transform;
+                return Ok(());
+            }
+            world.add(entity, Transform::UID, transform).map_err(runtime_error)
+        });
+        methods.add_method("remove_component", |_, handle, (entity, component): (Entity, String)| {
+            handle.world.borrow_mut().active().remove(entity, component.as_str().into()).map_err(runtime_error)
+        });
+    }
+}
@@ -1,5 +1,9 @@
+use std::{cell::RefCell, rc::Rc};
+
 use anyhow::Result;
-use rapier3d::prelude::{RigidBodySet, ColliderSet, PhysicsPipeline, QueryPipeline, IslandManager, BroadPhase, NarrowPhase, CCDSolver, ImpulseJointSet, MultibodyJointSet};
+use rapier3d::prelude::{RigidBodyHandle, RigidBodySet, ColliderSet, PhysicsPipeline, QueryPipeline, IslandManager, BroadPhase, NarrowPhase, CCDSolver, ImpulseJointSet, MultibodyJointSet};
+
+use crate::{ecs::component::ComponentHooks, feature::component::rigid_body::RigidBody, registry::component::ComponentRegistry};
 
 #[derive(Default)]
 pub struct PhysicsManager {
@@ -14,12 +18,32 @@ pub struct PhysicsManager {
     collider_set: ColliderSet,
     impulse_joint_set: ImpulseJointSet,
     multibody_joint_set: MultibodyJointSet,
+
+    // Bodies released by the removal hook of rigid bodies
+    removed: Rc<RefCell<Vec<RigidBodyHandle>>>,
 }
 
 impl PhysicsManager {
+
+    /// Define physics components with hooks collecting the bodies of removed components
+    pub(crate) fn define_components(&self, registry: &mut ComponentRegistry) -> Result<()> {
+        let removed = self.removed.clone();
+        registry.define_static::<RigidBody>(RigidBody::NAME, ComponentHooks {
+            on_add: None,
+            on_remove: Some(Rc::new(move |_, rigid_body: &RigidBody| {
+                if let Some(handle) = rigid_body.rigid_body_handle { removed.borrow_mut().push(handle); }
+            })),
+        })?;
+        Ok(())
+    }
     
     pub(crate) fn update(&mut self) -> Result<()> {
 
+        // Remove bodies of removed components
+        for handle in self.removed.take() {
+            self.rigid_body_set.remove(handle, &mut self.island_manager, &mut self.collider_set, &mut self.impulse_joint_set, &mut self.multibody_joint_set, true);
+        }
+
         Ok(())
     }
 }
\ No newline at end of file
@@ -1,13 +1,56 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, any::TypeId};
 
-use anyhow::{anyhow, Result};
-use serde::{Serialize, Deserialize, Serializer};
+use anyhow::{anyhow, Context, Result};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
 
-use crate::{uid::UID, feature::asset::runtime_component::FieldType, ecs::{container::{AnyComponentContainer, ComponentContainer, DynamicComponent1, DynamicComponent2, DynamicComponent3, DynamicComponent4, DynamicComponent5}, component::Component, singleton::{AnySingleton, Singleton}}};
+use crate::{uid::UID, feature::asset::runtime_component::{FieldType, FieldValue}, ecs::{container::{AnyComponentContainer, ComponentContainer, DynamicComponent}, component::{Component, ComponentRef, ComponentHooks}, singleton::{AnySingleton, Singleton}, entity::Entity}};
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct DynamicComponentField {
+    pub name: String,
+    pub ty: FieldType,
+}
+
+/// Ordered list of fields, the order is used for storage and serialization.
+/// States saved before the fields were ordered (a map of fields, stored sorted
+/// by name) are not compatible and can't be loaded.
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DynamicComponentDefinition {
-    pub fields: HashMap<String, FieldType>,
+    pub fields: Vec<DynamicComponentField>,
+}
+
+impl DynamicComponentDefinition {
+
+    pub fn add_field(&mut self, name: &str, ty: FieldType) -> Result<()> {
+        if self.fields.iter().any(|field| field.name == name) {
+            return Err(anyhow!("Field '{}' already defined", name));
+        }
+        self.fields.push(DynamicComponentField { name: name.to_string(), ty });
+        Ok(())
+    }
+
+    pub(crate) fn field(&self, name: &str) -> Result<(usize, FieldType)> {
+        self.fields.iter().enumerate()
+            .find(|(_, field)| field.name == name)
+            .map(|(index, field)| (index, field.ty))
+            .with_context(|| format!("Field '{}' not found", name))
+    }
+
+    pub(crate) fn default_values(&self) -> Vec<FieldValue> {
+        self.fields.iter().map(|field| field.ty.default_value()).collect()
+    }
+
+    fn validate(&self, fields: &[FieldValue]) -> Result<()> {
+        if fields.len() != self.fields.len() {
+            return Err(anyhow!("Expect {} fields but got {}", self.fields.len(), fields.len()));
+        }
+        for (field, value) in self.fields.iter().zip(fields) {
+            if value.field_type() != field.ty {
+                return Err(anyhow!("Field '{}' expects {:?} but got {:?}", field.name, field.ty, value.field_type()));
+            }
+        }
+        Ok(())
+    }
 }
 
 pub(crate) enum ComponentKind {
@@ -23,16 +66,29 @@ pub(crate) trait AnyComponentDefinitionReflection {
     fn deserialize_container(&self, deserializer: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn AnyComponentContainer>>;
     fn serialize_singleton<'a>(&'a self, singleton: &'a dyn AnySingleton) -> Box<dyn erased_serde::Serialize + 'a>;
     fn deserialize_singleton(&self, deserializer: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn AnySingleton>>;
+    fn serialize_component<'a>(&'a self, container: &'a dyn AnyComponentContainer, entity: Entity) -> Result<Option<Box<dyn erased_serde::Serialize + 'a>>>;
+    fn deserialize_component(&self, container: &mut dyn AnyComponentContainer, entity: Entity, deserializer: &mut dyn erased_serde::Deserializer) -> Result<()>;
+    fn add_from_json(&self, container: &mut dyn AnyComponentContainer, entity: Entity, value: serde_json::Value) -> Result<()>;
+    fn read_fields(&self, container: &dyn AnyComponentContainer, entity: Entity) -> Result<Option<Vec<FieldValue>>>;
+    fn write_fields(&self, container: &mut dyn AnyComponentContainer, entity: Entity, fields: Vec<FieldValue>) -> Result<()>;
+}
+
+fn write_component<C: Component>(container: &mut dyn AnyComponentContainer, entity: Entity, component: C) -> Result<()> {
+    let container = container.as_any_mut().downcast_mut::<ComponentContainer<C>>().with_context(|| "Component type mismatch")?;
+    if container.contains(entity) {
+        return container.replace(entity, component);
+    }
+    container.add(entity, component)
 }
 
 pub(crate) struct ComponentDefinitionReflection<C: Component> {
-    _phantom: std::marker::PhantomData<C>,
+    hooks: ComponentHooks<C>,
 }
 
 impl<C: Component> AnyComponentDefinitionReflection for ComponentDefinitionReflection<C> {
     
     fn create_container(&self) -> Box<dyn AnyComponentContainer> {
-        Box::new(ComponentContainer::<C>::new())
+        Box::new(ComponentContainer::<C>::new(self.hooks.clone()))
     }
 
     fn serialize_container<'a>(&'a self, container: &'a dyn AnyComponentContainer) -> Box<dyn erased_serde::Serialize + 'a> {
@@ -48,7 +104,9 @@ impl<C: Component> AnyComponentDefinitionReflection for ComponentDefinitionRefle
     }
 
     fn deserialize_container(&self, deserializer: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn AnyComponentContainer>> {
-        Ok(Box::new(ComponentContainer::<C>::deserialize(deserializer)?))
+        let mut container = ComponentContainer::<C>::deserialize(deserializer)?;
+        container.hooks = self.hooks.clone();
+        Ok(Box::new(container))
     }
 
     fn serialize_singleton<'a>(&'a self, singleton: &'a dyn AnySingleton) -> Box<dyn erased_serde::Serialize + 'a> {
@@ -66,6 +124,97 @@ impl<C: Component> AnyComponentDefinitionReflection for ComponentDefinitionRefle
     fn deserialize_singleton(&self, deserializer: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn AnySingleton>> {
         Ok(Box::new(Singleton::<C>::new(C::deserialize(deserializer)?)))
     }
+
+    fn serialize_component<'a>(&'a self, container: &'a dyn AnyComponentContainer, entity: Entity) -> Result<Option<Box<dyn erased_serde::Serialize + 'a>>> {
+        struct SerializeContext<'a, C: Component> {
+            component: ComponentRef<'a, C>,
+        }
+        impl<'a, C: Component> Serialize for SerializeContext<'a, C> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.component.serialize(serializer)
+            }
+        }
+        let container = container.as_any().downcast_ref::<ComponentContainer<C>>().expect("Invalid container type");
+        Ok(container.get(entity)?.map(|component| Box::new(SerializeContext { component }) as Box<dyn erased_serde::Serialize>))
+    }
+
+    fn deserialize_component(&self, container: &mut dyn AnyComponentContainer, entity: Entity, deserializer: &mut dyn erased_serde::Deserializer) -> Result<()> {
+        write_component(container, entity, C::deserialize(deserializer)?)
+    }
+
+    fn add_from_json(&self, container: &mut dyn AnyComponentContainer, entity: Entity, value: serde_json::Value) -> Result<()> {
+        if container.contains(entity) {
+            return Err(anyhow!("Component already added"));
+        }
+        write_component(container, entity, C::deserialize(value)?)
+    }
+
+    fn read_fields(&self, _container: &dyn AnyComponentContainer, _entity: Entity) -> Result<Option<Vec<FieldValue>>> {
+        Err(anyhow!("Component is not dynamic"))
+    }
+
+    fn write_fields(&self, _container: &mut dyn AnyComponentContainer, _entity: Entity, _fields: Vec<FieldValue>) -> Result<()> {
+        Err(anyhow!("Component is not dynamic"))
+    }
+}
+
+pub(crate) struct DynamicComponentDefinitionReflection {
+    inner: ComponentDefinitionReflection<DynamicComponent>,
+    definition: DynamicComponentDefinition,
+}
+
+impl AnyComponentDefinitionReflection for DynamicComponentDefinitionReflection {
+
+    fn create_container(&self) -> Box<dyn AnyComponentContainer> {
+        self.inner.create_container()
+    }
+
+    fn serialize_container<'a>(&'a self, container: &'a dyn AnyComponentContainer) -> Box<dyn erased_serde::Serialize + 'a> {
+        self.inner.serialize_container(container)
+    }
+
+    fn deserialize_container(&self, deserializer: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn AnyComponentContainer>> {
+        let container = ComponentContainer::<DynamicComponent>::deserialize(deserializer)?;
+        for component in container.components.borrow().iter() {
+            self.definition.validate(&component.0).context("Incompatible dynamic component, the state may have been saved with an older format")?;
+        }
+        Ok(Box::new(container))
+    }
+
+    fn serialize_singleton<'a>(&'a self, singleton: &'a dyn AnySingleton) -> Box<dyn erased_serde::Serialize + 'a> {
+        self.inner.serialize_singleton(singleton)
+    }
+
+    fn deserialize_singleton(&self, deserializer: &mut dyn erased_serde::Deserializer) -> Result<Box<dyn AnySingleton>> {
+        self.inner.deserialize_singleton(deserializer)
+    }
+
+    fn serialize_component<'a>(&'a self, container: &'a dyn AnyComponentContainer, entity: Entity) -> Result<Option<Box<dyn erased_serde::Serialize + 'a>>> {
+        self.inner.serialize_component(container, entity)
+    }
+
+    fn deserialize_component(&self, container: &mut dyn AnyComponentContainer, entity: Entity, deserializer: &mut dyn erased_serde::Deserializer) -> Result<()> {
+        let fields = Vec::<FieldValue>::deserialize(deserializer)?;
+        self.write_fields(container, entity, fields)
+    }
+
+    fn add_from_json(&self, container: &mut dyn AnyComponentContainer, entity: Entity, value: serde_json::Value) -> Result<()> {
+        if container.contains(entity) {
+            return Err(anyhow!("Component already added"));
+        }
+        let fields = Vec::<FieldValue>::deserialize(value)?;
+        self.write_fields(container, entity, fields)
+    }
+
+    fn read_fields(&self, container: &dyn AnyComponentContainer, entity: Entity) -> Result<Option<Vec<FieldValue>>> {
+        let container = container.as_any().downcast_ref::<ComponentContainer<DynamicComponent>>().with_context(|| "Component type mismatch")?;
+        Ok(container.get(entity)?.map(|component| component.0.clone()))
+    }
+
+    fn write_fields(&self, container: &mut dyn AnyComponentContainer, entity: Entity, fields: Vec<FieldValue>) -> Result<()> {
+        self.definition.validate(&fields)?;
+        write_component(container, entity, DynamicComponent(fields))
+    }
 }
 
 pub(crate) struct ComponentDefinition {
@@ -77,6 +226,7 @@ pub(crate) struct ComponentDefinition {
 #[derive(Default)]
 pub(crate) struct ComponentRegistry {
     components: HashMap<UID, ComponentDefinition>,
+    types: HashMap<TypeId, UID>,
 }
 
 impl ComponentRegistry {
@@ -90,26 +240,61 @@ impl ComponentRegistry {
         Ok(uid)
     }
 
-    pub(crate) fn define_static<C: Component>(&mut self, name: &str) -> Result<UID> {
-        let reflection = ComponentDefinitionReflection::<C> { _phantom: std::marker::PhantomData };
+    pub(crate) fn define_static<C: Component>(&mut self, name: &str, hooks: ComponentHooks<C>) -> Result<UID> {
+        if let Some(uid) = self.types.get(&TypeId::of::<C>()) {
+            return Err(anyhow!("Component type already defined with name '{}'", self.components[uid].name));
+        }
+        let reflection = ComponentDefinitionReflection::<C> { hooks };
         let uid = self.define(name, ComponentKind::Static, Box::new(reflection))?;
+        self.types.insert(TypeId::of::<C>(), uid);
         Ok(uid)
     }
 
     pub(crate) fn define_dynamic(&mut self, name: &str, definition: DynamicComponentDefinition) -> Result<UID> {
-        let reflection: Box<dyn AnyComponentDefinitionReflection> = match definition.fields.len() {
-            1 => Box::new(ComponentDefinitionReflection::<DynamicComponent1> { _phantom: std::marker::PhantomData }),
-            2 => Box::new(ComponentDefinitionReflection::<DynamicComponent2> { _phantom: std::marker::PhantomData }),
-            3 => Box::new(ComponentDefinitionReflection::<DynamicComponent3> { _phantom: std::marker::PhantomData }),
-            4 => Box::new(ComponentDefinitionReflection::<DynamicComponent4> { _phantom: std::marker::PhantomData }),
-            5 => Box::new(ComponentDefinitionReflection::<DynamicComponent5> { _phantom: std::marker::PhantomData }),
-            _ => return Err(anyhow!("Runtime component with 0 or more than 5 fields not supported")),
-        };
+        for (index, field) in definition.fields.iter().enumerate() {
+            if definition.fields[..index].iter().any(|other| other.name == field.name) {
+                return Err(anyhow!("Field '{}' defined twice", field.name));
+            }
+        }
+        let reflection = Box::new(DynamicComponentDefinitionReflection {
+            inner: ComponentDefinitionReflection { hooks: ComponentHooks::default() },
+            definition: definition.clone(),
+        });
         let uid = self.define(name, ComponentKind::Dynamic(definition), reflection)?;
         Ok(uid)
     }
 
+    /// Dynamic definitions are saved with the engine state because worlds cannot be loaded without them
+    pub(crate) fn save_state<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut definitions = self.components.values()
+            .filter_map(|component| match &component.kind {
+                ComponentKind::Dynamic(definition) => Some((component.name.as_str(), definition)),
+                ComponentKind::Static => None,
+            })
+            .collect::<Vec<_>>();
+        definitions.sort_by(|a, b| a.0.cmp(b.0));
+        definitions.serialize(serializer)
+    }
+
+    pub(crate) fn load_state<'de, D: Deserializer<'de>>(&mut self, deserializer: D) -> Result<(), D::Error> {
+        use serde::de::Error;
+        let definitions = Vec::<(String, DynamicComponentDefinition)>::deserialize(deserializer)?;
+        for (name, definition) in definitions {
+            match self.components.get(&UID::new(&name)).map(|component| &component.kind) {
+                Some(ComponentKind::Dynamic(current)) if *current == definition => {},
+                Some(_) => return Err(Error::custom(format!("Component '{}' already defined with a different definition", name))),
+                None => { self.define_dynamic(&name, definition).map_err(Error::custom)?; },
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn get(&self, uid: UID) -> Option<&ComponentDefinition> {
         self.components.get(&uid)
     }
+
+    /// Find the UID of a statically defined component from its type
+    pub(crate) fn find<C: Component>(&self) -> Option<UID> {
+        self.types.get(&TypeId::of::<C>()).copied()
+    }
 }
\ No newline at end of file
@@ -2,11 +2,12 @@ use std::collections::HashMap;
 
 use anyhow::{Result, anyhow};
 
-use crate::{uid::UID, ecs::system::SystemCallback};
+use crate::{uid::UID, ecs::{system::{SystemCallback, ParallelSystemCallback, SystemAccess}, condition::RunConditionCallback}};
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub(crate) enum SystemCode {
     Static(SystemCallback),
+    Parallel(ParallelSystemCallback, SystemAccess),
     Rhai(UID),
     Lua(UID),
 }
@@ -16,9 +17,15 @@ pub(crate) struct SystemDefinition {
     pub(crate) code: SystemCode,
 }
 
+pub(crate) struct RunConditionDefinition {
+    pub(crate) name: String,
+    pub(crate) callback: RunConditionCallback,
+}
+
 #[derive(Default)]
 pub(crate) struct SystemRegistry {
     systems: HashMap<UID, SystemDefinition>,
+    conditions: HashMap<UID, RunConditionDefinition>,
 }
 
 impl SystemRegistry {
@@ -39,6 +46,13 @@ impl SystemRegistry {
         })
     }
 
+    pub(crate) fn define_parallel(&mut self, name: &str, system: ParallelSystemCallback, access: SystemAccess) -> Result<()> {
+        self.define(SystemDefinition {
+            name: name.to_string(),
+            code: SystemCode::Parallel(system, access),
+        })
+    }
+
     pub(crate) fn define_rhai(&mut self, name: &str, script: UID) -> Result<()> {
         self.define(SystemDefinition {
             name: name.to_string(),
@@ -46,7 +60,27 @@ impl SystemRegistry {
         })
     }
 
+    pub(crate) fn define_lua(&mut self, name: &str, script: UID) -> Result<()> {
+        self.define(SystemDefinition {
+            name: name.to_string(),
+            code: SystemCode::Lua(script),
+        })
+    }
+
     pub(crate) fn get(&self, uid: &UID) -> Option<&SystemDefinition> {
         self.systems.get(uid)
     }
+
+    pub(crate) fn define_condition(&mut self, name: &str, callback: RunConditionCallback) -> Result<()> {
+        let uid: UID = name.into();
+        if self.conditions.contains_key(&uid) {
+            return Err(anyhow!("Run condition already defined"));
+        }
+        self.conditions.insert(uid, RunConditionDefinition { name: name.to_string(), callback });
+        Ok(())
+    }
+
+    pub(crate) fn get_condition(&self, uid: &UID) -> Option<&RunConditionDefinition> {
+        self.conditions.get(uid)
+    }
 }
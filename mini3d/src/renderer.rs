@@ -1,10 +1,10 @@
-use std::collections::{HashMap, HashSet, hash_map};
+use std::{collections::{HashMap, HashSet, hash_map}, cell::RefCell, rc::Rc};
 
 use anyhow::{Result, Context};
 use glam::{UVec2, uvec2};
 use serde::{Serialize, Deserialize, Serializer, ser::SerializeTuple, Deserializer, de::Visitor};
 
-use crate::{math::rect::IRect, asset::AssetManager, uid::UID, feature::{component::{local_to_world::LocalToWorld, camera::Camera, static_mesh::StaticMesh, viewport::Viewport, canvas::Canvas}, asset::{material::Material, mesh::Mesh, texture::Texture, font::{Font, FontAtlas}, model::Model}}, ecs::{ECSManager, entity::Entity, view::ComponentView}};
+use crate::{math::rect::IRect, asset::AssetManager, uid::UID, feature::{component::{local_to_world::LocalToWorld, camera::Camera, static_mesh::StaticMesh, viewport::Viewport, canvas::Canvas}, asset::{material::Material, mesh::Mesh, texture::Texture, font::{Font, FontAtlas}, model::Model}}, ecs::{ECSManager, entity::Entity, query::{Changed, Or}, component::ComponentHooks}, registry::component::ComponentRegistry};
 
 use self::{backend::{RendererBackend, BackendMaterialDescriptor, TextureHandle, MeshHandle, MaterialHandle, SceneCameraHandle, SceneModelHandle, SceneCanvasHandle, ViewportHandle, SceneHandle}, graphics::Graphics, color::Color};
 
@@ -13,6 +13,9 @@ pub mod color;
 pub mod rasterizer;
 pub mod graphics;
 
+/// Name under which the scene synchronization tracks component changes
+const SYNC_NAME: &str = "_renderer_sync";
+
 // 3:2 aspect ratio
 // pub const SCREEN_WIDTH: u32 = 480;
 // pub const SCREEN_HEIGHT: u32 = 320;
@@ -159,6 +162,15 @@ impl RendererResourceManager {
     }
 }
 
+/// Handles released by the removal hooks of renderer components
+#[derive(Default)]
+struct RemovedHandles {
+    scene_cameras: HashSet<SceneCameraHandle>,
+    scene_models: HashSet<SceneModelHandle>,
+    scene_canvases: HashSet<SceneCanvasHandle>,
+    viewports: HashSet<ViewportHandle>,
+}
+
 #[derive(Default)]
 pub struct RendererManager {
 
@@ -166,10 +178,7 @@ pub struct RendererManager {
     resources: RendererResourceManager,
 
     // Destroyed handles
-    pub(crate) scene_cameras_removed: HashSet<SceneCameraHandle>,
-    pub(crate) scene_models_removed: HashSet<SceneModelHandle>,
-    pub(crate) scene_canvases_removed: HashSet<SceneCanvasHandle>,
-    pub(crate) viewports_removed: HashSet<ViewportHandle>,
+    removed: Rc<RefCell<RemovedHandles>>,
 
     // Cached resources
     scenes: HashMap<UID, SceneHandle>,
@@ -184,6 +193,39 @@ pub struct RendererManager {
 
 impl RendererManager {
 
+    /// Define renderer components with hooks collecting the handles of removed components
+    pub(crate) fn define_components(&self, registry: &mut ComponentRegistry) -> Result<()> {
+        let removed = self.removed.clone();
+        registry.define_static::<Camera>(Camera::NAME, ComponentHooks {
+            on_add: None,
+            on_remove: Some(Rc::new(move |_, camera: &Camera| {
+                if let Some(handle) = camera.handle { removed.borrow_mut().scene_cameras.insert(handle); }
+            })),
+        })?;
+        let removed = self.removed.clone();
+        registry.define_static::<StaticMesh>(StaticMesh::NAME, ComponentHooks {
+            on_add: None,
+            on_remove: Some(Rc::new(move |_, static_mesh: &StaticMesh| {
+                if let Some(handle) = static_mesh.handle { removed.borrow_mut().scene_models.insert(handle); }
+            })),
+        })?;
+        let removed = self.removed.clone();
+        registry.define_static::<Canvas>(Canvas::NAME, ComponentHooks {
+            on_add: None,
+            on_remove: Some(Rc::new(move |_, canvas: &Canvas| {
+                if let Some(handle) = canvas.handle { removed.borrow_mut().scene_canvases.insert(handle); }
+            })),
+        })?;
+        let removed = self.removed.clone();
+        registry.define_static::<Viewport>(Viewport::NAME, ComponentHooks {
+            on_add: None,
+            on_remove: Some(Rc::new(move |_, viewport: &Viewport| {
+                if let Some(handle) = viewport.handle { removed.borrow_mut().viewports.insert(handle); }
+            })),
+        })?;
+        Ok(())
+    }
+
     pub(crate) fn reset(
         &mut self, 
         ecs: &mut ECSManager,
@@ -191,9 +233,7 @@ impl RendererManager {
 
         self.resources.reset();
 
-        self.scene_cameras_removed.clear();
-        self.scene_models_removed.clear();
-        self.scene_canvases_removed.clear();
+        *self.removed.borrow_mut() = RemovedHandles::default();
 
         for world in ecs.worlds.get_mut().values_mut() {
             for camera in world.get_mut().view_mut::<Camera>(Camera::UID)?.iter() {
@@ -217,23 +257,24 @@ impl RendererManager {
         &mut self, 
         backend: &mut impl RendererBackend,
         asset: &AssetManager,
+        registry: &ComponentRegistry,
         ecs: &mut ECSManager,
     ) -> Result<()> {
-        
+
         // Remove entities
-        for handle in self.scene_cameras_removed.drain() {
+        let removed = std::mem::take(&mut *self.removed.borrow_mut());
+        for handle in removed.scene_cameras {
+            self.cameras.retain(|_, camera| *camera != handle);
             backend.scene_camera_remove(handle)?;
         }
-        for handle in self.scene_models_removed.drain() {
+        for handle in removed.scene_models {
             backend.scene_model_remove(handle)?;
         }
-        for handle in self.scene_canvases_removed.drain() {
-            backend.scene_canvas_remove(handle)?;
-        }
-        for handle in self.scene_canvases_removed.drain() {
+        for handle in removed.scene_canvases {
             backend.scene_canvas_remove(handle)?;
         }
-        for handle in self.viewports_removed.drain() {
+        for handle in removed.viewports {
+            self.viewports.retain(|_, viewport| *viewport != handle);
             backend.viewport_remove(handle)?;
         }
 
@@ -246,34 +287,67 @@ impl RendererManager {
         // Update scene components
         {
             let world = ecs.worlds.get_mut().get_mut(&ecs.active_world).unwrap().get_mut();
+            let last_run = world.last_run(SYNC_NAME.into());
             
-            // Prepare views
-            let local_to_world = world.view_mut::<LocalToWorld>(LocalToWorld::UID)?;
-            let mut cameras = world.view_mut::<Camera>(Camera::UID)?;
-            let mut viewports = world.view_mut::<Viewport>(Viewport::UID)?;
-            let mut static_meshes = world.view_mut::<StaticMesh>(StaticMesh::UID)?;
-            let mut canvases = world.view_mut::<Canvas>(Canvas::UID)?;
-            
-            // Update cameras
-            for e in &world.query(&[Camera::UID, LocalToWorld::UID]) {
-                let c = cameras.get_mut(e).unwrap();
-                let t = local_to_world.get(e).unwrap();
-                if c.handle.is_none() {
-                    let handle = backend.scene_camera_add()?;
-                    self.cameras.insert(e, handle);
-                    c.handle = Some(handle);
+            // Create missing handles
+            {
+                let mut cameras = world.view_mut::<Camera>(Camera::UID)?;
+                let mut viewports = world.view_mut::<Viewport>(Viewport::UID)?;
+                let mut static_meshes = world.view_mut::<StaticMesh>(StaticMesh::UID)?;
+                let mut canvases = world.view_mut::<Canvas>(Canvas::UID)?;
+
+                for e in &world.query(&[Camera::UID, LocalToWorld::UID]) {
+                    if cameras[e].handle.is_none() {
+                        let handle = backend.scene_camera_add()?;
+                        self.cameras.insert(e, handle);
+                        cameras[e].handle = Some(handle);
+                    }
+                }
+
+                for e in &world.query(&[Viewport::UID]) {
+                    if viewports[e].handle.is_none() {
+                        let v = viewports.get_mut(e).unwrap();
+                        v.handle = Some(backend.viewport_add(v.resolution)?);
+                        v.out_of_date = true;
+                        self.viewports.insert(e, v.handle.unwrap());
+                    }
+                }
+
+                for e in &world.query(&[StaticMesh::UID, LocalToWorld::UID]) {
+                    // Release static meshes whose model changed
+                    if static_meshes[e].handle.is_some() && static_meshes[e].handle_model != static_meshes[e].model {
+                        let handle = static_meshes.get_mut(e).unwrap().handle.take().unwrap();
+                        backend.scene_model_remove(handle)?;
+                    }
+                    if static_meshes[e].handle.is_none() {
+                        let s = static_meshes.get_mut(e).unwrap();
+                        let model: &Model = asset.get(Model::UID, s.model)?.with_context(|| "Model not found")?;
+                        let mesh_handle = self.resources.request_mesh(&model.mesh, backend, asset)?.handle;
+                        let handle = backend.scene_model_add(mesh_handle)?;
+                        for (index, material) in model.materials.iter().enumerate() {
+                            let material_handle = self.resources.request_material(material, backend, asset)?.handle;
+                            backend.scene_model_set_material(handle, index, material_handle)?;
+                        }
+                        s.handle = Some(handle);
+                        s.handle_model = s.model;
+                    }
+                }
+
+                for e in &world.query(&[Canvas::UID, LocalToWorld::UID]) {
+                    if canvases[e].handle.is_none() {
+                        let c = canvases.get_mut(e).unwrap();
+                        c.handle = Some(backend.scene_canvas_add(c.resolution)?);
+                    }
                 }
+            }
+
+            // Update cameras
+            for (c, t) in &mut world.query_mut::<(&Camera, &LocalToWorld), Or<(Changed<Camera>, Changed<LocalToWorld>)>>(registry, last_run)? {
                 backend.scene_camera_update(c.handle.unwrap(), t.translation(), t.forward(), t.up(), c.fov)?;
             }
             
             // Update viewports
-            for e in &world.query(&[Viewport::UID]) {
-                let v = viewports.get_mut(e).unwrap();
-                if v.handle.is_none() {
-                    v.handle = Some(backend.viewport_add(v.resolution)?);
-                    v.out_of_date = true;
-                    self.viewports.insert(e, v.handle.unwrap());
-                }
+            for v in &mut world.query_mut::<&mut Viewport, Changed<Viewport>>(registry, last_run)? {
                 if v.out_of_date {
                     let camera = v.camera.map(|entity| *self.cameras.get(&entity).unwrap());
                     backend.viewport_set_camera(v.handle.unwrap(), camera)?;
@@ -283,31 +357,16 @@ impl RendererManager {
             }
 
             // Update static meshes
-            for e in &world.query(&[StaticMesh::UID, LocalToWorld::UID]) {
-                let s = static_meshes.get_mut(e).unwrap();
-                let t = local_to_world.get(e).unwrap();
-                if s.handle.is_none() {
-                    let model: &Model = asset.get(Model::UID, s.model)?.with_context(|| "Model not found")?;
-                    let mesh_handle = self.resources.request_mesh(&model.mesh, backend, asset)?.handle;
-                    let handle = backend.scene_model_add(mesh_handle)?;
-                    for (index, material) in model.materials.iter().enumerate() {
-                        let material_handle = self.resources.request_material(material, backend, asset)?.handle;
-                        backend.scene_model_set_material(handle, index, material_handle)?;
-                    }
-                    s.handle = Some(handle);
-                }
+            for (s, t) in &mut world.query_mut::<(&StaticMesh, &LocalToWorld), Or<(Changed<StaticMesh>, Changed<LocalToWorld>)>>(registry, last_run)? {
                 backend.scene_model_transfer_matrix(s.handle.unwrap(), t.matrix)?;
             }
 
             // Update Scene Canvas
-            for e in &world.query(&[Canvas::UID, LocalToWorld::UID]) {
-                let c = canvases.get_mut(e).unwrap();
-                let t = local_to_world.get(e).unwrap();
-                if c.handle.is_none() {
-                    c.handle = Some(backend.scene_canvas_add(c.resolution)?);
-                }
+            for (c, t) in &mut world.query_mut::<(&Canvas, &LocalToWorld), Or<(Changed<Canvas>, Changed<LocalToWorld>)>>(registry, last_run)? {
                 backend.scene_canvas_transfer_matrix(c.handle.unwrap(), t.matrix)?;        
             }
+
+            world.end_system(SYNC_NAME.into());
         }
 
         // Render main screen
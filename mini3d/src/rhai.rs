@@ -3,17 +3,55 @@ use std::collections::{HashMap, hash_map};
 use rhai::exported_module;
 use anyhow::{Result, anyhow, Context};
 
-use crate::{asset::AssetManager, uid::UID, feature::asset::rhai_script::RhaiScript};
+use glam::{Vec2, Vec3, Quat, Mat4};
 
-use self::{script_storage::rhai_script_storage_api, input::rhai_input_api};
+use crate::{renderer::color::Color, math::rect::IRect, asset::AssetManager, uid::UID, feature::{asset::rhai_script::RhaiScript, component::transform::Transform}, context::{SystemContext, time::TimeContext}, ecs::entity::Entity};
 
+use self::{binding::BindingScope, script_storage::rhai_script_storage_api, input::{rhai_input_api, InputManagerHandle}, world::{rhai_world_api, WorldHandle}, math::{rhai_vec2_api, rhai_vec3_api, rhai_quat_api, rhai_mat4_api}, transform::rhai_transform_api, time::rhai_time_api, graphics::{rhai_color_api, rhai_rect_api, rhai_graphics_api, GraphicsHandle}, registry::{rhai_registry_api, RegistryHandle}};
+
+pub(crate) mod binding;
+pub mod graphics;
 pub mod input;
+pub mod math;
+pub mod registry;
 pub mod script_storage;
+pub mod time;
+pub mod transform;
 pub mod world;
 
+const SYSTEM_ENTRY_POINT: &str = "run";
+
+struct CompiledRhaiScript {
+    name: String,
+    ast: rhai::AST,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RhaiScriptLimits {
+    /// Maximum number of operations of a single script call (0 for unlimited)
+    pub max_operations: u64,
+    /// Maximum depth of nested function calls
+    pub max_call_levels: usize,
+    /// Maximum length of strings in bytes (0 for unlimited)
+    pub max_string_size: usize,
+}
+
+impl Default for RhaiScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 1_000_000,
+            max_call_levels: 64,
+            max_string_size: 65536,
+        }
+    }
+}
+
 pub struct RhaiScriptCache {
     pub engine: rhai::Engine,
-    scripts: HashMap<UID, rhai::AST>,
+    scripts: HashMap<UID, CompiledRhaiScript>,
+    faults: HashMap<UID, String>,
+    revisions: HashMap<UID, u32>,
+    limits: RhaiScriptLimits,
 }
 
 impl Default for RhaiScriptCache {
@@ -21,28 +59,130 @@ impl Default for RhaiScriptCache {
         let mut cache = Self {
             engine: rhai::Engine::new(),
             scripts: Default::default(),
+            faults: Default::default(),
+            revisions: Default::default(),
+            limits: Default::default(),
         };
+        cache.set_limits(RhaiScriptLimits::default());
         cache.engine.register_global_module(exported_module!(rhai_script_storage_api).into());
         cache.engine.register_global_module(exported_module!(rhai_input_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_world_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_vec2_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_vec3_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_quat_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_mat4_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_transform_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_time_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_color_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_rect_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_graphics_api).into());
+        cache.engine.register_global_module(exported_module!(rhai_registry_api).into());
+        cache.engine.register_type_with_name::<Entity>("Entity");
+        cache.engine.register_type_with_name::<Vec2>("Vec2");
+        cache.engine.register_type_with_name::<Vec3>("Vec3");
+        cache.engine.register_type_with_name::<Quat>("Quat");
+        cache.engine.register_type_with_name::<Mat4>("Mat4");
+        cache.engine.register_type_with_name::<Transform>("Transform");
+        cache.engine.register_type_with_name::<TimeContext>("Time");
+        cache.engine.register_type_with_name::<Color>("Color");
+        cache.engine.register_type_with_name::<IRect>("Rect");
+        cache.engine.register_type_with_name::<GraphicsHandle>("Graphics");
         cache
     }
 }
 
 impl RhaiScriptCache {
-    pub fn call(&mut self, uid: UID, asset: &AssetManager, scope: &mut rhai::Scope, function: &str) -> Result<()> {
+
+    pub fn limits(&self) -> RhaiScriptLimits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: RhaiScriptLimits) {
+        self.engine.set_max_operations(limits.max_operations);
+        self.engine.set_max_call_levels(limits.max_call_levels);
+        self.engine.set_max_string_size(limits.max_string_size);
+        self.limits = limits;
+    }
+
+    pub fn fault(&self, uid: UID) -> Option<&str> {
+        self.faults.get(&uid).map(String::as_str)
+    }
+
+    pub fn iter_faults(&self) -> impl Iterator<Item = (UID, &str)> {
+        self.faults.iter().map(|(uid, fault)| (*uid, fault.as_str()))
+    }
+
+    /// Allow a faulted script to run again
+    pub fn reset_fault(&mut self, uid: UID) {
+        self.faults.remove(&uid);
+    }
+
+    /// Number of times the script has been reloaded
+    pub fn revision(&self, uid: UID) -> u32 {
+        self.revisions.get(&uid).copied().unwrap_or_default()
+    }
+
+    /// Drop the compiled script and its fault. The script is recompiled
+    /// from the current asset on the next call.
+    pub(crate) fn invalidate(&mut self, uid: UID) {
+        let compiled = self.scripts.remove(&uid).is_some();
+        let faulted = self.faults.remove(&uid).is_some();
+        if compiled || faulted {
+            *self.revisions.entry(uid).or_default() += 1;
+        }
+    }
+
+    /// Call a script function. A script that fails is marked as faulted and
+    /// skipped by later calls until its fault is reset.
+    pub fn call(&mut self, uid: UID, asset: &AssetManager, scope: &mut rhai::Scope, function: &str) {
+        self.call_with(uid, asset, scope, function, false);
+    }
+
+    /// Same as [`RhaiScriptCache::call`] but does nothing if the script does not define the function
+    pub fn call_optional(&mut self, uid: UID, asset: &AssetManager, scope: &mut rhai::Scope, function: &str) {
+        self.call_with(uid, asset, scope, function, true);
+    }
+
+    fn call_with(&mut self, uid: UID, asset: &AssetManager, scope: &mut rhai::Scope, function: &str, optional: bool) {
+        if self.faults.contains_key(&uid) {
+            return;
+        }
+        if let Err(err) = self.try_call(uid, asset, scope, function, optional) {
+            self.faults.insert(uid, err.to_string());
+        }
+    }
+
+    fn try_call(&mut self, uid: UID, asset: &AssetManager, scope: &mut rhai::Scope, function: &str, optional: bool) -> Result<()> {
         // Lazy script compilation
         if let hash_map::Entry::Vacant(e) = self.scripts.entry(uid) {
-            let script: &RhaiScript = asset.get(RhaiScript::UID, uid)?
+            let entry = asset.entry::<RhaiScript>(RhaiScript::UID, uid)?
                 .with_context(|| "Rhai script not found")?;
-            let ast = self.engine.compile(script.source.clone())?;
-            e.insert(ast);
+            let ast = self.engine.compile(entry.asset.source.clone()).map_err(|err| {
+                anyhow!("Failed to compile rhai script '{}': {}", entry.name, err)
+            })?;
+            e.insert(CompiledRhaiScript { name: entry.name.clone(), ast });
         }
         // Call script
-        if let Some(ast) = self.scripts.get(&uid) {
-            self.engine.call_fn::<()>(scope, ast, function, ()).map_err(|err| {
-                anyhow!(err.to_string())
+        if let Some(script) = self.scripts.get(&uid) {
+            if optional && !script.ast.iter_functions().any(|metadata| metadata.name == function) {
+                return Ok(());
+            }
+            self.engine.call_fn::<()>(scope, &script.ast, function, ()).map_err(|err| {
+                anyhow!("Rhai script '{}' failed in '{}': {}", script.name, function, err)
             })?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    pub(crate) fn run_system(&mut self, uid: UID, ctx: &mut SystemContext) {
+        let mut scope = rhai::Scope::new();
+        let mut bindings = BindingScope::new();
+        scope.push_constant("INPUT", InputManagerHandle::bind(&mut bindings, &mut *ctx.input.manager));
+        let world = WorldHandle::bind(&mut bindings, &mut ctx.world);
+        scope.push_constant("GFX", GraphicsHandle::bind(&mut bindings, ctx.renderer.graphics(), Some(&world)));
+        scope.push_constant("WORLD", world);
+        scope.push_constant("REGISTRY", RegistryHandle::bind(&mut bindings, &mut ctx.registry));
+        scope.push_constant("TIME", ctx.time.clone());
+        self.call(uid, ctx.asset.manager, &mut scope, SYSTEM_ENTRY_POINT)
+    }
+}
@@ -0,0 +1,48 @@
+use std::{cell::Cell, marker::PhantomData, ptr::NonNull, rc::Rc};
+
+use rhai::EvalAltResult;
+
+type BindingSlot = Rc<Cell<Option<NonNull<()>>>>;
+
+/// Reference to host data shared with scripts. The binding is only valid
+/// while the [`BindingScope`] that created it is alive.
+#[derive(Clone)]
+pub(crate) struct Binding(BindingSlot);
+
+impl Binding {
+
+    /// # Safety
+    /// `T` must be the type of the data bound by the scope.
+    pub(crate) unsafe fn get_mut<T>(&mut self) -> Result<&mut T, Box<EvalAltResult>> {
+        let pointer = self.0.get().ok_or("Script handle used outside of its script call")?;
+        Ok(pointer.cast::<T>().as_mut())
+    }
+}
+
+/// Host data exclusively borrowed by scripts for the duration of a call.
+/// Every binding is invalidated when the scope is dropped.
+pub(crate) struct BindingScope<'a> {
+    slots: Vec<BindingSlot>,
+    _marker: PhantomData<&'a mut ()>,
+}
+
+impl<'a> BindingScope<'a> {
+
+    pub(crate) fn new() -> Self {
+        Self { slots: Vec::new(), _marker: PhantomData }
+    }
+
+    pub(crate) fn bind<T>(&mut self, data: &'a mut T) -> Binding {
+        let slot = Rc::new(Cell::new(Some(NonNull::from(data).cast())));
+        self.slots.push(slot.clone());
+        Binding(slot)
+    }
+}
+
+impl<'a> Drop for BindingScope<'a> {
+    fn drop(&mut self) {
+        for slot in &self.slots {
+            slot.set(None);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use glam::IVec2;
+use rhai::plugin::*;
+
+use crate::{renderer::{graphics::Graphics, color::Color}, math::rect::IRect, context::world::WorldContext, feature::component::canvas::Canvas, ecs::entity::Entity};
+
+use super::{binding::{Binding, BindingScope}, world::WorldHandle};
+
+const DEFAULT_FONT: &str = "default";
+
+/// Records graphics commands into the screen or into a canvas component
+#[derive(Clone)]
+pub(crate) struct GraphicsHandle {
+    screen: Binding,
+    world: Option<Binding>,
+    canvas: Option<Entity>,
+}
+
+impl GraphicsHandle {
+
+    /// Canvas targets are only available when the world is bound
+    pub(crate) fn bind<'a>(scope: &mut BindingScope<'a>, screen: &'a mut Graphics, world: Option<&WorldHandle>) -> Self {
+        Self { screen: scope.bind(screen), world: world.map(WorldHandle::binding), canvas: None }
+    }
+
+    fn record(&mut self, command: impl FnOnce(&mut Graphics)) -> Result<(), Box<EvalAltResult>> {
+        if let Some(entity) = self.canvas {
+            let world = self.world.as_mut().ok_or("Canvas target is not available")?;
+            let world = unsafe { world.get_mut::<WorldContext>()? }.active();
+            let mut canvas = world.get_mut::<Canvas>(entity, Canvas::UID).map_err(|err| err.to_string())?
+                .ok_or_else(|| format!("Canvas not found for {:?}", entity))?;
+            command(&mut canvas.graphics);
+        } else {
+            command(unsafe { self.screen.get_mut::<Graphics>()? });
+        }
+        Ok(())
+    }
+}
+
+fn channel(value: rhai::INT) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+fn size(value: rhai::INT) -> u32 {
+    value.max(0) as u32
+}
+
+#[export_module]
+pub mod rhai_color_api {
+
+    pub(crate) fn color(r: rhai::INT, g: rhai::INT, b: rhai::INT, a: rhai::INT) -> Color {
+        Color::rgba(channel(r), channel(g), channel(b), channel(a))
+    }
+
+    pub(crate) fn color_rgb(r: rhai::INT, g: rhai::INT, b: rhai::INT) -> Color {
+        Color::rgba(channel(r), channel(g), channel(b), 255)
+    }
+
+    #[rhai_fn(get = "r", pure)]
+    pub(crate) fn get_r(color: &mut Color) -> rhai::INT { color.r() as rhai::INT }
+    #[rhai_fn(get = "g", pure)]
+    pub(crate) fn get_g(color: &mut Color) -> rhai::INT { color.g() as rhai::INT }
+    #[rhai_fn(get = "b", pure)]
+    pub(crate) fn get_b(color: &mut Color) -> rhai::INT { color.b() as rhai::INT }
+    #[rhai_fn(get = "a", pure)]
+    pub(crate) fn get_a(color: &mut Color) -> rhai::INT { color.a() as rhai::INT }
+
+    #[rhai_fn(name = "==")]
+    pub(crate) fn eq(a: Color, b: Color) -> bool { a == b }
+    #[rhai_fn(name = "!=")]
+    pub(crate) fn neq(a: Color, b: Color) -> bool { a != b }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn to_string(color: &mut Color) -> String { format!("{:?}", color) }
+    #[rhai_fn(name = "to_debug", pure)]
+    pub(crate) fn to_debug(color: &mut Color) -> String { format!("{:?}", color) }
+}
+
+#[export_module]
+pub mod rhai_rect_api {
+
+    pub(crate) fn rect(x: rhai::INT, y: rhai::INT, width: rhai::INT, height: rhai::INT) -> IRect {
+        IRect::new(x, y, size(width), size(height))
+    }
+
+    #[rhai_fn(get = "x", pure)]
+    pub(crate) fn get_x(rect: &mut IRect) -> rhai::INT { rect.left() }
+    #[rhai_fn(get = "y", pure)]
+    pub(crate) fn get_y(rect: &mut IRect) -> rhai::INT { rect.top() }
+    #[rhai_fn(get = "width", pure)]
+    pub(crate) fn get_width(rect: &mut IRect) -> rhai::INT { rect.width() as rhai::INT }
+    #[rhai_fn(get = "height", pure)]
+    pub(crate) fn get_height(rect: &mut IRect) -> rhai::INT { rect.height() as rhai::INT }
+    #[rhai_fn(get = "left", pure)]
+    pub(crate) fn get_left(rect: &mut IRect) -> rhai::INT { rect.left() }
+    #[rhai_fn(get = "top", pure)]
+    pub(crate) fn get_top(rect: &mut IRect) -> rhai::INT { rect.top() }
+    #[rhai_fn(get = "right", pure)]
+    pub(crate) fn get_right(rect: &mut IRect) -> rhai::INT { rect.right() }
+    #[rhai_fn(get = "bottom", pure)]
+    pub(crate) fn get_bottom(rect: &mut IRect) -> rhai::INT { rect.bottom() }
+
+    #[rhai_fn(pure)]
+    pub(crate) fn contains(rect: &mut IRect, x: rhai::INT, y: rhai::INT) -> bool {
+        rect.contains(IVec2::new(x, y))
+    }
+
+    pub(crate) fn translate(rect: &mut IRect, x: rhai::INT, y: rhai::INT) {
+        rect.translate(IVec2::new(x, y));
+    }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn to_string(rect: &mut IRect) -> String { format!("{:?}", rect) }
+    #[rhai_fn(name = "to_debug", pure)]
+    pub(crate) fn to_debug(rect: &mut IRect) -> String { format!("{:?}", rect) }
+}
+
+#[export_module]
+pub mod rhai_graphics_api {
+
+    /// Graphics recording into the canvas component of the entity
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn canvas(gfx: &mut GraphicsHandle, entity: Entity) -> Result<GraphicsHandle, Box<EvalAltResult>> {
+        if gfx.world.is_none() {
+            return Err("Canvas target is not available".into());
+        }
+        Ok(GraphicsHandle { canvas: Some(entity), ..gfx.clone() })
+    }
+
+    /// Graphics recording into the screen
+    #[rhai_fn(pure)]
+    pub(crate) fn screen(gfx: &mut GraphicsHandle) -> GraphicsHandle {
+        GraphicsHandle { canvas: None, ..gfx.clone() }
+    }
+
+    /// Graphics::print (`print` is reserved by rhai)
+    #[rhai_fn(name = "print_text", pure, return_raw)]
+    pub(crate) fn print_text_default(gfx: &mut GraphicsHandle, x: rhai::INT, y: rhai::INT, text: &str) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.print(IVec2::new(x, y), text, DEFAULT_FONT.into()))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn print_text(gfx: &mut GraphicsHandle, x: rhai::INT, y: rhai::INT, text: &str, font: &str) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.print(IVec2::new(x, y), text, font.into()))
+    }
+
+    #[rhai_fn(name = "blit_texture", pure, return_raw)]
+    pub(crate) fn blit_texture_default(gfx: &mut GraphicsHandle, texture: &str, extent: IRect, x: rhai::INT, y: rhai::INT) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.blit_texture(texture.into(), extent, IVec2::new(x, y), Color::WHITE, 0))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn blit_texture(gfx: &mut GraphicsHandle, texture: &str, extent: IRect, x: rhai::INT, y: rhai::INT, filtering: Color, alpha_threshold: rhai::INT) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.blit_texture(texture.into(), extent, IVec2::new(x, y), filtering, channel(alpha_threshold)))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn fill_rect(gfx: &mut GraphicsHandle, extent: IRect, color: Color) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.fill_rect(extent, color))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn draw_rect(gfx: &mut GraphicsHandle, extent: IRect, color: Color) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.draw_rect(extent, color))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn draw_line(gfx: &mut GraphicsHandle, x0: rhai::INT, y0: rhai::INT, x1: rhai::INT, y1: rhai::INT, color: Color) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.draw_line(IVec2::new(x0, y0), IVec2::new(x1, y1), color))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn draw_vline(gfx: &mut GraphicsHandle, x: rhai::INT, y0: rhai::INT, y1: rhai::INT, color: Color) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.draw_vline(x, y0, y1, color))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn draw_hline(gfx: &mut GraphicsHandle, y: rhai::INT, x0: rhai::INT, x1: rhai::INT, color: Color) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.draw_hline(y, x0, x1, color))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn scissor(gfx: &mut GraphicsHandle, extent: IRect) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.scissor(Some(extent)))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn clear_scissor(gfx: &mut GraphicsHandle) -> Result<(), Box<EvalAltResult>> {
+        gfx.record(|graphics| graphics.scissor(None))
+    }
+}
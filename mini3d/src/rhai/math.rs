@@ -0,0 +1,225 @@
+use glam::{Vec2, Vec3, Quat, Mat4, EulerRot};
+use rhai::plugin::*;
+
+#[export_module]
+pub mod rhai_vec2_api {
+
+    pub(crate) fn vec2(x: f32, y: f32) -> Vec2 {
+        Vec2::new(x, y)
+    }
+
+    #[rhai_fn(get = "x", pure)]
+    pub(crate) fn get_x(v: &mut Vec2) -> f32 { v.x }
+    #[rhai_fn(set = "x")]
+    pub(crate) fn set_x(v: &mut Vec2, x: f32) { v.x = x; }
+    #[rhai_fn(get = "y", pure)]
+    pub(crate) fn get_y(v: &mut Vec2) -> f32 { v.y }
+    #[rhai_fn(set = "y")]
+    pub(crate) fn set_y(v: &mut Vec2, y: f32) { v.y = y; }
+
+    #[rhai_fn(name = "+")]
+    pub(crate) fn add(a: Vec2, b: Vec2) -> Vec2 { a + b }
+    #[rhai_fn(name = "-")]
+    pub(crate) fn sub(a: Vec2, b: Vec2) -> Vec2 { a - b }
+    #[rhai_fn(name = "-")]
+    pub(crate) fn neg(a: Vec2) -> Vec2 { -a }
+    #[rhai_fn(name = "*")]
+    pub(crate) fn mul(a: Vec2, b: Vec2) -> Vec2 { a * b }
+    #[rhai_fn(name = "*")]
+    pub(crate) fn mul_scalar(a: Vec2, b: f32) -> Vec2 { a * b }
+    #[rhai_fn(name = "*")]
+    pub(crate) fn scalar_mul(a: f32, b: Vec2) -> Vec2 { a * b }
+    #[rhai_fn(name = "/")]
+    pub(crate) fn div(a: Vec2, b: Vec2) -> Vec2 { a / b }
+    #[rhai_fn(name = "/")]
+    pub(crate) fn div_scalar(a: Vec2, b: f32) -> Vec2 { a / b }
+    #[rhai_fn(name = "==")]
+    pub(crate) fn eq(a: Vec2, b: Vec2) -> bool { a == b }
+    #[rhai_fn(name = "!=")]
+    pub(crate) fn neq(a: Vec2, b: Vec2) -> bool { a != b }
+
+    #[rhai_fn(pure)]
+    pub(crate) fn length(v: &mut Vec2) -> f32 { v.length() }
+    #[rhai_fn(pure)]
+    pub(crate) fn length_squared(v: &mut Vec2) -> f32 { v.length_squared() }
+    #[rhai_fn(pure)]
+    pub(crate) fn normalize(v: &mut Vec2) -> Vec2 { v.normalize_or_zero() }
+    #[rhai_fn(pure)]
+    pub(crate) fn dot(a: &mut Vec2, b: Vec2) -> f32 { a.dot(b) }
+    #[rhai_fn(pure)]
+    pub(crate) fn distance(a: &mut Vec2, b: Vec2) -> f32 { a.distance(b) }
+    #[rhai_fn(pure)]
+    pub(crate) fn lerp(a: &mut Vec2, b: Vec2, s: f32) -> Vec2 { a.lerp(b, s) }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn to_string(v: &mut Vec2) -> String { format!("{:?}", v) }
+    #[rhai_fn(name = "to_debug", pure)]
+    pub(crate) fn to_debug(v: &mut Vec2) -> String { format!("{:?}", v) }
+}
+
+#[export_module]
+pub mod rhai_vec3_api {
+
+    pub(crate) fn vec3(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3::new(x, y, z)
+    }
+
+    #[rhai_fn(get = "x", pure)]
+    pub(crate) fn get_x(v: &mut Vec3) -> f32 { v.x }
+    #[rhai_fn(set = "x")]
+    pub(crate) fn set_x(v: &mut Vec3, x: f32) { v.x = x; }
+    #[rhai_fn(get = "y", pure)]
+    pub(crate) fn get_y(v: &mut Vec3) -> f32 { v.y }
+    #[rhai_fn(set = "y")]
+    pub(crate) fn set_y(v: &mut Vec3, y: f32) { v.y = y; }
+    #[rhai_fn(get = "z", pure)]
+    pub(crate) fn get_z(v: &mut Vec3) -> f32 { v.z }
+    #[rhai_fn(set = "z")]
+    pub(crate) fn set_z(v: &mut Vec3, z: f32) { v.z = z; }
+
+    #[rhai_fn(name = "+")]
+    pub(crate) fn add(a: Vec3, b: Vec3) -> Vec3 { a + b }
+    #[rhai_fn(name = "-")]
+    pub(crate) fn sub(a: Vec3, b: Vec3) -> Vec3 { a - b }
+    #[rhai_fn(name = "-")]
+    pub(crate) fn neg(a: Vec3) -> Vec3 { -a }
+    #[rhai_fn(name = "*")]
+    pub(crate) fn mul(a: Vec3, b: Vec3) -> Vec3 { a * b }
+    #[rhai_fn(name = "*")]
+    pub(crate) fn mul_scalar(a: Vec3, b: f32) -> Vec3 { a * b }
+    #[rhai_fn(name = "*")]
+    pub(crate) fn scalar_mul(a: f32, b: Vec3) -> Vec3 { a * b }
+    #[rhai_fn(name = "/")]
+    pub(crate) fn div(a: Vec3, b: Vec3) -> Vec3 { a / b }
+    #[rhai_fn(name = "/")]
+    pub(crate) fn div_scalar(a: Vec3, b: f32) -> Vec3 { a / b }
+    #[rhai_fn(name = "==")]
+    pub(crate) fn eq(a: Vec3, b: Vec3) -> bool { a == b }
+    #[rhai_fn(name = "!=")]
+    pub(crate) fn neq(a: Vec3, b: Vec3) -> bool { a != b }
+
+    #[rhai_fn(pure)]
+    pub(crate) fn length(v: &mut Vec3) -> f32 { v.length() }
+    #[rhai_fn(pure)]
+    pub(crate) fn length_squared(v: &mut Vec3) -> f32 { v.length_squared() }
+    #[rhai_fn(pure)]
+    pub(crate) fn normalize(v: &mut Vec3) -> Vec3 { v.normalize_or_zero() }
+    #[rhai_fn(pure)]
+    pub(crate) fn dot(a: &mut Vec3, b: Vec3) -> f32 { a.dot(b) }
+    #[rhai_fn(pure)]
+    pub(crate) fn cross(a: &mut Vec3, b: Vec3) -> Vec3 { a.cross(b) }
+    #[rhai_fn(pure)]
+    pub(crate) fn distance(a: &mut Vec3, b: Vec3) -> f32 { a.distance(b) }
+    #[rhai_fn(pure)]
+    pub(crate) fn lerp(a: &mut Vec3, b: Vec3, s: f32) -> Vec3 { a.lerp(b, s) }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn to_string(v: &mut Vec3) -> String { format!("{:?}", v) }
+    #[rhai_fn(name = "to_debug", pure)]
+    pub(crate) fn to_debug(v: &mut Vec3) -> String { format!("{:?}", v) }
+}
+
+#[export_module]
+pub mod rhai_quat_api {
+
+    pub(crate) fn quat_identity() -> Quat {
+        Quat::IDENTITY
+    }
+
+    pub(crate) fn quat_from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+        Quat::from_axis_angle(axis.normalize_or_zero(), angle)
+    }
+
+    pub(crate) fn quat_from_euler(x: f32, y: f32, z: f32) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, y, x, z)
+    }
+
+    pub(crate) fn quat_from_rotation_x(angle: f32) -> Quat { Quat::from_rotation_x(angle) }
+    pub(crate) fn quat_from_rotation_y(angle: f32) -> Quat { Quat::from_rotation_y(angle) }
+    pub(crate) fn quat_from_rotation_z(angle: f32) -> Quat { Quat::from_rotation_z(angle) }
+
+    #[rhai_fn(get = "x", pure)]
+    pub(crate) fn get_x(q: &mut Quat) -> f32 { q.x }
+    #[rhai_fn(get = "y", pure)]
+    pub(crate) fn get_y(q: &mut Quat) -> f32 { q.y }
+    #[rhai_fn(get = "z", pure)]
+    pub(crate) fn get_z(q: &mut Quat) -> f32 { q.z }
+    #[rhai_fn(get = "w", pure)]
+    pub(crate) fn get_w(q: &mut Quat) -> f32 { q.w }
+
+    #[rhai_fn(name = "*")]
+    pub(crate) fn mul(a: Quat, b: Quat) -> Quat { a * b }
+    #[rhai_fn(name = "*")]
+    pub(crate) fn mul_vec3(a: Quat, b: Vec3) -> Vec3 { a * b }
+    #[rhai_fn(name = "==")]
+    pub(crate) fn eq(a: Quat, b: Quat) -> bool { a == b }
+    #[rhai_fn(name = "!=")]
+    pub(crate) fn neq(a: Quat, b: Quat) -> bool { a != b }
+
+    #[rhai_fn(pure)]
+    pub(crate) fn inverse(q: &mut Quat) -> Quat { q.inverse() }
+    #[rhai_fn(pure)]
+    pub(crate) fn normalize(q: &mut Quat) -> Quat { q.normalize() }
+    #[rhai_fn(pure)]
+    pub(crate) fn slerp(a: &mut Quat, b: Quat, s: f32) -> Quat { a.slerp(b, s) }
+    /// Euler angles (x, y, z) matching quat_from_euler
+    #[rhai_fn(pure)]
+    pub(crate) fn to_euler(q: &mut Quat) -> Vec3 {
+        let (y, x, z) = q.to_euler(EulerRot::YXZ);
+        Vec3::new(x, y, z)
+    }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn to_string(q: &mut Quat) -> String { format!("{:?}", q) }
+    #[rhai_fn(name = "to_debug", pure)]
+    pub(crate) fn to_debug(q: &mut Quat) -> String { format!("{:?}", q) }
+}
+
+#[export_module]
+pub mod rhai_mat4_api {
+
+    pub(crate) fn mat4_identity() -> Mat4 {
+        Mat4::IDENTITY
+    }
+
+    pub(crate) fn mat4_from_translation(translation: Vec3) -> Mat4 {
+        Mat4::from_translation(translation)
+    }
+
+    pub(crate) fn mat4_from_quat(rotation: Quat) -> Mat4 {
+        Mat4::from_quat(rotation)
+    }
+
+    pub(crate) fn mat4_from_scale(scale: Vec3) -> Mat4 {
+        Mat4::from_scale(scale)
+    }
+
+    pub(crate) fn mat4_from_scale_rotation_translation(scale: Vec3, rotation: Quat, translation: Vec3) -> Mat4 {
+        Mat4::from_scale_rotation_translation(scale, rotation, translation)
+    }
+
+    pub(crate) fn mat4_look_at(eye: Vec3, center: Vec3, up: Vec3) -> Mat4 {
+        Mat4::look_at_rh(eye, center, up)
+    }
+
+    #[rhai_fn(name = "*")]
+    pub(crate) fn mul(a: Mat4, b: Mat4) -> Mat4 { a * b }
+    #[rhai_fn(name = "==")]
+    pub(crate) fn eq(a: Mat4, b: Mat4) -> bool { a == b }
+    #[rhai_fn(name = "!=")]
+    pub(crate) fn neq(a: Mat4, b: Mat4) -> bool { a != b }
+
+    #[rhai_fn(pure)]
+    pub(crate) fn transform_point(m: &mut Mat4, point: Vec3) -> Vec3 { m.transform_point3(point) }
+    #[rhai_fn(pure)]
+    pub(crate) fn transform_vector(m: &mut Mat4, vector: Vec3) -> Vec3 { m.transform_vector3(vector) }
+    #[rhai_fn(pure)]
+    pub(crate) fn inverse(m: &mut Mat4) -> Mat4 { m.inverse() }
+    #[rhai_fn(pure)]
+    pub(crate) fn transpose(m: &mut Mat4) -> Mat4 { m.transpose() }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn to_string(m: &mut Mat4) -> String { format!("{:?}", m) }
+    #[rhai_fn(name = "to_debug", pure)]
+    pub(crate) fn to_debug(m: &mut Mat4) -> String { format!("{:?}", m) }
+}
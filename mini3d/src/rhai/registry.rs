@@ -0,0 +1,49 @@
+use rhai::plugin::*;
+
+use crate::{context::registry::RegistryContext, registry::component::DynamicComponentDefinition, feature::asset::runtime_component::FieldType};
+
+use super::binding::{Binding, BindingScope};
+
+#[derive(Clone)]
+pub(crate) struct RegistryHandle(Binding);
+
+impl RegistryHandle {
+
+    pub(crate) fn bind<'a, 'b>(scope: &mut BindingScope<'a>, registry: &'a mut RegistryContext<'b>) -> Self {
+        Self(scope.bind(registry))
+    }
+
+    fn registry(&mut self) -> Result<&mut RegistryContext<'_>, Box<EvalAltResult>> {
+        unsafe { self.0.get_mut::<RegistryContext>() }
+    }
+}
+
+fn parse_field(field: Dynamic) -> Result<(String, FieldType), Box<EvalAltResult>> {
+    let pair = field.into_array().map_err(|_| "Field must be declared as [name, type]")?;
+    if pair.len() != 2 {
+        return Err("Field must be declared as [name, type]".into());
+    }
+    let mut pair = pair.into_iter();
+    let name = pair.next().unwrap().into_string()?;
+    let ty = pair.next().unwrap().into_string()?;
+    let ty = serde_json::from_value::<FieldType>(serde_json::Value::String(ty.clone()))
+        .map_err(|_| format!("Unknown field type '{}'", ty))?;
+    Ok((name, ty))
+}
+
+#[export_module]
+pub mod rhai_registry_api {
+
+    /// Define a dynamic component from a list of [name, type] fields
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn define_component(registry: &mut RegistryHandle, name: &str, fields: rhai::Array) -> Result<(), Box<EvalAltResult>> {
+        let mut definition = DynamicComponentDefinition::default();
+        for field in fields {
+            let (field, ty) = parse_field(field)?;
+            definition.add_field(&field, ty).map_err(|err| err.to_string())?;
+        }
+        registry.registry()?.define_dynamic_component(name, definition)
+            .map_err(|err| format!("Failed to define component '{}': {}", name, err))?;
+        Ok(())
+    }
+}
@@ -2,84 +2,76 @@ use rhai::plugin::*;
 
 use crate::feature::component::script_storage::ScriptStorage;
 
-#[derive(Debug, Eq, PartialEq, Clone, Hash)]
-pub(crate) struct ScriptStorageHandle(usize);
+use super::binding::{Binding, BindingScope};
 
-impl From<&mut ScriptStorage> for ScriptStorageHandle {
-    fn from(storage: &mut ScriptStorage) -> Self {
-        Self::new(storage)
+#[derive(Clone)]
+pub(crate) struct ScriptStorageHandle(Binding);
+
+impl ScriptStorageHandle {
+
+    pub(crate) fn bind<'a>(scope: &mut BindingScope<'a>, storage: &'a mut ScriptStorage) -> Self {
+        Self(scope.bind(storage))
     }
-}
 
-impl AsMut<ScriptStorage> for ScriptStorageHandle {
-    fn as_mut(&mut self) -> &mut ScriptStorage {
-        unsafe { std::mem::transmute(self.0) }
+    fn storage(&mut self) -> Result<&mut ScriptStorage, Box<EvalAltResult>> {
+        unsafe { self.0.get_mut::<ScriptStorage>() }
     }
 }
 
-impl ScriptStorageHandle {
-
-    fn new(storage: &mut ScriptStorage) -> Self {
-        let handle = unsafe { std::mem::transmute(storage) };
-        Self(handle)
-    }
+fn key_not_found(key: &str) -> Box<EvalAltResult> {
+    format!("Storage key '{}' not found", key).into()
 }
 
 #[export_module]
 pub mod rhai_script_storage_api {
 
-    #[rhai_fn(pure)]
-    pub(crate) fn get_bool(storage: &mut ScriptStorageHandle, key: &str) -> bool {
-        let storage: &mut ScriptStorage = storage.as_mut(); 
-        storage.get_bool(key).unwrap()
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn get_bool(storage: &mut ScriptStorageHandle, key: &str) -> Result<bool, Box<EvalAltResult>> {
+        storage.storage()?.get_bool(key).ok_or_else(|| key_not_found(key))
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn set_bool(storage: &mut ScriptStorageHandle, key: &str, value: bool) {
-        let storage: &mut ScriptStorage = storage.as_mut();
-        storage.set_bool(key, value);
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_bool(storage: &mut ScriptStorageHandle, key: &str, value: bool) -> Result<(), Box<EvalAltResult>> {
+        storage.storage()?.set_bool(key, value);
+        Ok(())
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn get_int(storage: &mut ScriptStorageHandle, key: &str) -> i32 {
-        let storage: &mut ScriptStorage = storage.as_mut(); 
-        storage.get_int(key).unwrap()
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn get_int(storage: &mut ScriptStorageHandle, key: &str) -> Result<i32, Box<EvalAltResult>> {
+        storage.storage()?.get_int(key).ok_or_else(|| key_not_found(key))
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn set_int(storage: &mut ScriptStorageHandle, key: &str, value: i32) {
-        let storage: &mut ScriptStorage = storage.as_mut();
-        storage.set_int(key, value);
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_int(storage: &mut ScriptStorageHandle, key: &str, value: i32) -> Result<(), Box<EvalAltResult>> {
+        storage.storage()?.set_int(key, value);
+        Ok(())
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn get_float(storage: &mut ScriptStorageHandle, key: &str) -> f32 {
-        let storage: &mut ScriptStorage = storage.as_mut(); 
-        storage.get_float(key).unwrap()
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn get_float(storage: &mut ScriptStorageHandle, key: &str) -> Result<f32, Box<EvalAltResult>> {
+        storage.storage()?.get_float(key).ok_or_else(|| key_not_found(key))
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn set_float(storage: &mut ScriptStorageHandle, key: &str, value: f32) {
-        let storage: &mut ScriptStorage = storage.as_mut();
-        storage.set_float(key, value);
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_float(storage: &mut ScriptStorageHandle, key: &str, value: f32) -> Result<(), Box<EvalAltResult>> {
+        storage.storage()?.set_float(key, value);
+        Ok(())
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn get_string(storage: &mut ScriptStorageHandle, key: &str) -> String {
-        let storage: &mut ScriptStorage = storage.as_mut(); 
-        storage.get_string(key).unwrap()
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn get_string(storage: &mut ScriptStorageHandle, key: &str) -> Result<String, Box<EvalAltResult>> {
+        storage.storage()?.get_string(key).ok_or_else(|| key_not_found(key))
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn set_string(storage: &mut ScriptStorageHandle, key: &str, value: String) {
-        let storage: &mut ScriptStorage = storage.as_mut();
-        storage.set_string(key, value);
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_string(storage: &mut ScriptStorageHandle, key: &str, value: String) -> Result<(), Box<EvalAltResult>> {
+        storage.storage()?.set_string(key, value);
+        Ok(())
     }
 
-    #[rhai_fn(pure)]
-    pub(crate) fn list_keys(storage: &mut ScriptStorageHandle, key: &str) -> rhai::Dynamic {
-        let storage: &mut ScriptStorage = storage.as_mut();
-        storage.list_keys(key).unwrap().cloned().collect::<Vec<String>>().into()
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn list_keys(storage: &mut ScriptStorageHandle, key: &str) -> Result<rhai::Dynamic, Box<EvalAltResult>> {
+        let keys = storage.storage()?.list_keys(key).ok_or_else(|| key_not_found(key))?;
+        Ok(keys.cloned().collect::<Vec<String>>().into())
     }
 }
-
@@ -0,0 +1,17 @@
+use rhai::plugin::*;
+
+use crate::context::time::TimeContext;
+
+#[export_module]
+pub mod rhai_time_api {
+
+    #[rhai_fn(get = "delta", pure)]
+    pub(crate) fn delta(time: &mut TimeContext) -> f32 {
+        time.delta() as f32
+    }
+
+    #[rhai_fn(get = "global", pure)]
+    pub(crate) fn global(time: &mut TimeContext) -> f32 {
+        time.global() as f32
+    }
+}
@@ -0,0 +1,74 @@
+use glam::{Vec3, Quat, Mat4};
+use rhai::plugin::*;
+
+use crate::feature::component::transform::Transform;
+
+#[export_module]
+pub mod rhai_transform_api {
+
+    pub(crate) fn transform(translation: Vec3, rotation: Quat, scale: Vec3) -> Transform {
+        Transform { translation, rotation, scale }
+    }
+
+    pub(crate) fn transform_from_translation(translation: Vec3) -> Transform {
+        Transform::from_translation(translation)
+    }
+
+    #[rhai_fn(get = "translation", pure)]
+    pub(crate) fn get_translation(t: &mut Transform) -> Vec3 { t.translation }
+    #[rhai_fn(set = "translation")]
+    pub(crate) fn set_translation(t: &mut Transform, translation: Vec3) { t.translation = translation; }
+    #[rhai_fn(get = "rotation", pure)]
+    pub(crate) fn get_rotation(t: &mut Transform) -> Quat { t.rotation }
+    #[rhai_fn(set = "rotation")]
+    pub(crate) fn set_rotation(t: &mut Transform, rotation: Quat) { t.rotation = rotation; }
+    #[rhai_fn(get = "scale", pure)]
+    pub(crate) fn get_scale(t: &mut Transform) -> Vec3 { t.scale }
+    #[rhai_fn(set = "scale")]
+    pub(crate) fn set_scale(t: &mut Transform, scale: Vec3) { t.scale = scale; }
+
+    #[rhai_fn(pure)]
+    pub(crate) fn matrix(t: &mut Transform) -> Mat4 { t.matrix() }
+    #[rhai_fn(pure)]
+    pub(crate) fn forward(t: &mut Transform) -> Vec3 { t.forward() }
+    #[rhai_fn(pure)]
+    pub(crate) fn backward(t: &mut Transform) -> Vec3 { t.backward() }
+    #[rhai_fn(pure)]
+    pub(crate) fn up(t: &mut Transform) -> Vec3 { t.up() }
+    #[rhai_fn(pure)]
+    pub(crate) fn down(t: &mut Transform) -> Vec3 { t.down() }
+    #[rhai_fn(pure)]
+    pub(crate) fn left(t: &mut Transform) -> Vec3 { t.left() }
+    #[rhai_fn(pure)]
+    pub(crate) fn right(t: &mut Transform) -> Vec3 { t.right() }
+
+    pub(crate) fn translate(t: &mut Transform, translation: Vec3) {
+        t.translation += translation;
+    }
+
+    pub(crate) fn rotate(t: &mut Transform, rotation: Quat) {
+        t.rotation = (rotation * t.rotation).normalize();
+    }
+
+    pub(crate) fn rotate_x(t: &mut Transform, angle: f32) {
+        rotate(t, Quat::from_rotation_x(angle));
+    }
+
+    pub(crate) fn rotate_y(t: &mut Transform, angle: f32) {
+        rotate(t, Quat::from_rotation_y(angle));
+    }
+
+    pub(crate) fn rotate_z(t: &mut Transform, angle: f32) {
+        rotate(t, Quat::from_rotation_z(angle));
+    }
+
+    /// Rotate around the transform local axis
+    pub(crate) fn rotate_local(t: &mut Transform, rotation: Quat) {
+        t.rotation = (t.rotation * rotation).normalize();
+    }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn to_string(t: &mut Transform) -> String {
+        format!("Transform {{ translation: {:?}, rotation: {:?}, scale: {:?} }}", t.translation, t.rotation, t.scale)
+    }
+}
@@ -1,37 +1,381 @@
-// use rhai::plugin::*;
-
-// #[derive(Debug, Eq, PartialEq, Clone, Hash)]
-// pub(crate) struct WorldHandle(usize);
-
-// impl From<&mut ProcessContext<'_, '_>> for WorldHandle {
-//     fn from(input: &mut ProcessContext) -> Self {
-//         Self::new(input)
-//     }
-// }
-
-// impl<'a, 'b> AsMut<ProcessContext<'a, 'b>> for WorldHandle {
-//     fn as_mut(&mut self) -> &mut ProcessContext<'a, 'b> {
-//         unsafe { std::mem::transmute(self.0) }
-//     }
-// }
-
-// impl WorldHandle {
-
-//     fn new(input: &mut ProcessContext) -> Self {
-//         let handle = unsafe { std::mem::transmute(input) };
-//         Self(handle)
-//     }
-// }
-
-// #[export_module]
-// pub mod rhai_world_api {
-
-//     #[rhai_fn(pure)]
-//     pub(crate) fn action_pressed(_input: &mut WorldHandle) -> bool {
-//         // let context: &mut ProgramContext = input.as_mut();
-//         // let group = context.input.find_group(group).unwrap().id;
-//         // context.input.find_action(group, name).unwrap().is_pressed()
-//         false
-//     }
-// }
+use rhai::plugin::*;
+use serde_json::{Value, Number};
 
+use glam::{Vec2, Vec3, Quat, Mat4};
+
+use crate::{context::world::{WorldContext, WorldInstanceContext}, renderer::color::Color, math::rect::IRect, ecs::entity::Entity, feature::{component::transform::Transform, asset::runtime_component::{FieldType, FieldValue}}, uid::UID};
+
+use super::binding::{Binding, BindingScope};
+
+#[derive(Clone)]
+pub(crate) struct WorldHandle(Binding);
+
+impl WorldHandle {
+
+    pub(crate) fn bind<'a, 'b>(scope: &mut BindingScope<'a>, world: &'a mut WorldContext<'b>) -> Self {
+        Self(scope.bind(world))
+    }
+
+    pub(crate) fn binding(&self) -> Binding {
+        self.0.clone()
+    }
+
+    fn world(&mut self) -> Result<&mut WorldContext<'_>, Box<EvalAltResult>> {
+        unsafe { self.0.get_mut::<WorldContext>() }
+    }
+}
+
+fn value_to_dynamic(value: Value) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::UNIT,
+        Value::Bool(value) => value.into(),
+        Value::Number(number) => {
+            if let Some(value) = number.as_i64().and_then(|value| rhai::INT::try_from(value).ok()) {
+                value.into()
+            } else if let Some(value) = number.as_f64().filter(|_| number.is_f64()) {
+                (value as rhai::FLOAT).into()
+            } else {
+                // Integers out of the script range (e.g. UID) are kept as strings
+                number.to_string().into()
+            }
+        },
+        Value::String(value) => value.into(),
+        Value::Array(values) => Dynamic::from_array(values.into_iter().map(value_to_dynamic).collect()),
+        Value::Object(values) => Dynamic::from_map(values.into_iter().map(|(key, value)| (key.into(), value_to_dynamic(value))).collect()),
+    }
+}
+
+fn native_to_value(value: Dynamic) -> Result<Value, Box<EvalAltResult>> {
+    let value = if let Some(value) = value.clone().try_cast::<Vec2>() {
+        serde_json::to_value(value)
+    } else if let Some(value) = value.clone().try_cast::<Vec3>() {
+        serde_json::to_value(value)
+    } else if let Some(value) = value.clone().try_cast::<Quat>() {
+        serde_json::to_value(value)
+    } else if let Some(value) = value.clone().try_cast::<Mat4>() {
+        serde_json::to_value(value)
+    } else if let Some(value) = value.clone().try_cast::<Color>() {
+        serde_json::to_value(value)
+    } else {
+        serde_json::to_value(value.cast::<IRect>())
+    };
+    value.map_err(|err| err.to_string().into())
+}
+
+fn dynamic_to_value(value: Dynamic) -> Result<Value, Box<EvalAltResult>> {
+    if value.is_unit() {
+        Ok(Value::Null)
+    } else if let Some(value) = value.clone().try_cast::<bool>() {
+        Ok(value.into())
+    } else if let Some(value) = value.clone().try_cast::<rhai::INT>() {
+        Ok(value.into())
+    } else if let Some(value) = value.clone().try_cast::<rhai::FLOAT>() {
+        Number::from_f64(value as f64).map(Value::Number).ok_or_else(|| "Invalid float value".into())
+    } else if value.is_string() {
+        Ok(value.into_string()?.into())
+    } else if value.is::<Vec2>() || value.is::<Vec3>() || value.is::<Quat>() || value.is::<Mat4>() || value.is::<Color>() || value.is::<IRect>() {
+        native_to_value(value)
+    } else if value.is_array() {
+        Ok(Value::Array(value.into_array()?.into_iter().map(dynamic_to_value).collect::<Result<_, _>>()?))
+    } else if value.is_map() {
+        let mut values = serde_json::Map::new();
+        for (key, value) in value.cast::<rhai::Map>() {
+            values.insert(key.to_string(), dynamic_to_value(value)?);
+        }
+        Ok(Value::Object(values))
+    } else {
+        Err(format!("Unsupported component field type '{}'", value.type_name()).into())
+    }
+}
+
+fn field_to_dynamic(value: FieldValue) -> Dynamic {
+    match value {
+        FieldValue::String(value) => value.into(),
+        FieldValue::Integer(value) => value.into(),
+        FieldValue::Float(value) => value.into(),
+        FieldValue::Boolean(value) => value.into(),
+        FieldValue::Vec2(value) => Dynamic::from(value),
+        FieldValue::Vec3(value) => Dynamic::from(value),
+        FieldValue::Vec4(value) => Dynamic::from_array(value.to_array().into_iter().map(Dynamic::from).collect()),
+        FieldValue::Entity(value) => Dynamic::from(value),
+        FieldValue::Array(values) => Dynamic::from_array(values.into_iter().map(field_to_dynamic).collect()),
+    }
+}
+
+/// Array elements are not typed by the definition, the type is inferred from the value
+fn infer_field_type(value: &Dynamic) -> Result<FieldType, Box<EvalAltResult>> {
+    if value.is::<bool>() {
+        Ok(FieldType::Boolean)
+    } else if value.is::<rhai::INT>() {
+        Ok(FieldType::Integer)
+    } else if value.is::<rhai::FLOAT>() {
+        Ok(FieldType::Float)
+    } else if value.is_string() {
+        Ok(FieldType::String)
+    } else if value.is::<Vec2>() {
+        Ok(FieldType::Vec2)
+    } else if value.is::<Vec3>() {
+        Ok(FieldType::Vec3)
+    } else if value.is::<Entity>() {
+        Ok(FieldType::Entity)
+    } else if value.is_array() {
+        Ok(FieldType::Array)
+    } else {
+        Err(format!("Unsupported field value type '{}'", value.type_name()).into())
+    }
+}
+
+fn dynamic_to_field(value: Dynamic, ty: FieldType) -> Result<FieldValue, Box<EvalAltResult>> {
+    let type_name = value.type_name();
+    let field = match ty {
+        FieldType::String => value.into_string().ok().map(FieldValue::String),
+        FieldType::Integer => value.try_cast::<rhai::INT>().map(FieldValue::Integer),
+        FieldType::Float => {
+            if let Some(value) = value.clone().try_cast::<rhai::INT>() {
+                Some(FieldValue::Float(value as f32))
+            } else {
+                value.try_cast::<rhai::FLOAT>().map(FieldValue::Float)
+            }
+        },
+        FieldType::Boolean => value.try_cast::<bool>().map(FieldValue::Boolean),
+        FieldType::Vec2 => value.try_cast::<Vec2>().map(FieldValue::Vec2),
+        FieldType::Vec3 => value.try_cast::<Vec3>().map(FieldValue::Vec3),
+        FieldType::Vec4 => {
+            let values = value.into_array().ok()
+                .map(|values| values.into_iter().filter_map(|value| value.try_cast::<rhai::FLOAT>()).collect::<Vec<_>>());
+            values.filter(|values| values.len() == 4).map(|values| FieldValue::Vec4(glam::Vec4::from_slice(&values)))
+        },
+        FieldType::Entity => value.try_cast::<Entity>().map(FieldValue::Entity),
+        FieldType::Array => {
+            let values = value.into_array().map_err(|_| format!("Expected {:?} but got '{}'", ty, type_name))?;
+            let values = values.into_iter()
+                .map(|value| infer_field_type(&value).and_then(|ty| dynamic_to_field(value, ty)))
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(FieldValue::Array(values))
+        },
+    };
+    field.ok_or_else(|| format!("Expected {:?} but got '{}'", ty, type_name).into())
+}
+
+fn get_dynamic_component(world: &WorldInstanceContext, entity: Entity, component: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+    let definition = world.dynamic_definition(component.into()).unwrap();
+    let mut values = rhai::Map::new();
+    for field in &definition.fields {
+        match world.get_field(entity, component.into(), &field.name).map_err(|err| err.to_string())? {
+            Some(value) => { values.insert(field.name.as_str().into(), field_to_dynamic(value)); },
+            None => return Ok(Dynamic::UNIT),
+        }
+    }
+    Ok(values.into())
+}
+
+fn set_dynamic_component(world: &mut WorldInstanceContext, entity: Entity, component: &str, data: Dynamic) -> Result<(), Box<EvalAltResult>> {
+    let definition = world.dynamic_definition(component.into()).cloned().unwrap();
+    let data = data.try_cast::<rhai::Map>().ok_or_else(|| format!("Expected a map to write component '{}'", component))?;
+    if !world.contains(entity, component.into()).map_err(|err| err.to_string())? {
+        world.add_dynamic(entity, component.into()).map_err(|err| err.to_string())?;
+    }
+    for (field, value) in data {
+        let (_, ty) = definition.field(&field).map_err(|err| err.to_string())?;
+        let value = dynamic_to_field(value, ty).map_err(|err| format!("Invalid field '{}': {}", field, err))?;
+        world.set_field(entity, component.into(), &field, value).map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// Apply the script value on the serialized component. The current value
+/// is used as schema to restore integers given as strings.
+fn patch_value(value: &mut Value, patch: Dynamic) -> Result<(), Box<EvalAltResult>> {
+    if patch.is_map() {
+        if !value.is_object() {
+            *value = Value::Object(Default::default());
+        }
+        let values = value.as_object_mut().unwrap();
+        for (key, patch) in patch.cast::<rhai::Map>() {
+            patch_value(values.entry(key.to_string()).or_insert(Value::Null), patch)?;
+        }
+    } else if patch.is_array() {
+        if !value.is_array() {
+            *value = Value::Array(Default::default());
+        }
+        let patches = patch.into_array()?;
+        let values = value.as_array_mut().unwrap();
+        values.resize(patches.len(), Value::Null);
+        for (value, patch) in values.iter_mut().zip(patches) {
+            patch_value(value, patch)?;
+        }
+    } else if patch.is_string() && value.is_number() {
+        let patch = patch.into_string()?;
+        *value = Value::Number(patch.parse::<Number>().map_err(|err| err.to_string())?);
+    } else {
+        *value = dynamic_to_value(patch)?;
+    }
+    Ok(())
+}
+
+#[export_module]
+pub mod rhai_world_api {
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn create_entity(world: &mut WorldHandle) -> Result<Entity, Box<EvalAltResult>> {
+        Ok(world.world()?.active().create())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn destroy_entity(world: &mut WorldHandle, entity: Entity) -> Result<(), Box<EvalAltResult>> {
+        world.world()?.active().destroy(entity).map_err(|err| err.to_string().into())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn is_alive(world: &mut WorldHandle, entity: Entity) -> Result<bool, Box<EvalAltResult>> {
+        Ok(world.world()?.active().is_alive(entity))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn find_entity(world: &mut WorldHandle, name: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        Ok(world.world()?.active().find(name).map_or(Dynamic::UNIT, Dynamic::from))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn entity_name(world: &mut WorldHandle, entity: Entity) -> Result<Dynamic, Box<EvalAltResult>> {
+        Ok(world.world()?.active().name(entity).map_or(Dynamic::UNIT, |name| name.into()))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_entity_name(world: &mut WorldHandle, entity: Entity, name: &str) -> Result<(), Box<EvalAltResult>> {
+        world.world()?.active().set_name(entity, name).map_err(|err| err.to_string().into())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn remove_entity_name(world: &mut WorldHandle, entity: Entity) -> Result<(), Box<EvalAltResult>> {
+        world.world()?.active().remove_name(entity);
+        Ok(())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn query(world: &mut WorldHandle, components: rhai::Array) -> Result<rhai::Array, Box<EvalAltResult>> {
+        let components = components.into_iter()
+            .map(|component| component.into_string().map(UID::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(world.world()?.active().query(&components).iter().map(Dynamic::from).collect())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn has_component(world: &mut WorldHandle, entity: Entity, component: &str) -> Result<bool, Box<EvalAltResult>> {
+        Ok(world.world()?.active().contains(entity, component.into()).map_err(|err| err.to_string())?)
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn get_component(world: &mut WorldHandle, entity: Entity, component: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        let world = world.world()?.active();
+        if world.dynamic_definition(component.into()).is_some() {
+            return get_dynamic_component(&world, entity, component);
+        }
+        let value = match world.serialize_component(entity, component.into()).map_err(|err| err.to_string())? {
+            Some(data) => value_to_dynamic(serde_json::to_value(&*data).map_err(|err| err.to_string())?),
+            None => Dynamic::UNIT,
+        };
+        Ok(value)
+    }
+
+    /// Serializable fields are patched then the component is replaced. Its hooks run,
+    /// so runtime handles of the previous value are released and recreated on sync.
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_component(world: &mut WorldHandle, entity: Entity, component: &str, data: Dynamic) -> Result<(), Box<EvalAltResult>> {
+        let mut world = world.world()?.active();
+        if world.dynamic_definition(component.into()).is_some() {
+            return set_dynamic_component(&mut world, entity, component, data);
+        }
+        let mut value = match world.serialize_component(entity, component.into()).map_err(|err| err.to_string())? {
+            Some(current) => serde_json::to_value(&*current).map_err(|err| err.to_string())?,
+            None => Value::Null,
+        };
+        patch_value(&mut value, data)?;
+        world.deserialize_component(entity, component.into(), &mut <dyn erased_serde::Deserializer>::erase(value))
+            .map_err(|err| format!("Failed to write component '{}': {}", component, err).into())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn get_field(world: &mut WorldHandle, entity: Entity, component: &str, field: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        let world = world.world()?.active();
+        let value = world.get_field(entity, component.into(), field).map_err(|err| err.to_string())?;
+        Ok(value.map_or(Dynamic::UNIT, field_to_dynamic))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_field(world: &mut WorldHandle, entity: Entity, component: &str, field: &str, value: Dynamic) -> Result<(), Box<EvalAltResult>> {
+        let mut world = world.world()?.active();
+        let (_, ty) = world.dynamic_definition(component.into())
+            .ok_or_else(|| format!("Component '{}' is not dynamic", component))?
+            .field(field).map_err(|err| err.to_string())?;
+        let value = dynamic_to_field(value, ty)?;
+        world.set_field(entity, component.into(), field, value).map_err(|err| err.to_string().into())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn get_transform(world: &mut WorldHandle, entity: Entity) -> Result<Dynamic, Box<EvalAltResult>> {
+        let world = world.world()?.active();
+        let transform = world.get::<Transform>(entity, Transform::UID).map_err(|err| err.to_string())?;
+        Ok(transform.map_or(Dynamic::UNIT, |transform| Dynamic::from(transform.clone())))
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn set_transform(world: &mut WorldHandle, entity: Entity, transform: Transform) -> Result<(), Box<EvalAltResult>> {
+        let mut world = world.world()?.active();
+        if let Some(mut current) = world.get_mut::<Transform>(entity, Transform::UID).map_err(|err| err.to_string())? {
+            *current = transform;
+            return Ok(());
+        }
+        world.add(entity, Transform::UID, transform).map_err(|err| err.to_string().into())
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub(crate) fn remove_component(world: &mut WorldHandle, entity: Entity, component: &str) -> Result<(), Box<EvalAltResult>> {
+        world.world()?.active().remove(entity, component.into()).map_err(|err| err.to_string().into())
+    }
+
+    #[rhai_fn(name = "to_string", pure)]
+    pub(crate) fn entity_to_string(entity: &mut Entity) -> String {
+        format!("{:?}", entity)
+    }
+
+    #[rhai_fn(name = "to_debug", pure)]
+    pub(crate) fn entity_to_debug(entity: &mut Entity) -> String {
+        format!("{:?}", entity)
+    }
+
+    #[rhai_fn(name = "==", pure)]
+    pub(crate) fn entity_eq(entity: &mut Entity, other: Entity) -> bool {
+        *entity == other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::{HashMap, HashSet}, cell::RefCell};
+
+    use rhai::Dynamic;
+
+    use crate::{context::world::WorldContext, ecs::testing::{setup_with_hooks, Health, HookLog, HEALTH}, registry::RegistryManager, rhai::binding::BindingScope, uid::UID};
+
+    use super::{WorldHandle, rhai_world_api};
+
+    #[test]
+    fn set_component_runs_replace_hooks() {
+        let log = HookLog::default();
+        let (mut world, components) = setup_with_hooks(log.hooks());
+        let entity = world.create();
+        world.add(&components, entity, HEALTH, Health(1)).unwrap();
+        let registry = RefCell::new(RegistryManager { components, ..Default::default() });
+        let uid = UID::new("test");
+        let mut worlds = HashMap::from([(uid, RefCell::new(Box::new(world)))]);
+        let (mut change_world, mut removed_worlds) = (None, HashSet::new());
+        let mut context = WorldContext { registry: &registry, worlds: &mut worlds, active_world: uid, change_world: &mut change_world, removed_worlds: &mut removed_worlds, system: UID::null() };
+        let mut bindings = BindingScope::new();
+        let mut handle = WorldHandle::bind(&mut bindings, &mut context);
+        rhai_world_api::set_component(&mut handle, entity, "health", Dynamic::from_int(4)).unwrap();
+        drop(bindings);
+        assert_eq!((log.added.get(), log.removed.get()), (5, 1));
+        assert_eq!(context.active().get::<Health>(entity, HEALTH).unwrap().unwrap().0, 4);
+    }
+}
@@ -1,6 +1,38 @@
-use crate::rhai::RhaiScriptCache;
+use std::{rc::Rc, cell::RefCell};
+
+use anyhow::Result;
+
+use crate::{rhai::RhaiScriptCache, lua::LuaScriptCache, feature::{asset::{rhai_script::RhaiScript, lua_script::LuaScript}, component::rhai_scripts::{RhaiScripts, RhaiScriptInstance, RhaiScriptStatus}}, registry::component::ComponentRegistry, ecs::{entity::Entity, component::ComponentHooks}, uid::UID};
 
 #[derive(Default)]
 pub(crate) struct ScriptManager {
     pub(crate) rhai: RhaiScriptCache,
-}
\ No newline at end of file
+    pub(crate) lua: LuaScriptCache,
+    // Running instances of removed rhai scripts components, stopped by the next update
+    pub(crate) removed_rhai_scripts: Rc<RefCell<Vec<(Entity, RhaiScriptInstance)>>>,
+}
+
+impl ScriptManager {
+
+    /// Define script components with hooks collecting the instances to stop
+    pub(crate) fn define_components(&self, registry: &mut ComponentRegistry) -> Result<()> {
+        let removed = self.removed_rhai_scripts.clone();
+        registry.define_static::<RhaiScripts>(RhaiScripts::NAME, ComponentHooks {
+            on_add: Some(Rc::new(|_, scripts: &mut RhaiScripts| scripts.restart())),
+            on_remove: Some(Rc::new(move |entity, scripts: &RhaiScripts| {
+                removed.borrow_mut().extend(scripts.instances.iter()
+                    .filter(|instance| instance.status != RhaiScriptStatus::Starting)
+                    .map(|instance| (entity, instance.clone())));
+            })),
+        })?;
+        Ok(())
+    }
+
+    pub(crate) fn on_asset_modified(&mut self, asset: UID, uid: UID) {
+        if asset == RhaiScript::UID {
+            self.rhai.invalidate(uid);
+        } else if asset == LuaScript::UID {
+            self.lua.invalidate(uid);
+        }
+    }
+}
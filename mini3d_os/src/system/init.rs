@@ -1,9 +1,9 @@
-use mini3d::{context::SystemContext, anyhow::Result, feature::{asset::{font::Font, input_table::{InputTable, InputAction, InputAxis, InputAxisRange}, material::Material, model::Model, mesh::Mesh, rhai_script::RhaiScript, texture::Texture, system_group::{SystemGroup, SystemPipeline}}, component::{lifecycle::Lifecycle, transform::Transform, local_to_world::LocalToWorld, rotator::Rotator, static_mesh::StaticMesh, free_fly::FreeFly, script_storage::ScriptStorage, rhai_scripts::RhaiScripts, hierarchy::Hierarchy, camera::Camera, viewport::Viewport, ui::{UIComponent, UIRenderTarget}}}, renderer::{SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_RESOLUTION}, ecs::procedure::Procedure, glam::{Vec3, Quat, IVec2}, event::asset::ImportAssetEvent, rand, ui::{UI, checkbox::Checkbox, interaction_layout::InteractionInputs, self}, uid::UID};
+use mini3d::{context::SystemContext, anyhow::Result, feature::{asset::{font::Font, input_table::{InputTable, InputAction, InputAxis, InputAxisRange}, lua_script::LuaScript, material::Material, model::Model, mesh::Mesh, rhai_script::RhaiScript, texture::Texture, system_group::{SystemGroup, SystemPipeline}}, component::{lifecycle::Lifecycle, transform::Transform, local_to_world::LocalToWorld, rotator::Rotator, static_mesh::StaticMesh, free_fly::FreeFly, script_storage::ScriptStorage, rhai_scripts::RhaiScripts, hierarchy::Hierarchy, camera::Camera, viewport::Viewport, ui::{UIComponent, UIRenderTarget}}}, renderer::{SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_RESOLUTION}, ecs::procedure::Procedure, glam::{Vec3, Quat, IVec2}, event::asset::ImportAssetEvent, rand, ui::{UI, checkbox::Checkbox, interaction_layout::InteractionInputs, self}, uid::UID};
 
 use crate::{input::{CommonAction, CommonAxis}, asset::DefaultAsset, component::os::OS};
 
 fn define_features(ctx: &mut SystemContext) -> Result<()> {
-    ctx.registry.define_static_component::<OS>(OS::NAME)?;
+    ctx.registry.define_static_component::<OS>(OS::NAME, Default::default())?;
     ctx.registry.define_static_system("update", crate::system::update::update)?;
     Ok(())
 }
@@ -235,6 +235,9 @@ fn setup_assets(ctx: &mut SystemContext) -> Result<()> {
             ImportAssetEvent::Texture(texture) => {
                 ctx.asset.add(Texture::UID, &texture.name, default_bundle, texture.data.clone())?;
             },
+            ImportAssetEvent::LuaScript(lua_script) => {
+                ctx.asset.add(LuaScript::UID, &lua_script.name, default_bundle, lua_script.data.clone())?;
+            },
             _ => {},
         }
     }
@@ -378,7 +381,6 @@ fn setup_scheduler(ctx: &mut SystemContext) -> Result<()> {
         UID::new("transform_propagate"),
         UID::new("ui_update"),
         UID::new("ui_render"),
-        UID::new("renderer"),
         UID::new("despawn_entities"),
         UID::new("free_fly"),
         UID::new("update"),